@@ -0,0 +1,121 @@
+//! Check that `ByteLines` upholds its documented invariants for arbitrary
+//! byte streams split into arbitrary reads
+#![no_main]
+use elapsed_cmd::bytelines::ByteLines;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// Split on NUL instead of newline
+    nul_delimited: bool,
+    /// Call `take_partial()` after each record for which this is true
+    take_partials: Vec<bool>,
+    /// The sizes of the reads that the data is delivered in; any data left
+    /// over after these are used up is delivered in a single final read
+    read_sizes: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl Input {
+    fn delimiter(&self) -> u8 {
+        if self.nul_delimited { b'\0' } else { b'\n' }
+    }
+
+    /// Split the data into nonempty chunks of the sizes in `read_sizes`
+    fn chunks(&self) -> Vec<&[u8]> {
+        let mut chunks = Vec::new();
+        let mut rest = &self.data[..];
+        for &size in &self.read_sizes {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, tail) = rest.split_at(usize::from(size).clamp(1, rest.len()));
+            chunks.push(chunk);
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            chunks.push(rest);
+        }
+        chunks
+    }
+}
+
+/// Read all of the records from a reader that delivers `chunks`, calling
+/// `take_partial()` after the `i`-th record whenever `take_partials[i]` is
+/// true.  Records returned by `take_partial()` are paired with `true`.
+fn read_records(
+    chunks: &[&[u8]],
+    delimiter: u8,
+    strip_cr: bool,
+    take_partials: &[bool],
+) -> Vec<(Vec<u8>, bool)> {
+    let mut builder = tokio_test::io::Builder::new();
+    for &chunk in chunks {
+        builder.read(chunk);
+    }
+    let mut lines = ByteLines::new(builder.build());
+    lines.delimiter = delimiter;
+    lines.strip_cr = strip_cr;
+    let mut records = Vec::new();
+    tokio_test::block_on(async {
+        while let Some(ln) = lines.next_line().await.expect("mock reads should not fail") {
+            records.push((ln, false));
+            if take_partials
+                .get(records.len() - 1)
+                .copied()
+                .unwrap_or(false)
+            {
+                if let Some(partial) = lines.take_partial() {
+                    records.push((partial, true));
+                }
+            }
+        }
+    });
+    records
+}
+
+fuzz_target!(|input: Input| {
+    let delimiter = input.delimiter();
+    let chunks = input.chunks();
+    let records = read_records(&chunks, delimiter, false, &input.take_partials);
+    let n = records.len();
+    for (i, (rec, partial)) in records.iter().enumerate() {
+        let (&last, body) = rec.split_last().expect("records should be nonempty");
+        assert!(
+            !body.contains(&delimiter),
+            "delimiter in middle of record {rec:?}"
+        );
+        if *partial {
+            assert_ne!(last, delimiter, "partial record {rec:?} contains delimiter");
+        } else {
+            assert!(
+                last == delimiter || i + 1 == n,
+                "unterminated record {rec:?} before EOF"
+            );
+        }
+    }
+    let concat = records
+        .iter()
+        .flat_map(|(rec, _)| rec.iter().copied())
+        .collect::<Vec<_>>();
+    assert_eq!(concat, input.data);
+    if input.take_partials.is_empty() {
+        // Without `take_partial()`, the records don't depend on the chunking
+        // of the data, and `strip_cr` only drops CRs before record-ending LFs.
+        let whole = read_records(&[&input.data], delimiter, false, &[]);
+        assert_eq!(records, whole);
+        let stripped = read_records(&chunks, delimiter, true, &[]);
+        let expected = records
+            .into_iter()
+            .map(|(mut rec, partial)| {
+                if rec.ends_with(b"\r\n") {
+                    rec.truncate(rec.len() - 2);
+                    rec.push(b'\n');
+                }
+                (rec, partial)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(stripped, expected);
+    }
+});
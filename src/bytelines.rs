@@ -0,0 +1,386 @@
+//! Splitting of a stream of bytes into lines or other delimited records
+use std::future::Future;
+use std::io;
+use std::pin::{Pin, pin};
+use std::task::{Context, Poll, ready};
+use std::time::Instant;
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// The number of bytes to try to read from the reader at a time
+const READ_BUFFER_SIZE: usize = 2048;
+
+/// A splitter of the bytes read from a reader into records terminated by
+/// [`delimiter`](ByteLines::delimiter) (newline by default).
+///
+/// The records returned by [`next_line()`](ByteLines::next_line) and
+/// [`take_partial()`](ByteLines::take_partial) satisfy the following
+/// invariants, regardless of how the reader's data is split up into reads.
+/// These invariants are part of this type's API; they are checked by this
+/// module's tests and by the `byte_lines` fuzz target in the repository's
+/// `fuzz/` directory.
+///
+/// - No record is empty.
+///
+/// - Each record contains `delimiter` only as its last byte.  A record returned
+///   by `next_line()` lacks a final delimiter only if it consists of the last
+///   bytes before EOF, and a record returned by `take_partial()` never contains
+///   the delimiter.
+///
+/// - Concatenating all of the records in order reproduces the reader's data
+///   exactly, except that, if `strip_cr` is set, a CR immediately before the
+///   LF ending a record is removed.
+///
+/// - Aside from the effects of calling `take_partial()`, which returns
+///   whatever is buffered at the time, the records returned do not depend on
+///   how the reader's data is split up into reads.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ByteLines<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    next_index: usize,
+    eof: bool,
+    /// If true, a CR immediately before the LF ending a record is removed
+    pub strip_cr: bool,
+    /// The byte that terminates each record
+    pub delimiter: u8,
+    /// When any data was first read from `reader`
+    first_read: Option<Instant>,
+}
+
+impl<R> ByteLines<R> {
+    /// Construct a `ByteLines` that splits `reader`'s data into
+    /// newline-terminated lines, with CRs left in place
+    pub fn new(reader: R) -> Self {
+        ByteLines {
+            reader,
+            buffer: Vec::new(),
+            next_index: 0,
+            eof: false,
+            strip_cr: false,
+            delimiter: b'\n',
+            first_read: None,
+        }
+    }
+
+    /// Return a reference to the underlying reader
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Return when any data was first read from the reader, if it has been
+    /// yet
+    pub fn first_read(&self) -> Option<Instant> {
+        self.first_read
+    }
+
+    fn get_line(&mut self) -> Option<Vec<u8>> {
+        let mut r = if let Some(i) = self.buffer[self.next_index..]
+            .iter()
+            .position(|&b| b == self.delimiter)
+        {
+            let delim_pos = self.next_index + i;
+            self.next_index = 0;
+            Some(self.buffer.drain(0..=delim_pos).collect())
+        } else if self.eof {
+            let r = (!self.buffer.is_empty()).then(|| std::mem::take(&mut self.buffer));
+            self.next_index = 0;
+            r
+        } else {
+            self.next_index = self.buffer.len();
+            None
+        };
+        if self.strip_cr {
+            if let Some(buf) = &mut r {
+                let n = buf.len();
+                if n >= 2 && buf[n - 2] == b'\r' && buf[n - 1] == b'\n' {
+                    buf[n - 2] = b'\n';
+                    buf.truncate(n - 1);
+                }
+            }
+        }
+        r
+    }
+
+    /// Returns the number of bytes of output currently buffered that do not
+    /// (yet) form a complete line
+    pub fn partial_len(&self) -> usize {
+        if self.buffer.contains(&self.delimiter) {
+            0
+        } else {
+            self.buffer.len()
+        }
+    }
+
+    /// If the buffered output does not contain a complete line, remove & return
+    /// it
+    pub fn take_partial(&mut self) -> Option<Vec<u8>> {
+        (self.partial_len() > 0).then(|| {
+            self.next_index = 0;
+            std::mem::take(&mut self.buffer)
+        })
+    }
+
+    /// Read the next record from the reader, returning `None` once all of
+    /// the reader's data has been returned
+    pub fn next_line<'a>(&'a mut self) -> NextLine<'a, R> {
+        NextLine { inner: self }
+    }
+}
+
+/// A future returned by [`ByteLines::next_line()`]
+#[derive(Debug, Eq, PartialEq)]
+pub struct NextLine<'a, R> {
+    inner: &'a mut ByteLines<R>,
+}
+
+impl<R: AsyncRead + Unpin> Future for NextLine<'_, R> {
+    type Output = io::Result<Option<Vec<u8>>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            if let Some(ln) = self.inner.get_line() {
+                return Poll::Ready(Ok(Some(ln)));
+            } else if self.inner.eof {
+                return Poll::Ready(Ok(None));
+            } else {
+                let mut buf0 = vec![0u8; READ_BUFFER_SIZE];
+                let mut buf = ReadBuf::new(&mut buf0);
+                let reader = pin!(&mut self.inner.reader);
+                match ready!(reader.poll_read(cx, &mut buf)) {
+                    Ok(()) => {
+                        if buf.filled().is_empty() {
+                            self.inner.eof = true;
+                        } else {
+                            self.inner.first_read.get_or_insert_with(Instant::now);
+                            self.inner.buffer.extend_from_slice(buf.filled());
+                        }
+                    }
+                    Err(e) => return Err(e).into(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn many_short_lines() {
+        let reader = Cursor::new(b"Hello!\nI like your code.\nGoodbye!\n");
+        let mut lines = ByteLines::new(reader);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hello!\n");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            b"I like your code.\n"
+        );
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!\n");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn nul_delimited() {
+        let reader = Cursor::new(b"./foo\0./bar baz\n\0./quux");
+        let mut lines = ByteLines::new(reader);
+        lines.delimiter = 0;
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"./foo\0");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"./bar baz\n\0");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"./quux");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn many_short_lines_no_final_newline() {
+        let reader = Cursor::new(b"Hello!\nI like your code.\nGoodbye!");
+        let mut lines = ByteLines::new(reader);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hello!\n");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            b"I like your code.\n"
+        );
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn split_line() {
+        let reader = Builder::new()
+            .read(b"Hello, ")
+            .read(b"World!\n")
+            .read(b"Bye now!\n")
+            .build();
+        let mut lines = ByteLines::new(reader);
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            b"Hello, World!\n"
+        );
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Bye now!\n");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn non_utf8() {
+        let reader = Cursor::new(b"Hell\xF6!\nI like your code.\nGoodbye!\n");
+        let mut lines = ByteLines::new(reader);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hell\xF6!\n");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            b"I like your code.\n"
+        );
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!\n");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn take_partial() {
+        let reader = Builder::new()
+            .read(b"Hello!\nPassword: ")
+            .read(b"\nGoodbye!\n")
+            .build();
+        let mut lines = ByteLines::new(reader);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hello!\n");
+        assert_eq!(lines.partial_len(), 10);
+        assert_eq!(lines.take_partial().unwrap(), b"Password: ");
+        assert_eq!(lines.partial_len(), 0);
+        assert_eq!(lines.take_partial(), None);
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"\n");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!\n");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn strip_cr() {
+        let reader = Cursor::new(b"Hello!\r\nGoodbye!\n");
+        let mut lines = ByteLines::new(reader);
+        lines.strip_cr = true;
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hello!\n");
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!\n");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+    }
+
+    /// A small deterministic pseudorandom number generator (xorshift64*)
+    /// for generating test cases
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            let x = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32;
+            usize::try_from(x).unwrap() % n
+        }
+
+        /// Generate a stream of bytes that's heavy on delimiters &
+        /// carriage returns
+        fn data(&mut self) -> Vec<u8> {
+            const ALPHABET: &[u8] = b"\n\n\r\0ab \xF6";
+            let len = self.below(200);
+            std::iter::repeat_with(|| ALPHABET[self.below(ALPHABET.len())])
+                .take(len)
+                .collect()
+        }
+
+        /// Split `data` into nonempty chunks at random positions
+        fn chunks<'a>(&mut self, data: &'a [u8]) -> Vec<&'a [u8]> {
+            let mut chunks = Vec::new();
+            let mut rest = data;
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(1 + self.below(rest.len().min(40)));
+                chunks.push(chunk);
+                rest = tail;
+            }
+            chunks
+        }
+    }
+
+    /// Read all of the records from a reader that delivers `chunks`
+    async fn read_records(chunks: &[&[u8]], delimiter: u8, strip_cr: bool) -> Vec<Vec<u8>> {
+        let mut builder = Builder::new();
+        for &chunk in chunks {
+            builder.read(chunk);
+        }
+        let mut lines = ByteLines::new(builder.build());
+        lines.delimiter = delimiter;
+        lines.strip_cr = strip_cr;
+        let mut records = Vec::new();
+        while let Some(ln) = lines.next_line().await.unwrap() {
+            records.push(ln);
+        }
+        records
+    }
+
+    #[tokio::test]
+    async fn arbitrary_chunking() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for n in 0..1000 {
+            let delimiter = if n % 2 == 0 { b'\n' } else { b'\0' };
+            let data = rng.data();
+            let chunks = rng.chunks(&data);
+            let records = read_records(&chunks, delimiter, false).await;
+            assert_eq!(records.concat(), data, "chunks = {chunks:?}");
+            for (i, rec) in records.iter().enumerate() {
+                let (&last, body) = rec.split_last().expect("records should be nonempty");
+                assert!(
+                    !body.contains(&delimiter),
+                    "delimiter in middle of record {rec:?}"
+                );
+                assert!(
+                    last == delimiter || i + 1 == records.len(),
+                    "unterminated record {rec:?} before EOF"
+                );
+            }
+            assert_eq!(
+                records,
+                read_records(&[&data], delimiter, false).await,
+                "chunks = {chunks:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn arbitrary_chunking_strip_cr() {
+        let mut rng = Rng(0x243F_6A88_85A3_08D3);
+        for _ in 0..500 {
+            let data = rng.data();
+            let chunks = rng.chunks(&data);
+            let records = read_records(&chunks, b'\n', true).await;
+            let expected = data
+                .iter()
+                .enumerate()
+                .filter(|&(i, &b)| !(b == b'\r' && data.get(i + 1) == Some(&b'\n')))
+                .map(|(_, &b)| b)
+                .collect::<Vec<_>>();
+            assert_eq!(records.concat(), expected, "chunks = {chunks:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn arbitrary_take_partial() {
+        let mut rng = Rng(0x1319_8A2E_0370_7344);
+        for _ in 0..500 {
+            let data = rng.data();
+            let chunks = rng.chunks(&data);
+            let mut builder = Builder::new();
+            for &chunk in &chunks {
+                builder.read(chunk);
+            }
+            let mut lines = ByteLines::new(builder.build());
+            let mut records = Vec::new();
+            while let Some(ln) = lines.next_line().await.unwrap() {
+                records.push(ln);
+                if rng.below(3) == 0 {
+                    if let Some(partial) = lines.take_partial() {
+                        assert!(!partial.contains(&b'\n'), "chunks = {chunks:?}");
+                        records.push(partial);
+                    }
+                }
+            }
+            assert!(records.iter().all(|rec| !rec.is_empty()));
+            assert_eq!(records.concat(), data, "chunks = {chunks:?}");
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use cfg_if::cfg_if;
+use std::io;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+
+/// How long the helper process is given to fail at startup (e.g., because
+/// `systemd-inhibit` wasn't allowed to take the lock) before it's assumed to
+/// be working
+const HELPER_STARTUP: Duration = Duration::from_millis(250);
+
+/// A helper process that prevents the system from going to sleep for as long
+/// as it runs
+#[derive(Debug)]
+pub(crate) struct SleepInhibitor {
+    helper: Child,
+}
+
+impl SleepInhibitor {
+    /// Start a helper process that keeps the system awake, giving `why` as
+    /// the reason where supported.
+    ///
+    /// On Linux, this uses `systemd-inhibit`; on macOS, this uses
+    /// `caffeinate`.  On other platforms, this returns an error of kind
+    /// [`io::ErrorKind::Unsupported`].  If the helper exits during the first
+    /// [`HELPER_STARTUP`], an error including its stderr is returned.
+    pub(crate) async fn start(why: &str) -> io::Result<SleepInhibitor> {
+        let (program, mut cmd) = helper_command(why)?;
+        let mut helper = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        // `systemd-inhibit` exits immediately when it can't take the lock,
+        // e.g., when polkit refuses a user logged in over SSH or logind isn't
+        // running, and that shouldn't go unnoticed.
+        match tokio::time::timeout(HELPER_STARTUP, helper.wait()).await {
+            Ok(r) => {
+                let rc = r?;
+                let mut msg = String::new();
+                if let Some(mut stderr) = helper.stderr.take() {
+                    let _ = stderr.read_to_string(&mut msg).await;
+                }
+                let msg = msg.trim();
+                if msg.is_empty() {
+                    Err(io::Error::other(format!("{program} failed: {rc}")))
+                } else {
+                    Err(io::Error::other(format!("{program} failed: {rc}: {msg}")))
+                }
+            }
+            Err(_) => Ok(SleepInhibitor { helper }),
+        }
+    }
+
+    /// Terminate the helper process, allowing the system to sleep again
+    pub(crate) async fn stop(mut self) -> io::Result<()> {
+        self.helper.kill().await
+    }
+}
+
+/// Return the name of the helper program that keeps the system awake on this
+/// platform along with the command for running it, giving `why` as the reason
+/// where supported
+// This only fails on platforms without a helper.
+#[allow(clippy::unnecessary_wraps)]
+fn helper_command(why: &str) -> io::Result<(&'static str, Command)> {
+    cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            let program = "systemd-inhibit";
+            let mut cmd = Command::new(program);
+            cmd.arg("--what=idle:sleep")
+                .arg("--who=elapsed")
+                .arg(format!("--why={why}"))
+                .arg("--mode=block")
+                .arg("sleep")
+                .arg("infinity");
+            Ok((program, cmd))
+        } else if #[cfg(target_os = "macos")] {
+            let _ = why;
+            let program = "caffeinate";
+            let mut cmd = Command::new(program);
+            cmd.arg("-i");
+            Ok((program, cmd))
+        } else {
+            let _ = why;
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "not supported on this platform",
+            ))
+        }
+    }
+}
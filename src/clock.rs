@@ -0,0 +1,338 @@
+use std::fmt::{self, Write};
+use std::time::{Duration, SystemTime};
+
+/// The minimum discrepancy between the wall-clock duration and the elapsed
+/// time (as measured by a monotonic clock, which does not advance while the
+/// system is suspended) for the former to be reported
+const DISCREPANCY_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// The time zone in which wall-clock times are displayed
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Zone {
+    /// The system's local time zone
+    #[default]
+    Local,
+    /// UTC, for `--utc`
+    Utc,
+}
+
+impl Zone {
+    /// Return the zone's offset from UTC, in seconds, as of time `t`.  If
+    /// the local time zone can't be determined, it's taken to be UTC.
+    fn offset_at(self, t: SystemTime) -> i64 {
+        match self {
+            Zone::Local => jiff::Timestamp::try_from(t).map_or(0, |ts| {
+                i64::from(jiff::tz::TimeZone::system().to_offset(ts).seconds())
+            }),
+            Zone::Utc => 0,
+        }
+    }
+}
+
+/// The wall-clock time at which the command was started, used for reporting
+/// when the command ran in addition to how long it ran, along with any jumps
+/// in the wall clock noticed since then
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct WallClock {
+    start: SystemTime,
+    /// The time zone in which to display times
+    zone: Zone,
+    /// The difference, in milliseconds, between the wall-clock time passed
+    /// since the start and the elapsed time as of the last check
+    offset: i128,
+    jumps: Vec<ClockJump>,
+}
+
+impl WallClock {
+    pub(crate) fn start(zone: Zone) -> WallClock {
+        WallClock {
+            start: SystemTime::now(),
+            zone,
+            offset: 0,
+            jumps: Vec::new(),
+        }
+    }
+
+    /// Compare the wall clock against the elapsed time as measured by a
+    /// monotonic clock, and record a jump if the difference between them has
+    /// changed by at least [`DISCREPANCY_THRESHOLD`] since the last check
+    /// (e.g., because the system clock was stepped by NTP or set by hand, or
+    /// because the system was suspended).  Gradual drift is not reported.
+    pub(crate) fn check(&mut self, elapsed: Duration) {
+        self.check_at(SystemTime::now(), elapsed);
+    }
+
+    fn check_at(&mut self, now: SystemTime, elapsed: Duration) {
+        let wall = match now.duration_since(self.start) {
+            Ok(d) => i128::try_from(d.as_millis()).unwrap_or(i128::MAX),
+            Err(e) => -i128::try_from(e.duration().as_millis()).unwrap_or(i128::MAX),
+        };
+        let offset = wall - i128::try_from(elapsed.as_millis()).unwrap_or(i128::MAX);
+        let change = offset - self.offset;
+        let by = Duration::from_millis(u64::try_from(change.unsigned_abs()).unwrap_or(u64::MAX));
+        if by >= DISCREPANCY_THRESHOLD {
+            self.jumps.push(ClockJump {
+                at: elapsed,
+                forwards: change > 0,
+                by,
+            });
+        }
+        self.offset = offset;
+    }
+
+    /// Return the jumps in the wall clock noticed so far
+    pub(crate) fn jumps(&self) -> &[ClockJump] {
+        &self.jumps
+    }
+
+    /// Return the date & time at which the command was started, in the form
+    /// "YYYY-MM-DDTHH:MM:SS+HH:MM" (or "...Z" in UTC)
+    pub(crate) fn start_timestamp(&self) -> String {
+        format_timestamp(self.start, self.zone)
+    }
+
+    /// Return the time of day at which the command was started, in the form
+    /// "HH:MM:SS+HH:MM" (or "HH:MM:SSZ" in UTC)
+    pub(crate) fn start_time_of_day(&self) -> String {
+        format_time_of_day(self.start, self.zone)
+    }
+
+    /// Describe the span of wall-clock time from the start until now, given
+    /// the elapsed time as measured by a monotonic clock
+    pub(crate) fn describe_span(&self, elapsed: Duration) -> String {
+        describe_span(self.start, SystemTime::now(), elapsed, self.zone)
+    }
+}
+
+/// A sudden change in the wall-clock time relative to the elapsed time
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct ClockJump {
+    /// How long after the start the jump was noticed
+    pub(crate) at: Duration,
+    /// True if the wall clock jumped forwards, false if backwards
+    pub(crate) forwards: bool,
+    /// How far the wall clock jumped
+    pub(crate) by: Duration,
+}
+
+impl fmt::Display for ClockJump {
+    /// Display the jump in the form "+HH:MM:SS at HH:MM:SS"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.forwards { '+' } else { '-' };
+        let (by, at) = (self.by.as_secs(), self.at.as_secs());
+        write!(
+            f,
+            "{sign}{:02}:{:02}:{:02} at {:02}:{:02}:{:02}",
+            by / 3600,
+            by / 60 % 60,
+            by % 60,
+            at / 3600,
+            at / 60 % 60,
+            at % 60
+        )
+    }
+}
+
+/// Describe the span of wall-clock time from `start` to `end` in time zone
+/// `zone`, e.g., "2026-10-16T12:00:00Z to 2026-10-16T12:05:03Z".  If the
+/// wall-clock duration differs from `elapsed` by at least
+/// [`DISCREPANCY_THRESHOLD`], the wall-clock duration is included as well.
+fn describe_span(start: SystemTime, end: SystemTime, elapsed: Duration, zone: Zone) -> String {
+    let mut s = format!(
+        "{} to {}",
+        format_timestamp(start, zone),
+        format_timestamp(end, zone)
+    );
+    let wall = end.duration_since(start).unwrap_or_default();
+    if wall.abs_diff(elapsed) >= DISCREPANCY_THRESHOLD {
+        let secs = wall.as_secs();
+        let _ = write!(
+            s,
+            "; wall clock {:02}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        );
+    }
+    s
+}
+
+/// Format a timestamp in time zone `zone` as an ISO 8601 date & time, in the
+/// form "YYYY-MM-DDTHH:MM:SS+HH:MM", or "YYYY-MM-DDTHH:MM:SSZ" in UTC.
+/// Times before the Unix epoch are treated as the epoch.
+pub(crate) fn format_timestamp(t: SystemTime, zone: Zone) -> String {
+    let (secs, suffix) = local_seconds(t, zone);
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let secs = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}{suffix}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Format the time of day of a timestamp in time zone `zone` in the form
+/// "HH:MM:SS+HH:MM", or "HH:MM:SSZ" in UTC.  Times before the Unix epoch are
+/// treated as the epoch.
+fn format_time_of_day(t: SystemTime, zone: Zone) -> String {
+    let (secs, suffix) = local_seconds(t, zone);
+    let secs = secs % 86400;
+    format!(
+        "{:02}:{:02}:{:02}{suffix}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Return the number of seconds since the Unix epoch of the time `t` as read
+/// on a clock in time zone `zone`, along with the ISO 8601 designator for
+/// the zone's offset from UTC at that time
+fn local_seconds(t: SystemTime, zone: Zone) -> (u64, String) {
+    let secs = t
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    match zone {
+        Zone::Utc => (secs, String::from("Z")),
+        Zone::Local => {
+            let offset = zone.offset_at(t);
+            (secs.saturating_add_signed(offset), format_offset(offset))
+        }
+    }
+}
+
+/// Format an offset from UTC in seconds in the form "+HH:MM" or "-HH:MM"
+fn format_offset(offset: i64) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let minutes = offset.unsigned_abs() / 60;
+    format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Convert a number of days since 1970-01-01 to a (year, month, day) date in
+/// the proleptic Gregorian calendar, using Howard Hinnant's `civil_from_days`
+/// algorithm
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[rstest]
+    #[case(0, "1970-01-01T00:00:00Z")]
+    #[case(951_782_400, "2000-02-29T00:00:00Z")]
+    #[case(1_709_251_199, "2024-02-29T23:59:59Z")]
+    #[case(1_735_689_600, "2025-01-01T00:00:00Z")]
+    #[case(1_792_152_000, "2026-10-16T12:00:00Z")]
+    #[case(4_102_444_800, "2100-01-01T00:00:00Z")]
+    fn utc(#[case] secs: u64, #[case] s: &str) {
+        assert_eq!(format_timestamp(at(secs), Zone::Utc), s);
+    }
+
+    #[rstest]
+    #[case(0, "00:00:00Z")]
+    #[case(1_709_251_199, "23:59:59Z")]
+    #[case(1_792_159_331, "14:02:11Z")]
+    fn utc_time(#[case] secs: u64, #[case] s: &str) {
+        assert_eq!(format_time_of_day(at(secs), Zone::Utc), s);
+    }
+
+    #[test]
+    fn utc_before_epoch() {
+        let t = SystemTime::UNIX_EPOCH - Duration::from_secs(60);
+        assert_eq!(format_timestamp(t, Zone::Utc), "1970-01-01T00:00:00Z");
+    }
+
+    #[rstest]
+    #[case(0, "+00:00")]
+    #[case(7200, "+02:00")]
+    #[case(19_800, "+05:30")]
+    #[case(-16_200, "-04:30")]
+    fn offset_designator(#[case] offset: i64, #[case] s: &str) {
+        assert_eq!(format_offset(offset), s);
+    }
+
+    #[rstest]
+    #[case(303, "2026-10-16T12:00:00Z to 2026-10-16T12:05:03Z")]
+    #[case(302, "2026-10-16T12:00:00Z to 2026-10-16T12:05:03Z")]
+    #[case(3, "2026-10-16T12:00:00Z to 2026-10-16T12:05:03Z; wall clock 00:05:03")]
+    fn span(#[case] elapsed_secs: u64, #[case] s: &str) {
+        let start = at(1_792_152_000);
+        let end = at(1_792_152_303);
+        let elapsed = Duration::from_millis(elapsed_secs * 1000 + 500);
+        assert_eq!(describe_span(start, end, elapsed, Zone::Utc), s);
+    }
+
+    #[test]
+    fn jumps() {
+        let mut clock = WallClock {
+            start: at(1_792_152_000),
+            zone: Zone::Utc,
+            offset: 0,
+            jumps: Vec::new(),
+        };
+        // Small amounts of drift aren't jumps, even once they add up.
+        clock.check_at(at(1_792_152_010), Duration::from_millis(9_400));
+        clock.check_at(at(1_792_152_020), Duration::from_millis(18_800));
+        assert_eq!(clock.jumps(), []);
+        // The clock is set forward an hour.
+        clock.check_at(at(1_792_155_630), Duration::from_millis(28_800));
+        // Nothing more happens for a while.
+        clock.check_at(at(1_792_155_640), Duration::from_millis(38_800));
+        // The clock is stepped back five seconds.
+        clock.check_at(at(1_792_155_645), Duration::from_millis(48_800));
+        assert_eq!(
+            clock.jumps(),
+            [
+                ClockJump {
+                    at: Duration::from_millis(28_800),
+                    forwards: true,
+                    by: Duration::from_secs(3600),
+                },
+                ClockJump {
+                    at: Duration::from_millis(48_800),
+                    forwards: false,
+                    by: Duration::from_secs(5),
+                },
+            ]
+        );
+        assert_eq!(clock.jumps()[0].to_string(), "+01:00:00 at 00:00:28");
+        assert_eq!(clock.jumps()[1].to_string(), "-00:00:05 at 00:00:48");
+    }
+
+    #[test]
+    fn jump_before_start() {
+        let mut clock = WallClock {
+            start: at(1_792_152_000),
+            zone: Zone::Utc,
+            offset: 0,
+            jumps: Vec::new(),
+        };
+        clock.check_at(at(1_792_151_990), Duration::from_secs(5));
+        assert_eq!(
+            clock.jumps(),
+            [ClockJump {
+                at: Duration::from_secs(5),
+                forwards: false,
+                by: Duration::from_secs(15),
+            }]
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+
+/// The file that `--debug` writes to when no path is given
+pub(crate) const DEFAULT_DEBUG_FILE: &str = "elapsed-debug.log";
+
+/// Start logging `elapsed`'s internal events (reads, redraws, exit handling,
+/// etc.) to the file at `path`, as is done when `--debug` is given so that
+/// users can report misbehavior with actionable details.  Each entry is
+/// prefixed with the time since `start` and written out immediately.
+pub(crate) fn init(path: &Path, start: Instant) -> io::Result<()> {
+    let file = File::create(path)?;
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_timer(SinceStart(start))
+        .with_max_level(LevelFilter::DEBUG)
+        .with_ansi(false)
+        .with_level(false)
+        .with_target(false)
+        .finish();
+    tracing::subscriber::set_global_default(subscriber).map_err(io::Error::other)
+}
+
+/// A timer for log entries that shows the time since the command was started
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct SinceStart(Instant);
+
+impl FormatTime for SinceStart {
+    fn format_time(&self, w: &mut Writer<'_>) -> fmt::Result {
+        let elapsed = self.0.elapsed();
+        write!(
+            w,
+            "[{:5}.{:06}]",
+            elapsed.as_secs(),
+            elapsed.subsec_micros()
+        )
+    }
+}
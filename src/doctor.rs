@@ -0,0 +1,219 @@
+//! Implementation of `--doctor`, which reports on the terminal environment and
+//! which terminal features `elapsed` will use in it
+use crate::term;
+use std::io::{self, IsTerminal, Write};
+
+/// Write a report on the environment to `out`
+pub(crate) fn run<W: Write>(mut out: W) -> io::Result<()> {
+    let stderr_tty = io::stderr().is_terminal();
+    let mut report = vec![
+        ("elapsed version", String::from(env!("CARGO_PKG_VERSION"))),
+        (
+            "TERM",
+            std::env::var("TERM").unwrap_or_else(|_| String::from("(not set)")),
+        ),
+        ("stdin", describe_tty(io::stdin().is_terminal())),
+        ("stdout", describe_tty(io::stdout().is_terminal())),
+        ("stderr", describe_tty(stderr_tty)),
+        ("Terminal size", terminal_size()),
+    ];
+    let status = if !stderr_tty {
+        "not shown, as stderr is not a terminal"
+    } else if term::is_dumb() {
+        "shown on stderr, erased with backspaces as TERM is \"dumb\""
+    } else {
+        "shown on stderr, redrawn with ANSI escape sequences"
+    };
+    report.push(("Status line", String::from(status)));
+    let color = if stderr_tty && !term::is_dumb() {
+        "yes"
+    } else {
+        "no"
+    };
+    report.push(("Color stderr by default", String::from(color)));
+    report.push(("Synchronized output", sync_output()));
+    report.push(("Pseudo-terminals (--tty)", pty_availability()));
+    let notify = if term::is_kitty() {
+        "OSC 99 (kitty)"
+    } else {
+        "OSC 9"
+    };
+    report.push(("Notifications (--notify)", String::from(notify)));
+    let tmux = match (std::env::var_os("TMUX"), std::env::var("TMUX_PANE")) {
+        (Some(_), Ok(pane)) => format!("running inside tmux pane {pane}"),
+        (Some(_), Err(_)) => String::from("running inside tmux, but TMUX_PANE is not set"),
+        (None, _) => String::from("not running inside tmux"),
+    };
+    report.push(("tmux (--tmux)", tmux));
+    let systemd = if std::env::var_os("NOTIFY_SOCKET").is_some() {
+        "NOTIFY_SOCKET is set; the service's start timeout will be extended"
+    } else {
+        "NOTIFY_SOCKET is not set"
+    };
+    report.push(("systemd", String::from(systemd)));
+    let width = report
+        .iter()
+        .map(|(k, _)| k.len())
+        .max()
+        .unwrap_or_default();
+    for (key, value) in report {
+        writeln!(out, "{key:width$}  {value}")?;
+    }
+    out.flush()
+}
+
+fn describe_tty(is_tty: bool) -> String {
+    String::from(if is_tty { "terminal" } else { "not a terminal" })
+}
+
+fn terminal_size() -> String {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            match terminal_size::terminal_size() {
+                Some((width, height)) => format!("{} columns x {} rows", width.0, height.0),
+                None => String::from("unknown"),
+            }
+        } else {
+            String::from("unknown")
+        }
+    }
+}
+
+fn pty_availability() -> String {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            // Creating a pty registers it with the Tokio runtime for async I/O.
+            let rt = match tokio::runtime::Builder::new_current_thread()
+                .enable_io()
+                .build()
+            {
+                Ok(rt) => rt,
+                Err(e) => return format!("unknown ({e})"),
+            };
+            let _guard = rt.enter();
+            match pty_process::open() {
+                Ok(_) => String::from("available"),
+                Err(e) => format!("unavailable: {e}"),
+            }
+        } else {
+            String::from("not supported on this system")
+        }
+    }
+}
+
+fn sync_output() -> String {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            match query_sync_output() {
+                Ok(Some(true)) => String::from("supported"),
+                Ok(Some(false)) => String::from("not supported"),
+                Ok(None) => String::from("unknown (the terminal did not respond)"),
+                Err(e) => format!("unknown ({e})"),
+            }
+        } else {
+            String::from("unknown")
+        }
+    }
+}
+
+/// Ask the terminal whether it supports synchronized output (DEC private mode
+/// 2026) by sending a DECRQM query followed by a Primary Device Attributes
+/// query.  As practically all terminals respond to the latter, its response
+/// marks the end of any response to the former.  Returns `None` if the
+/// terminal did not respond at all.
+#[cfg(unix)]
+fn query_sync_output() -> io::Result<Option<bool>> {
+    use nix::sys::termios::{LocalFlags, SetArg, SpecialCharacterIndices, tcgetattr, tcsetattr};
+    use std::io::Read;
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    let orig = tcgetattr(&tty)?;
+    let mut raw = orig.clone();
+    raw.local_flags
+        .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+    // Make reads time out after half a second without input:
+    raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 0;
+    raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 5;
+    tcsetattr(&tty, SetArg::TCSANOW, &raw)?;
+    let mut inner = || -> io::Result<Vec<u8>> {
+        tty.write_all(b"\x1B[?2026$p\x1B[c")?;
+        tty.flush()?;
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 64];
+        while !has_device_attributes(&buf) && buf.len() < 1024 {
+            let n = tty.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+        Ok(buf)
+    };
+    let r = inner();
+    tcsetattr(&tty, SetArg::TCSANOW, &orig)?;
+    Ok(parse_sync_output_reply(&r?))
+}
+
+/// Interpret the terminal's response to the queries sent by
+/// [`query_sync_output()`]
+fn parse_sync_output_reply(buf: &[u8]) -> Option<bool> {
+    const PREFIX: &[u8] = b"\x1B[?2026;";
+    if let Some(i) = buf.windows(PREFIX.len()).position(|w| w == PREFIX) {
+        // 1 = set, 2 = reset, 3 = permanently set, 4 = permanently reset, 0
+        // = not recognized
+        match buf[i + PREFIX.len()..].get(..3) {
+            Some(b"1$y" | b"2$y" | b"3$y") => return Some(true),
+            Some(_) => return Some(false),
+            None => (),
+        }
+    }
+    has_device_attributes(buf).then_some(false)
+}
+
+/// Returns true if `buf` contains a Primary Device Attributes response, i.e.,
+/// `ESC [ ?` followed by digits & semicolons and then `c`
+fn has_device_attributes(buf: &[u8]) -> bool {
+    let mut rest = buf;
+    while let Some(i) = rest.windows(3).position(|w| w == b"\x1B[?") {
+        rest = &rest[i + 3..];
+        let params = rest
+            .iter()
+            .take_while(|&&b| b.is_ascii_digit() || b == b';')
+            .count();
+        if rest.get(params) == Some(&b'c') {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(b"", None)]
+    #[case(b"\x1B[?2026;", None)]
+    #[case(b"\x1B[?2026;1$y", Some(true))]
+    #[case(b"\x1B[?2026;2$y\x1B[?62;22c", Some(true))]
+    #[case(b"\x1B[?2026;4$y\x1B[?62;22c", Some(false))]
+    #[case(b"\x1B[?2026;0$y\x1B[?62;22c", Some(false))]
+    #[case(b"\x1B[?62;22c", Some(false))]
+    #[case(b"\x1B[?1;2c", Some(false))]
+    fn sync_output_reply(#[case] buf: &[u8], #[case] supported: Option<bool>) {
+        assert_eq!(parse_sync_output_reply(buf), supported);
+    }
+
+    #[rstest]
+    #[case(b"", false)]
+    #[case(b"\x1B[?62;22c", true)]
+    #[case(b"junk\x1B[?2026;2$y\x1B[?6c", true)]
+    #[case(b"\x1B[?62;22", false)]
+    #[case(b"\x1B[?2026;2$y", false)]
+    fn device_attributes(#[case] buf: &[u8], #[case] found: bool) {
+        assert_eq!(has_device_attributes(buf), found);
+    }
+}
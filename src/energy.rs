@@ -0,0 +1,199 @@
+//! Measurement of the energy consumed during the run via the RAPL (Running
+//! Average Power Limit) counters exposed by Linux's powercap framework, for
+//! `--energy`
+use crate::units::format_decimal;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// The directory in which Linux exposes powercap zones
+const POWERCAP_DIR: &str = "/sys/class/powercap";
+
+/// Energy counter readings for each CPU package taken at the start of the run
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct EnergyMeter {
+    zones: Vec<Zone>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Zone {
+    path: PathBuf,
+    /// The value after which the zone's counter wraps around to zero, in
+    /// microjoules
+    max_range: u64,
+    /// The counter's value at the start of the run, in microjoules
+    start: u64,
+}
+
+impl EnergyMeter {
+    /// Read the starting values of the energy counters for all CPU packages
+    pub(crate) fn start() -> Result<EnergyMeter, EnergyError> {
+        EnergyMeter::start_in(Path::new(POWERCAP_DIR))
+    }
+
+    fn start_in(dir: &Path) -> Result<EnergyMeter, EnergyError> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(EnergyError::NotFound),
+            Err(source) => {
+                return Err(EnergyError::Read {
+                    path: dir.to_path_buf(),
+                    source,
+                });
+            }
+        };
+        let mut paths = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_str().is_some_and(is_package_zone))
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        if paths.is_empty() {
+            return Err(EnergyError::NotFound);
+        }
+        paths.sort();
+        let mut zones = Vec::with_capacity(paths.len());
+        for path in paths {
+            let max_range = read_counter(&path.join("max_energy_range_uj"))?;
+            let start = read_counter(&path.join("energy_uj"))?;
+            zones.push(Zone {
+                path,
+                max_range,
+                start,
+            });
+        }
+        Ok(EnergyMeter { zones })
+    }
+
+    /// Return the total energy consumed by all CPU packages since the meter
+    /// was started, in microjoules
+    pub(crate) fn consumed(&self) -> Result<u64, EnergyError> {
+        let mut total = 0u64;
+        for zone in &self.zones {
+            let end = read_counter(&zone.path.join("energy_uj"))?;
+            total = total.saturating_add(counter_delta(zone.start, end, zone.max_range));
+        }
+        Ok(total)
+    }
+}
+
+/// Returns true if `name` is the name of a top-level RAPL zone, which covers
+/// a whole CPU package.  Subzones (e.g., "intel-rapl:0:0" for the cores of
+/// package 0) are already included in their package's counter, and the
+/// "intel-rapl-mmio" zones duplicate the package zones.
+fn is_package_zone(name: &str) -> bool {
+    name.strip_prefix("intel-rapl:")
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Return the amount a counter has increased by from `start` to `end`,
+/// allowing for it to have wrapped around once after reaching `max_range`
+fn counter_delta(start: u64, end: u64, max_range: u64) -> u64 {
+    if end >= start {
+        end - start
+    } else {
+        max_range.saturating_sub(start).saturating_add(end)
+    }
+}
+
+fn read_counter(path: &Path) -> Result<u64, EnergyError> {
+    let read_err = |source| EnergyError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+    fs::read_to_string(path)
+        .map_err(read_err)?
+        .trim()
+        .parse()
+        .map_err(|e| read_err(io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+/// Format an amount of energy in microjoules as joules with three decimal
+/// places, e.g., "12.345 J"
+pub(crate) fn format_joules(microjoules: u64) -> String {
+    let frac = format!("{:03}", microjoules % 1_000_000 / 1000);
+    format!("{} J", format_decimal(microjoules / 1_000_000, &frac))
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum EnergyError {
+    #[error("no RAPL energy counters found (only supported on Linux)")]
+    NotFound,
+    #[error("failed to read {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("intel-rapl:0", true)]
+    #[case("intel-rapl:12", true)]
+    #[case("intel-rapl:0:1", false)]
+    #[case("intel-rapl:", false)]
+    #[case("intel-rapl-mmio:0", false)]
+    #[case("intel-rapl", false)]
+    #[case("dtpm", false)]
+    fn package_zone(#[case] name: &str, #[case] ok: bool) {
+        assert_eq!(is_package_zone(name), ok);
+    }
+
+    #[rstest]
+    #[case(100, 250, 1000, 150)]
+    #[case(900, 50, 1000, 150)]
+    #[case(500, 500, 1000, 0)]
+    fn delta(#[case] start: u64, #[case] end: u64, #[case] max_range: u64, #[case] d: u64) {
+        assert_eq!(counter_delta(start, end, max_range), d);
+    }
+
+    #[rstest]
+    #[case(0, "0.000 J")]
+    #[case(999, "0.000 J")]
+    #[case(12_345_678, "12.345 J")]
+    #[case(1_000_000, "1.000 J")]
+    fn joules(#[case] microjoules: u64, #[case] s: &str) {
+        assert_eq!(format_joules(microjoules), s);
+    }
+
+    fn write_zone(dir: &Path, name: &str, max_range: u64, energy: u64) {
+        let zone = dir.join(name);
+        fs::create_dir(&zone).unwrap();
+        fs::write(zone.join("max_energy_range_uj"), format!("{max_range}\n")).unwrap();
+        fs::write(zone.join("energy_uj"), format!("{energy}\n")).unwrap();
+    }
+
+    #[test]
+    fn meter() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_zone(tmp.path(), "intel-rapl:0", 1_000_000, 999_000);
+        write_zone(tmp.path(), "intel-rapl:0:0", 1_000_000, 5000);
+        write_zone(tmp.path(), "intel-rapl:1", 1_000_000, 2000);
+        let meter = EnergyMeter::start_in(tmp.path()).unwrap();
+        fs::write(tmp.path().join("intel-rapl:0/energy_uj"), "4000\n").unwrap();
+        fs::write(tmp.path().join("intel-rapl:0:0/energy_uj"), "9000\n").unwrap();
+        fs::write(tmp.path().join("intel-rapl:1/energy_uj"), "2500\n").unwrap();
+        assert_eq!(meter.consumed().unwrap(), 5000 + 500);
+    }
+
+    #[test]
+    fn meter_no_zones() {
+        let tmp = tempfile::tempdir().unwrap();
+        write_zone(tmp.path(), "intel-rapl-mmio:0", 1_000_000, 0);
+        assert_matches!(
+            EnergyMeter::start_in(tmp.path()),
+            Err(EnergyError::NotFound)
+        );
+    }
+
+    #[test]
+    fn meter_no_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert_matches!(
+            EnergyMeter::start_in(&tmp.path().join("nonexistent")),
+            Err(EnergyError::NotFound)
+        );
+    }
+}
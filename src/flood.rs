@@ -0,0 +1,89 @@
+//! Detecting when the command is flooding the terminal with output, for
+//! `--auto-hide-at`
+use std::time::{Duration, Instant};
+
+/// The minimum length of the windows of time over which the output rate is
+/// measured.  This is kept shorter than the default refresh period so that,
+/// when windows are closed by ticks, every tick closes one.
+const WINDOW: Duration = Duration::from_millis(500);
+
+/// Tracks the rate at which the command produces lines of output in order to
+/// tell when it exceeds a threshold
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct FloodDetector {
+    /// The rate, in lines per second, above which output counts as a flood
+    threshold: u64,
+    /// When the current measurement window started
+    window_start: Instant,
+    /// The number of lines received in the current window
+    lines: u64,
+    flooding: bool,
+}
+
+impl FloodDetector {
+    pub(crate) fn new(threshold: u64, now: Instant) -> FloodDetector {
+        FloodDetector {
+            threshold,
+            window_start: now,
+            lines: 0,
+            flooding: false,
+        }
+    }
+
+    /// Record a line of output received at time `now`, and return whether
+    /// output is currently flooding.  A flood is detected as soon as more
+    /// lines arrive within a single window than the threshold rate allows for
+    /// the minimum window length.
+    pub(crate) fn record(&mut self, now: Instant) -> bool {
+        self.lines += 1;
+        if u128::from(self.lines) * 1000 > u128::from(self.threshold) * WINDOW.as_millis() {
+            self.flooding = true;
+        }
+        self.check(now)
+    }
+
+    /// Return whether output is currently flooding as of time `now`.  Once
+    /// the current window is over, this is decided by whether the average
+    /// rate over the window exceeded the threshold, and a new window is
+    /// started; thus, a flood is considered to have ended as soon as output
+    /// slows down for a full window.
+    pub(crate) fn check(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.window_start);
+        if elapsed >= WINDOW {
+            self.flooding =
+                u128::from(self.lines) * 1000 > u128::from(self.threshold) * elapsed.as_millis();
+            self.window_start = now;
+            self.lines = 0;
+        }
+        self.flooding
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flood() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut detector = FloodDetector::new(100, start);
+        for i in 0..50 {
+            assert!(!detector.record(at(i)));
+        }
+        assert!(detector.record(at(50)));
+        assert!(detector.check(at(300)));
+        // The first window saw 51 lines in 0.6 seconds, which is under the
+        // threshold.
+        assert!(!detector.check(at(600)));
+        for i in 0..80 {
+            detector.record(at(700 + i));
+        }
+        assert!(detector.check(at(900)));
+        // 80 lines in 0.7 seconds is still a flood.
+        assert!(detector.check(at(1300)));
+        // Nothing for a full window is a lull.
+        assert!(detector.check(at(1700)));
+        assert!(!detector.check(at(1800)));
+    }
+}
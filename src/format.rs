@@ -1,9 +1,37 @@
+use crate::procinfo::ProcStats;
+use crate::units::{format_bytes, format_count};
 use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
 use std::time::Duration;
 use thiserror::Error;
 
 const DEFAULT_PRECISION: usize = 6;
 
+/// The values that a status line can be rendered from
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Snapshot {
+    /// Time elapsed since the command was started
+    pub(crate) elapsed: Duration,
+    /// The most recent statistics sampled for the command's process tree, if
+    /// any
+    pub(crate) proc_stats: Option<ProcStats>,
+    /// How long after the command was started it first produced any output,
+    /// if it has done so yet
+    pub(crate) first_output: Option<Duration>,
+    /// How long the command is expected to run for in total, if known
+    pub(crate) expected: Option<Duration>,
+    /// The percentage of its work that the command last reported having
+    /// done via an OSC 9;4 escape sequence, if any
+    pub(crate) progress: Option<u8>,
+    /// The number of refreshes of the status line so far that were delayed
+    /// because the event loop was stalled
+    pub(crate) missed_ticks: u64,
+    /// How long until the command is stopped for exceeding `--timeout`, if a
+    /// timeout was given
+    pub(crate) time_left: Option<Duration>,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) struct Format {
     pieces: Vec<FormatPiece>,
@@ -22,10 +50,16 @@ impl Format {
         self.newlines
     }
 
-    pub(crate) fn display(&self, d: Duration) -> String {
+    /// Returns true if rendering the format uses statistics about the
+    /// command's process tree, which then need to be sampled on every tick
+    pub(crate) fn uses_proc_stats(&self) -> bool {
+        self.pieces.iter().any(FormatPiece::uses_proc_stats)
+    }
+
+    pub(crate) fn display(&self, snap: &Snapshot) -> String {
         let mut s = String::new();
         for p in &self.pieces {
-            p.display(&mut s, d);
+            p.display(&mut s, snap);
         }
         s
     }
@@ -46,6 +80,16 @@ impl Format {
     }
 }
 
+impl Format {
+    /// The default format for `--precise`, which shows milliseconds as well
+    pub(crate) fn precise() -> Format {
+        let mut fmt = Format::default();
+        fmt.push_char('.');
+        fmt.push(FormatPiece::Subseconds { precision: 3 });
+        fmt
+    }
+}
+
 impl Default for Format {
     fn default() -> Format {
         Format {
@@ -75,13 +119,37 @@ impl std::str::FromStr for Format {
                     Some('M') => fmt.push(FormatPiece::Minute),
                     Some('S') => fmt.push(FormatPiece::Second),
                     Some('s') => fmt.push(FormatPiece::TotalSeconds),
+                    Some('p') => match chars.next() {
+                        Some('s') => fmt.push(FormatPiece::ProcessState),
+                        _ => return Err(ParseFormatError::InvalidPercent('p')),
+                    },
+                    Some('C') => match chars.next() {
+                        Some('u') => fmt.push(FormatPiece::CpuTime),
+                        _ => return Err(ParseFormatError::InvalidPercent('C')),
+                    },
+                    Some('r') if eat(&mut chars, "ss-peak") => {
+                        fmt.push(FormatPiece::RssPeak);
+                    }
                     Some('f') => fmt.push(FormatPiece::Subseconds {
                         precision: DEFAULT_PRECISION,
                     }),
                     Some('n') => fmt.push_char('\n'),
                     Some('t') => fmt.push_char('\t'),
                     Some('e') => fmt.push_char('\x1B'),
+                    Some('P') => fmt.push(FormatPiece::Percent),
+                    Some('m') if eat(&mut chars, "issed") => fmt.push(FormatPiece::MissedTicks),
                     Some('%') => fmt.push_char('%'),
+                    Some('{') => {
+                        let mut name = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('}') => break,
+                                Some(c) => name.push(c),
+                                None => return Err(ParseFormatError::BrokenBrace),
+                            }
+                        }
+                        fmt.push(named_piece(&name).ok_or(ParseFormatError::InvalidName(name))?);
+                    }
                     Some(c) if c.is_ascii_digit() => {
                         let mut precision = c.to_digit(10).expect("should be digit");
                         while let Some(c) = chars.next_if(char::is_ascii_digit) {
@@ -117,6 +185,31 @@ impl std::str::FromStr for Format {
     }
 }
 
+/// Return the piece for the specifier written as `%{name}`.  Specifiers whose
+/// names start with the same letter as a one-letter specifier are written in
+/// braces so that, e.g., `%fd` still means `%f` followed by "d".
+fn named_piece(name: &str) -> Option<FormatPiece> {
+    match name {
+        "eta" => Some(FormatPiece::Eta),
+        "fd" => Some(FormatPiece::FdCount),
+        "th" => Some(FormatPiece::ThreadCount),
+        "ttfo" => Some(FormatPiece::FirstOutput),
+        "tl" => Some(FormatPiece::TimeLeft),
+        _ => None,
+    }
+}
+
+/// If the next characters in `chars` are `s`, consume them and return true
+fn eat(chars: &mut Peekable<Chars<'_>>, s: &str) -> bool {
+    let mut ahead = chars.clone();
+    if s.chars().all(|c| ahead.next() == Some(c)) {
+        *chars = ahead;
+        true
+    } else {
+        false
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum FormatPiece {
     String(String),
@@ -125,10 +218,32 @@ enum FormatPiece {
     Second,
     TotalSeconds,
     Subseconds { precision: usize },
+    CpuTime,
+    ProcessState,
+    FdCount,
+    ThreadCount,
+    RssPeak,
+    FirstOutput,
+    Percent,
+    Eta,
+    TimeLeft,
+    MissedTicks,
 }
 
 impl FormatPiece {
-    fn display(&self, out: &mut String, d: Duration) {
+    fn uses_proc_stats(&self) -> bool {
+        matches!(
+            self,
+            FormatPiece::CpuTime
+                | FormatPiece::ProcessState
+                | FormatPiece::FdCount
+                | FormatPiece::ThreadCount
+                | FormatPiece::RssPeak
+        )
+    }
+
+    fn display(&self, out: &mut String, snap: &Snapshot) {
+        let d = snap.elapsed;
         match self {
             FormatPiece::String(s) => out.push_str(s),
             FormatPiece::Hour => {
@@ -140,9 +255,7 @@ impl FormatPiece {
             FormatPiece::Second => {
                 let _ = write!(out, "{:02}", d.as_secs() % 60);
             }
-            FormatPiece::TotalSeconds => {
-                let _ = write!(out, "{}", d.as_secs());
-            }
+            FormatPiece::TotalSeconds => out.push_str(&format_count(d.as_secs())),
             FormatPiece::Subseconds { precision } => {
                 let mut frac = d.subsec_nanos();
                 let mut divisor = 1_000_000_000 / 10;
@@ -161,10 +274,125 @@ impl FormatPiece {
                     out.push(char::from_digit(d, 10).expect("should be valid decimal digit"));
                 }
             }
+            FormatPiece::CpuTime => {
+                if let Some(stats) = snap.proc_stats {
+                    let secs = stats.cpu_time.as_secs();
+                    let _ = write!(
+                        out,
+                        "{:02}:{:02}:{:02}",
+                        secs / 3600,
+                        secs / 60 % 60,
+                        secs % 60
+                    );
+                } else {
+                    out.push_str("--:--:--");
+                }
+            }
+            FormatPiece::ProcessState => {
+                out.push(snap.proc_stats.map_or('-', |stats| stats.state));
+            }
+            FormatPiece::FdCount => match snap.proc_stats.and_then(|stats| stats.fds) {
+                Some(n) => {
+                    let _ = write!(out, "{n}");
+                }
+                None => out.push('-'),
+            },
+            FormatPiece::ThreadCount => match snap.proc_stats {
+                Some(stats) => {
+                    let _ = write!(out, "{}", stats.threads);
+                }
+                None => out.push('-'),
+            },
+            FormatPiece::RssPeak => match snap.proc_stats {
+                Some(stats) => out.push_str(&format_bytes(stats.rss_peak)),
+                None => out.push('-'),
+            },
+            FormatPiece::FirstOutput => {
+                if let Some(d) = snap.first_output {
+                    let secs = d.as_secs();
+                    let _ = write!(
+                        out,
+                        "{:02}:{:02}:{:02}.{:03}",
+                        secs / 3600,
+                        secs / 60 % 60,
+                        secs % 60,
+                        d.subsec_millis()
+                    );
+                } else {
+                    out.push_str("--:--:--.---");
+                }
+            }
+            FormatPiece::Percent => match (snap.progress, snap.expected) {
+                (Some(pct), _) => {
+                    let _ = write!(out, "{pct}%");
+                }
+                (None, Some(expected)) => {
+                    let pct = (d.as_millis() * 100 / expected.as_millis().max(1)).min(100);
+                    let _ = write!(out, "{pct}%");
+                }
+                (None, None) => out.push('-'),
+            },
+            FormatPiece::Eta => {
+                // Progress reported by the command itself takes precedence
+                // over `--expect`; the time remaining is extrapolated from
+                // the rate of progress so far.
+                let expected = match snap.progress {
+                    Some(pct) if pct > 0 => Some(d.mul_f64(100.0 / f64::from(pct))),
+                    _ => snap.expected,
+                };
+                write_countdown(out, expected.map(|expected| expected.saturating_sub(d)));
+            }
+            FormatPiece::TimeLeft => write_countdown(out, snap.time_left),
+            FormatPiece::MissedTicks => out.push_str(&format_count(snap.missed_ticks)),
         }
     }
 }
 
+/// Write the time `remaining` in the form `HH:MM:SS`, or `--:--:--` if it's
+/// unknown.  The time is rounded up so that the countdown only reaches zero
+/// once the time is actually up.
+fn write_countdown(out: &mut String, remaining: Option<Duration>) {
+    if let Some(remaining) = remaining {
+        let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        let _ = write!(
+            out,
+            "{:02}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        );
+    } else {
+        out.push_str("--:--:--");
+    }
+}
+
+/// Describe a duration in words, to whole-second precision, in a manner
+/// suitable for reading out by a screen reader, e.g., "1 hour, 2 minutes and 3
+/// seconds"
+pub(crate) fn spoken_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    if secs == 0 {
+        return String::from("less than a second");
+    }
+    let parts = [
+        (secs / 3600, "hour"),
+        ((secs / 60) % 60, "minute"),
+        (secs % 60, "second"),
+    ]
+    .into_iter()
+    .filter(|&(n, _)| n > 0)
+    .map(|(n, unit)| format!("{n} {unit}{}", if n == 1 { "" } else { "s" }))
+    .collect::<Vec<_>>();
+    let (last, rest) = parts
+        .split_last()
+        .expect("at least one part should be nonzero");
+    if rest.is_empty() {
+        last.clone()
+    } else {
+        format!("{} and {last}", rest.join(", "))
+    }
+}
+
 #[derive(Clone, Debug, Eq, Error, PartialEq)]
 pub(crate) enum ParseFormatError {
     #[error("numeric overflow while parsing %f precision")]
@@ -173,6 +401,10 @@ pub(crate) enum ParseFormatError {
     InvalidPercent(char),
     #[error("'%' not followed by anything")]
     BrokenPercent,
+    #[error("'%{{' followed by invalid specifier name {0:?}")]
+    InvalidName(String),
+    #[error("'%{{' not followed by a closing '}}'")]
+    BrokenBrace,
     #[error("backslash followed by invalid character {0:?}")]
     InvalidEscape(char),
     #[error("backslash not followed by anything")]
@@ -190,6 +422,12 @@ mod tests {
         assert_eq!(fmt, Format::default());
     }
 
+    #[test]
+    fn parse_precise() {
+        let fmt = "Elapsed: %H:%M:%S.%3f".parse::<Format>().unwrap();
+        assert_eq!(fmt, Format::precise());
+    }
+
     #[test]
     fn newlines() {
         let fmt = "Hours: %H%nMinutes: %M\\nSeconds: %S\n"
@@ -222,12 +460,319 @@ mod tests {
     )]
     fn display(#[case] spec: &str, #[case] d: Duration, #[case] out: &str) {
         let fmt = spec.parse::<Format>().unwrap();
-        assert_eq!(fmt.display(d), out);
+        let snap = Snapshot {
+            elapsed: d,
+            proc_stats: None,
+            first_output: None,
+            expected: None,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(None, "Wall: 00:03:00, CPU: --:--:--")]
+    #[case(Some(Duration::ZERO), "Wall: 00:03:00, CPU: 00:00:00")]
+    #[case(Some(Duration::from_millis(40_999)), "Wall: 00:03:00, CPU: 00:00:40")]
+    #[case(Some(Duration::from_secs(3 * 3600 + 25)), "Wall: 00:03:00, CPU: 03:00:25")]
+    fn display_cpu_time(#[case] cpu_time: Option<Duration>, #[case] out: &str) {
+        let fmt = "Wall: %H:%M:%S, CPU: %Cu".parse::<Format>().unwrap();
+        assert!(fmt.uses_proc_stats());
+        let snap = Snapshot {
+            elapsed: Duration::from_secs(180),
+            proc_stats: cpu_time.map(|cpu_time| ProcStats {
+                cpu_time,
+                ..ProcStats::default()
+            }),
+            first_output: None,
+            expected: None,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(None, "[-] 00:00:05")]
+    #[case(Some('S'), "[S] 00:00:05")]
+    #[case(Some('D'), "[D] 00:00:05")]
+    fn display_process_state(#[case] state: Option<char>, #[case] out: &str) {
+        let fmt = "[%ps] %H:%M:%S".parse::<Format>().unwrap();
+        assert!(fmt.uses_proc_stats());
+        let snap = Snapshot {
+            elapsed: Duration::from_secs(5),
+            proc_stats: state.map(|state| ProcStats {
+                state,
+                ..ProcStats::default()
+            }),
+            first_output: None,
+            expected: None,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(None, "fds=- threads=-")]
+    #[case(
+        Some(ProcStats { threads: 4, fds: None, ..ProcStats::default() }),
+        "fds=- threads=4"
+    )]
+    #[case(
+        Some(ProcStats { threads: 1, fds: Some(17), ..ProcStats::default() }),
+        "fds=17 threads=1"
+    )]
+    fn display_counts(#[case] proc_stats: Option<ProcStats>, #[case] out: &str) {
+        let fmt = "fds=%{fd} threads=%{th}".parse::<Format>().unwrap();
+        assert!(fmt.uses_proc_stats());
+        let snap = Snapshot {
+            elapsed: Duration::ZERO,
+            proc_stats,
+            first_output: None,
+            expected: None,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    /// `%fd` & `%th` keep meaning `%f` followed by "d" and a tab followed by
+    /// "h", as they did before `%{fd}` & `%{th}` were added.
+    #[rstest]
+    #[case("%fd", "500000d")]
+    #[case("%th", "\th")]
+    fn counts_keep_old_meanings(#[case] spec: &str, #[case] out: &str) {
+        let snap = Snapshot {
+            elapsed: Duration::from_millis(1500),
+            ..Snapshot::default()
+        };
+        assert_eq!(spec.parse::<Format>().unwrap().display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(None, "Peak: -")]
+    #[case(Some(0), "Peak: 0 B")]
+    #[case(Some(3 << 20), "Peak: 3.0 MiB")]
+    fn display_rss_peak(#[case] rss_peak: Option<u64>, #[case] out: &str) {
+        let fmt = "Peak: %rss-peak".parse::<Format>().unwrap();
+        assert!(fmt.uses_proc_stats());
+        let snap = Snapshot {
+            elapsed: Duration::ZERO,
+            proc_stats: rss_peak.map(|rss_peak| ProcStats {
+                rss_peak,
+                ..ProcStats::default()
+            }),
+            first_output: None,
+            expected: None,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(None, "TTFO: --:--:--.---")]
+    #[case(Some(Duration::from_millis(1234)), "TTFO: 00:00:01.234")]
+    #[case(Some(Duration::from_secs(3723)), "TTFO: 01:02:03.000")]
+    fn display_first_output(#[case] first_output: Option<Duration>, #[case] out: &str) {
+        let fmt = "TTFO: %{ttfo}".parse::<Format>().unwrap();
+        assert!(!fmt.uses_proc_stats());
+        let snap = Snapshot {
+            elapsed: Duration::from_secs(5),
+            proc_stats: None,
+            first_output,
+            expected: None,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(Duration::ZERO, None, "[-] ETA --:--:--")]
+    #[case(
+        Duration::from_secs(30),
+        Some(Duration::from_secs(120)),
+        "[25%] ETA 00:01:30"
+    )]
+    #[case(
+        Duration::from_millis(119_999),
+        Some(Duration::from_secs(120)),
+        "[99%] ETA 00:00:01"
+    )]
+    #[case(
+        Duration::from_secs(150),
+        Some(Duration::from_secs(120)),
+        "[100%] ETA 00:00:00"
+    )]
+    #[case(
+        Duration::from_secs(5),
+        Some(Duration::from_secs(7200)),
+        "[0%] ETA 01:59:55"
+    )]
+    #[case(Duration::from_secs(5), Some(Duration::ZERO), "[100%] ETA 00:00:00")]
+    fn display_progress(
+        #[case] elapsed: Duration,
+        #[case] expected: Option<Duration>,
+        #[case] out: &str,
+    ) {
+        let fmt = "[%P] ETA %{eta}".parse::<Format>().unwrap();
+        assert!(!fmt.uses_proc_stats());
+        let snap = Snapshot {
+            elapsed,
+            proc_stats: None,
+            first_output: None,
+            expected,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(Duration::from_secs(30), Some(25), None, "[25%] ETA 00:01:30")]
+    #[case(
+        Duration::from_secs(30),
+        Some(25),
+        Some(Duration::from_secs(60)),
+        "[25%] ETA 00:01:30"
+    )]
+    #[case(Duration::from_secs(30), Some(0), None, "[0%] ETA --:--:--")]
+    #[case(
+        Duration::from_secs(30),
+        Some(0),
+        Some(Duration::from_secs(60)),
+        "[0%] ETA 00:00:30"
+    )]
+    #[case(Duration::from_secs(30), Some(100), None, "[100%] ETA 00:00:00")]
+    fn display_reported_progress(
+        #[case] elapsed: Duration,
+        #[case] progress: Option<u8>,
+        #[case] expected: Option<Duration>,
+        #[case] out: &str,
+    ) {
+        let fmt = "[%P] ETA %{eta}".parse::<Format>().unwrap();
+        let snap = Snapshot {
+            elapsed,
+            expected,
+            progress,
+            ..Snapshot::default()
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case("%et", "\x1Bt")]
+    #[case("%eta", "\x1Bta")]
+    #[case("%e[1m", "\x1B[1m")]
+    #[case("\\eta", "\x1Bta")]
+    fn escape_then_e(#[case] spec: &str, #[case] out: &str) {
+        let fmt = spec.parse::<Format>().unwrap();
+        assert_eq!(fmt.display(&Snapshot::default()), out);
+    }
+
+    #[test]
+    fn display_missed() {
+        let fmt = "%H:%M:%S (%missed missed)".parse::<Format>().unwrap();
+        let snap = Snapshot {
+            elapsed: Duration::from_secs(75),
+            missed_ticks: 3,
+            ..Snapshot::default()
+        };
+        assert_eq!(fmt.display(&snap), "00:01:15 (3 missed)");
+        assert!(matches!(
+            "%miss".parse::<Format>(),
+            Err(ParseFormatError::InvalidPercent('m'))
+        ));
+    }
+
+    #[rstest]
+    #[case("%tt", "\tt")]
+    #[case("%ttf", "\ttf")]
+    #[case("%ttfo", "\ttfo")]
+    #[case("\\ttfo", "\ttfo")]
+    fn tab_then_t(#[case] spec: &str, #[case] out: &str) {
+        let fmt = spec.parse::<Format>().unwrap();
+        let snap = Snapshot {
+            elapsed: Duration::ZERO,
+            proc_stats: None,
+            first_output: None,
+            expected: None,
+            progress: None,
+            missed_ticks: 0,
+            time_left: None,
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    #[rstest]
+    #[case(None, "Left: --:--:--")]
+    #[case(Some(Duration::from_millis(90_500)), "Left: 00:01:31")]
+    #[case(Some(Duration::from_secs(3600)), "Left: 01:00:00")]
+    #[case(Some(Duration::ZERO), "Left: 00:00:00")]
+    fn display_time_left(#[case] time_left: Option<Duration>, #[case] out: &str) {
+        let fmt = "Left: %{tl}".parse::<Format>().unwrap();
+        assert!(!fmt.uses_proc_stats());
+        let snap = Snapshot {
+            elapsed: Duration::from_secs(5),
+            time_left,
+            ..Snapshot::default()
+        };
+        assert_eq!(fmt.display(&snap), out);
+    }
+
+    /// `%tl` keeps meaning a tab followed by "l", as it did before `%{tl}`
+    /// was added.
+    #[test]
+    fn tl_keeps_old_meaning() {
+        let snap = Snapshot::default();
+        assert_eq!("%tl".parse::<Format>().unwrap().display(&snap), "\tl");
+    }
+
+    #[test]
+    fn default_uses_proc_stats() {
+        assert!(!Format::default().uses_proc_stats());
+    }
+
+    #[rstest]
+    #[case(Duration::from_millis(500), "less than a second")]
+    #[case(Duration::from_secs(1), "1 second")]
+    #[case(Duration::from_millis(59_999), "59 seconds")]
+    #[case(Duration::from_secs(60), "1 minute")]
+    #[case(Duration::from_secs(300), "5 minutes")]
+    #[case(Duration::from_secs(90), "1 minute and 30 seconds")]
+    #[case(Duration::from_secs(7200), "2 hours")]
+    #[case(Duration::from_secs(3723), "1 hour, 2 minutes and 3 seconds")]
+    #[case(Duration::from_secs(3603), "1 hour and 3 seconds")]
+    #[case(Duration::from_secs(360_000), "100 hours")]
+    fn spoken(#[case] d: Duration, #[case] out: &str) {
+        assert_eq!(spoken_duration(d), out);
     }
 
     #[rstest]
     #[case("Years: %Y")]
     #[case("Years: %")]
+    #[case("CPU: %C")]
+    #[case("CPU: %Cx")]
+    #[case("State: %p")]
+    #[case("State: %pS")]
+    #[case("RSS: %r")]
+    #[case("RSS: %rss")]
+    #[case("RSS: %rss-pea")]
+    #[case("FDs: %{")]
+    #[case("FDs: %{fd")]
+    #[case("FDs: %{}")]
+    #[case("FDs: %{FD}")]
+    #[case("FDs: %{fd }")]
     #[case("Time: %s\\r")]
     #[case("Time: %s\\")]
     #[case("Time: %s.%999999999999f")]
@@ -0,0 +1,10 @@
+//! Components of [`elapsed`](https://github.com/jwodder/elapsed) that are
+//! exposed as a library so that they can be fuzzed and reused by other
+//! projects.  The `elapsed` command itself is not part of the library API.
+//!
+//! The `test-support` feature enables the `test_support` module, a harness
+//! for testing terminal programs like `elapsed` in a pseudo-terminal [Unix
+//! only].
+pub mod bytelines;
+#[cfg(all(unix, feature = "test-support"))]
+pub mod test_support;
@@ -0,0 +1,62 @@
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Locations at which coreutils' `libstdbuf.so` (the library that the `stdbuf`
+/// command injects via `LD_PRELOAD`) is installed on common Linux
+/// distributions
+#[cfg(target_os = "linux")]
+static LIBSTDBUF_PATHS: &[&str] = &[
+    "/usr/libexec/coreutils/libstdbuf.so",
+    "/usr/lib/coreutils/libstdbuf.so",
+    "/usr/lib/x86_64-linux-gnu/coreutils/libstdbuf.so",
+    "/usr/lib/aarch64-linux-gnu/coreutils/libstdbuf.so",
+    "/usr/local/libexec/coreutils/libstdbuf.so",
+];
+
+/// Settings for coaxing a child process into line-buffering its stdout when
+/// it's connected to a pipe
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct LineBuffering {
+    /// Environment variables to set for the child process
+    pub(crate) env: Vec<(OsString, OsString)>,
+
+    /// True if no environment-based mechanism for forcing line-buffering of
+    /// arbitrary programs is available, in which case the child should be run
+    /// via a pseudo-terminal instead
+    pub(crate) needs_tty: bool,
+}
+
+impl LineBuffering {
+    /// Determine the line-buffering settings to use for a child process.
+    /// `tty` is true if the child will already be run via a pseudo-terminal,
+    /// in which case the C library will line-buffer stdout on its own.
+    pub(crate) fn new(tty: bool) -> LineBuffering {
+        let mut env = vec![("PYTHONUNBUFFERED".into(), "1".into())];
+        let mut needs_tty = false;
+        if !tty {
+            if let Some(lib) = find_libstdbuf() {
+                let mut preload = OsString::new();
+                if let Some(old) = std::env::var_os("LD_PRELOAD").filter(|s| !s.is_empty()) {
+                    preload.push(old);
+                    preload.push(":");
+                }
+                preload.push(lib);
+                env.push(("LD_PRELOAD".into(), preload));
+                env.push(("_STDBUF_O".into(), "L".into()));
+            } else {
+                needs_tty = cfg!(unix);
+            }
+        }
+        LineBuffering { env, needs_tty }
+    }
+}
+
+fn find_libstdbuf() -> Option<&'static Path> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            LIBSTDBUF_PATHS.iter().map(Path::new).find(|p| p.exists())
+        } else {
+            None
+        }
+    }
+}
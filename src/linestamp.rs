@@ -0,0 +1,143 @@
+//! Prefixing each line of the command's output with a timestamp, for
+//! `--timestamps=<STYLE>`
+use crate::Stream;
+use crate::clock::{Zone, format_timestamp};
+use crate::summary::format_duration;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+/// What kind of timestamp to prefix each line of output with
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum StampStyle {
+    /// The time elapsed since the command started, e.g., "`[00:01:02.345]`"
+    Elapsed,
+    /// The wall-clock time, e.g., "`[2026-10-16T14:00:00+02:00]`", or
+    /// "`[2026-10-16T12:00:00Z]`" with `--utc`
+    WallClock,
+}
+
+impl std::str::FromStr for StampStyle {
+    type Err = ParseStampStyleError;
+
+    fn from_str(s: &str) -> Result<StampStyle, ParseStampStyleError> {
+        match s {
+            "elapsed" => Ok(StampStyle::Elapsed),
+            "wallclock" => Ok(StampStyle::WallClock),
+            _ => Err(ParseStampStyleError),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid timestamp style; expected \"elapsed\" or \"wallclock\"")]
+pub(crate) struct ParseStampStyleError;
+
+/// Prefixes records of output that start a new line with a timestamp.  A
+/// record that doesn't end with the delimiter (e.g., a prompt) leaves the
+/// next record on the same stream to continue its line, and so that record
+/// is not prefixed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct LineStamper {
+    style: StampStyle,
+    /// The time zone in which to show wall-clock timestamps
+    zone: Zone,
+    /// Whether the last record on stdout (or merged output) left off in the
+    /// middle of a line
+    stdout_mid_line: bool,
+    /// Whether the last record on stderr left off in the middle of a line
+    stderr_mid_line: bool,
+}
+
+impl LineStamper {
+    pub(crate) fn new(style: StampStyle, zone: Zone) -> LineStamper {
+        LineStamper {
+            style,
+            zone,
+            stdout_mid_line: false,
+            stderr_mid_line: false,
+        }
+    }
+
+    /// Return `record`, received on `stream` at `elapsed` into the run (wall
+    /// clock time `now`), with a timestamp prepended if it starts a new line
+    pub(crate) fn stamp(
+        &mut self,
+        stream: Stream,
+        record: Vec<u8>,
+        delimiter: u8,
+        elapsed: Duration,
+        now: SystemTime,
+    ) -> Vec<u8> {
+        if record.is_empty() {
+            return record;
+        }
+        let mid_line = match stream {
+            Stream::Stdout | Stream::Merged => &mut self.stdout_mid_line,
+            Stream::Stderr => &mut self.stderr_mid_line,
+        };
+        let continued = std::mem::replace(mid_line, record.last() != Some(&delimiter));
+        if continued {
+            return record;
+        }
+        let stamp = match self.style {
+            StampStyle::Elapsed => format_duration(elapsed),
+            StampStyle::WallClock => format_timestamp(now, self.zone),
+        };
+        let mut stamped = format!("[{stamp}] ").into_bytes();
+        stamped.extend_from_slice(&record);
+        stamped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed() {
+        let mut stamper = LineStamper::new(StampStyle::Elapsed, Zone::Utc);
+        let now = SystemTime::UNIX_EPOCH;
+        let mut stamp = |stream, record: &[u8], millis| {
+            stamper.stamp(
+                stream,
+                record.to_vec(),
+                b'\n',
+                Duration::from_millis(millis),
+                now,
+            )
+        };
+        assert_eq!(
+            stamp(Stream::Stdout, b"Starting...\n", 12),
+            b"[00:00:00.012] Starting...\n"
+        );
+        assert_eq!(
+            stamp(Stream::Stdout, b"Password: ", 1500),
+            b"[00:00:01.500] Password: "
+        );
+        assert_eq!(
+            stamp(Stream::Stderr, b"Warning!\n", 1600),
+            b"[00:00:01.600] Warning!\n"
+        );
+        assert_eq!(stamp(Stream::Stdout, b"hunter2\n", 5000), b"hunter2\n");
+        assert_eq!(
+            stamp(Stream::Stdout, b"Done\n", 62345),
+            b"[00:01:02.345] Done\n"
+        );
+    }
+
+    #[test]
+    fn wallclock() {
+        let mut stamper = LineStamper::new(StampStyle::WallClock, Zone::Utc);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_792_152_000);
+        assert_eq!(
+            stamper.stamp(
+                Stream::Merged,
+                b"foo\0".to_vec(),
+                0,
+                Duration::from_secs(3),
+                now
+            ),
+            b"[2026-10-16T12:00:00Z] foo\0"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Files of newline-terminated records written by `--line-timings` &
+//! `--slowlog`, which may be shared by multiple instances of `elapsed` when
+//! `--append` is given
+use cfg_if::cfg_if;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// How much output to buffer before writing it to the file when not appending
+const BUFFER_SIZE: usize = 8192;
+
+/// A buffered writer to a file of newline-terminated records.
+///
+/// By default, the file is truncated when opened.  In append mode, the file
+/// is instead appended to, and each complete record is written with a single
+/// `write()` call while holding an exclusive advisory lock on the file (on
+/// Unix), so that records written by other processes appending to the same
+/// file never end up interleaved with ours.
+#[derive(Debug)]
+pub(crate) struct LogFile {
+    file: File,
+    buf: Vec<u8>,
+    append: bool,
+}
+
+impl LogFile {
+    pub(crate) fn create(path: &Path, append: bool) -> io::Result<LogFile> {
+        let file = if append {
+            OpenOptions::new().create(true).append(true).open(path)?
+        } else {
+            File::create(path)?
+        };
+        Ok(LogFile {
+            file,
+            buf: Vec::new(),
+            append,
+        })
+    }
+
+    /// Write out all of the complete records in the buffer
+    fn write_records(&mut self) -> io::Result<()> {
+        if let Some(end) = self.buf.iter().rposition(|&b| b == b'\n') {
+            let records = self.buf.drain(..=end).collect::<Vec<_>>();
+            self.write_out(&records)?;
+        }
+        Ok(())
+    }
+
+    fn write_out(&self, data: &[u8]) -> io::Result<()> {
+        cfg_if! {
+            if #[cfg(unix)] {
+                if self.append {
+                    use nix::fcntl::{Flock, FlockArg};
+                    // The duplicated descriptor shares the original's open
+                    // file description and thus its lock, which is released
+                    // when `locked` is dropped.
+                    let locked = Flock::lock(self.file.try_clone()?, FlockArg::LockExclusive)
+                        .map_err(|(_, e)| io::Error::from(e))?;
+                    return (&*locked).write_all(data);
+                }
+            }
+        }
+        (&self.file).write_all(data)
+    }
+}
+
+impl Write for LogFile {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.append || self.buf.len() >= BUFFER_SIZE {
+            self.write_records()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let rest = std::mem::take(&mut self.buf);
+        if !rest.is_empty() {
+            self.write_out(&rest)?;
+        }
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("log.txt");
+        std::fs::write(&path, "Earlier\n").unwrap();
+        let mut log = LogFile::create(&path, true).unwrap();
+        write!(log, "First").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "Earlier\n");
+        writeln!(log, " record").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Earlier\nFirst record\n"
+        );
+        write!(log, "Unterminated").unwrap();
+        log.flush().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Earlier\nFirst record\nUnterminated"
+        );
+    }
+
+    #[test]
+    fn truncate() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("log.txt");
+        std::fs::write(&path, "Earlier\n").unwrap();
+        let mut log = LogFile::create(&path, false).unwrap();
+        writeln!(log, "First record").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        log.flush().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "First record\n");
+    }
+}
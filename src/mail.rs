@@ -0,0 +1,465 @@
+//! Sending an email about the finished command via sendmail or an SMTP
+//! relay, for `--mail`
+use crate::pattern::trim_eol;
+use crate::summary::format_duration;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+
+/// Where to look for sendmail if it isn't in `PATH`, which it often isn't for
+/// non-root users
+const SENDMAIL_FALLBACKS: [&str; 2] = ["/usr/sbin/sendmail", "/usr/lib/sendmail"];
+
+/// The port to connect to on an SMTP relay if none is specified
+const SMTP_PORT: u16 = 25;
+
+/// How long to give an SMTP relay to accept the message before giving up
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How a `--mail` message is delivered
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Transport {
+    /// Pipe the message to the local sendmail
+    Sendmail,
+    /// Submit the message to the SMTP relay at the given `host:port`
+    Smtp(String),
+}
+
+impl Transport {
+    /// Return a transport for submitting messages to the SMTP relay at
+    /// `relay`, given as `HOST` or `HOST:PORT` (with IPv6 addresses in
+    /// brackets)
+    pub(crate) fn smtp(relay: &str) -> Transport {
+        let has_port = relay.rsplit_once(':').is_some_and(|(host, port)| {
+            !host.is_empty()
+                && port.parse::<u16>().is_ok()
+                && (!host.contains(':') || host.starts_with('['))
+        });
+        if has_port {
+            Transport::Smtp(relay.to_owned())
+        } else {
+            Transport::Smtp(format!("{relay}:{SMTP_PORT}"))
+        }
+    }
+}
+
+/// The most recent lines of output from the command
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Tail {
+    lines: VecDeque<String>,
+    max: usize,
+}
+
+impl Tail {
+    pub(crate) fn new(max: usize) -> Tail {
+        Tail {
+            lines: VecDeque::with_capacity(max),
+            max,
+        }
+    }
+
+    pub(crate) fn push(&mut self, line: &[u8]) {
+        if self.max == 0 {
+            return;
+        }
+        if self.lines.len() == self.max {
+            self.lines.pop_front();
+        }
+        self.lines
+            .push_back(String::from_utf8_lossy(trim_eol(line)).into_owned());
+    }
+}
+
+/// An email message reporting on how the command finished
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Message {
+    pub(crate) to: String,
+    /// The command line that was run
+    pub(crate) command: String,
+    /// The ID of the run, as given to the command in `ELAPSED_RUN_ID`
+    pub(crate) run_id: String,
+    /// How the command exited, e.g., "exited with code 0"
+    pub(crate) exit_status: String,
+    pub(crate) duration: Duration,
+    pub(crate) tail: Tail,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subject = format!("{} {}", self.command, self.exit_status).replace(['\r', '\n'], " ");
+        writeln!(f, "To: {}", self.to)?;
+        writeln!(f, "Subject: {}", encode_header(&subject))?;
+        writeln!(f, "MIME-Version: 1.0")?;
+        writeln!(f, "Content-Type: text/plain; charset=utf-8")?;
+        writeln!(f, "Content-Transfer-Encoding: 8bit")?;
+        writeln!(f)?;
+        writeln!(f, "Command:     {}", self.command)?;
+        writeln!(f, "Exit status: {}", self.exit_status)?;
+        writeln!(f, "Duration:    {}", format_duration(self.duration))?;
+        writeln!(f, "Run ID:      {}", self.run_id)?;
+        writeln!(f)?;
+        match self.tail.lines.len() {
+            0 => writeln!(f, "No output was received from the command.")?,
+            1 => writeln!(f, "Last line of output:\n")?,
+            n => writeln!(f, "Last {n} lines of output:\n")?,
+        }
+        for line in &self.tail.lines {
+            // Indenting the lines keeps any that start with "From " or "."
+            // from being mangled in transit.
+            writeln!(f, "    {line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Send `msg` to its recipient via `transport`
+pub(crate) async fn send(msg: &Message, transport: &Transport) -> io::Result<()> {
+    match transport {
+        Transport::Sendmail => send_via_sendmail(msg).await,
+        Transport::Smtp(relay) => tokio::time::timeout(SMTP_TIMEOUT, send_via_smtp(msg, relay))
+            .await
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out talking to SMTP relay {relay}"),
+                )
+            })?,
+    }
+}
+
+/// Send `msg` to its recipient by piping it to sendmail
+async fn send_via_sendmail(msg: &Message) -> io::Result<()> {
+    let sendmail = find_sendmail()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "sendmail not found"))?;
+    let mut p = Command::new(sendmail)
+        .arg("-oi")
+        .arg("--")
+        .arg(&msg.to)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = p.stdin.take() {
+        stdin.write_all(msg.to_string().as_bytes()).await?;
+        // Drop `stdin` so that sendmail sees EOF.
+    }
+    let status = p.wait().await?;
+    if !status.success() {
+        return Err(io::Error::other(format!("sendmail failed: {status}")));
+    }
+    Ok(())
+}
+
+/// Send `msg` to its recipient by submitting it to the SMTP relay at
+/// `relay` (a `host:port`).  No TLS or authentication is attempted, so this
+/// is meant for relays that accept mail from the local network, such as a
+/// smarthost on `localhost`.  The recipient is also used as the sender, as
+/// the message is a note to oneself.
+async fn send_via_smtp(msg: &Message, relay: &str) -> io::Result<()> {
+    let stream = TcpStream::connect(relay).await?;
+    // Identify ourselves by our address, which doesn't depend on how (or
+    // whether) the local host name is configured.
+    let helo = match stream.local_addr()? {
+        std::net::SocketAddr::V4(addr) => format!("[{}]", addr.ip()),
+        std::net::SocketAddr::V6(addr) => format!("[IPv6:{}]", addr.ip()),
+    };
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    expect_reply(&mut reader, "greeting", &[220]).await?;
+    let mut body = format!("From: {}\r\n", msg.to);
+    for line in msg.to_string().lines() {
+        // Lines starting with a period must have it doubled so that they
+        // aren't mistaken for the end of the message.
+        if line.starts_with('.') {
+            body.push('.');
+        }
+        body.push_str(line);
+        body.push_str("\r\n");
+    }
+    body.push_str(".\r\n");
+    let steps = [
+        ("EHLO", format!("EHLO {helo}\r\n"), &[250][..]),
+        ("MAIL FROM", format!("MAIL FROM:<{}>\r\n", msg.to), &[250]),
+        ("RCPT TO", format!("RCPT TO:<{}>\r\n", msg.to), &[250, 251]),
+        ("DATA", String::from("DATA\r\n"), &[354]),
+        ("message", body, &[250]),
+        ("QUIT", String::from("QUIT\r\n"), &[221]),
+    ];
+    for (what, command, ok) in steps {
+        writer.write_all(command.as_bytes()).await?;
+        writer.flush().await?;
+        expect_reply(&mut reader, what, ok).await?;
+    }
+    Ok(())
+}
+
+/// Read a (possibly multiline) reply from an SMTP server and return an error
+/// if its code is not one of `ok`.  `what` describes what the reply is to,
+/// for use in the error message.
+async fn expect_reply<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    what: &str,
+    ok: &[u16],
+) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("SMTP relay closed the connection before replying to {what}"),
+            ));
+        }
+        // The last line of a reply has a space (or nothing) after the code
+        // instead of a hyphen.
+        if line.as_bytes().get(3) != Some(&b'-') {
+            break;
+        }
+    }
+    let line = line.trim_end();
+    match line.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+        Some(code) if ok.contains(&code) => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "SMTP relay rejected {what}: {line}"
+        ))),
+    }
+}
+
+/// Find the sendmail executable, first in `PATH` and then in the usual
+/// locations outside of it
+fn find_sendmail() -> Option<PathBuf> {
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join("sendmail"))
+        .chain(SENDMAIL_FALLBACKS.into_iter().map(PathBuf::from))
+        .find(|p| p.is_file())
+}
+
+/// Encode a header value as an RFC 2047 "encoded-word" if it contains any
+/// non-ASCII characters
+fn encode_header(s: &str) -> String {
+    if s.is_ascii() {
+        s.to_owned()
+    } else {
+        format!("=?UTF-8?B?{}?=", base64(s.as_bytes()))
+    }
+}
+
+/// Encode `data` in standard base64 with padding
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or_default(),
+            chunk.get(2).copied().unwrap_or_default(),
+        ];
+        let n = (usize::from(b[0]) << 16) | (usize::from(b[1]) << 8) | usize::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(char::from(ALPHABET[(n >> (18 - 6 * i)) & 0x3F]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn tail() {
+        let mut tail = Tail::new(2);
+        tail.push(b"one\n");
+        tail.push(b"two\r\n");
+        tail.push(b"three");
+        assert_eq!(tail.lines, ["two", "three"]);
+    }
+
+    #[test]
+    fn tail_zero() {
+        let mut tail = Tail::new(0);
+        tail.push(b"one\n");
+        assert!(tail.lines.is_empty());
+    }
+
+    #[test]
+    fn message() {
+        let mut tail = Tail::new(10);
+        tail.push(b"Compiling foo\n");
+        tail.push(b"From here on\n");
+        let msg = Message {
+            to: String::from("me@example.com"),
+            command: String::from("make -j4"),
+            run_id: String::from("5f0c3a9e7d1b2468"),
+            exit_status: String::from("exited with code 2"),
+            duration: Duration::from_millis(83_456),
+            tail,
+        };
+        assert_eq!(
+            msg.to_string(),
+            concat!(
+                "To: me@example.com\n",
+                "Subject: make -j4 exited with code 2\n",
+                "MIME-Version: 1.0\n",
+                "Content-Type: text/plain; charset=utf-8\n",
+                "Content-Transfer-Encoding: 8bit\n",
+                "\n",
+                "Command:     make -j4\n",
+                "Exit status: exited with code 2\n",
+                "Duration:    00:01:23.456\n",
+                "Run ID:      5f0c3a9e7d1b2468\n",
+                "\n",
+                "Last 2 lines of output:\n",
+                "\n",
+                "    Compiling foo\n",
+                "    From here on\n",
+            )
+        );
+    }
+
+    #[test]
+    fn message_no_output() {
+        let msg = Message {
+            to: String::from("me@example.com"),
+            command: String::from("true"),
+            run_id: String::from("0123456789abcdef"),
+            exit_status: String::from("exited with code 0"),
+            duration: Duration::from_millis(5),
+            tail: Tail::new(10),
+        };
+        assert!(
+            msg.to_string()
+                .ends_with("\nNo output was received from the command.\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn smtp() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = listener.local_addr().unwrap().to_string();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut received = Vec::new();
+            writer
+                .write_all(b"220 relay.example.com ESMTP\r\n")
+                .await
+                .unwrap();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply: &[u8] = if in_data {
+                    if line == "." {
+                        in_data = false;
+                        b"250 Queued\r\n"
+                    } else {
+                        b""
+                    }
+                } else if line.starts_with("EHLO ") {
+                    b"250-relay.example.com\r\n250 8BITMIME\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 Go ahead\r\n"
+                } else if line == "QUIT" {
+                    b"221 Bye\r\n"
+                } else {
+                    b"250 OK\r\n"
+                };
+                received.push(line);
+                writer.write_all(reply).await.unwrap();
+            }
+            received
+        });
+        let mut tail = Tail::new(10);
+        tail.push(b"Done\n");
+        let msg = Message {
+            to: String::from("me@example.com"),
+            command: String::from("make"),
+            run_id: String::from("5f0c3a9e7d1b2468"),
+            exit_status: String::from("exited with code 0"),
+            duration: Duration::from_secs(1),
+            tail,
+        };
+        send(&msg, &Transport::smtp(&relay)).await.unwrap();
+        let received = server.await.unwrap();
+        assert_eq!(received[0], "EHLO [127.0.0.1]");
+        assert_eq!(
+            received[1..4],
+            [
+                "MAIL FROM:<me@example.com>",
+                "RCPT TO:<me@example.com>",
+                "DATA"
+            ]
+        );
+        assert_eq!(received[4], "From: me@example.com");
+        assert_eq!(received[5], "To: me@example.com");
+        assert!(received.contains(&String::from("    Done")));
+        assert_eq!(received[received.len() - 2..], [".", "QUIT"]);
+    }
+
+    #[tokio::test]
+    async fn smtp_rejected() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer
+                .write_all(b"220 relay.example.com ESMTP\r\n")
+                .await
+                .unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply: &[u8] = if line.starts_with("RCPT ") {
+                    b"550 5.7.1 Relaying denied\r\n"
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+        let msg = Message {
+            to: String::from("me@example.com"),
+            command: String::from("true"),
+            run_id: String::from("0123456789abcdef"),
+            exit_status: String::from("exited with code 0"),
+            duration: Duration::from_millis(5),
+            tail: Tail::new(10),
+        };
+        let e = send(&msg, &Transport::smtp(&relay)).await.unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "SMTP relay rejected RCPT TO: 550 5.7.1 Relaying denied"
+        );
+    }
+
+    #[rstest]
+    #[case("", "")]
+    #[case("f", "Zg==")]
+    #[case("fo", "Zm8=")]
+    #[case("foo", "Zm9v")]
+    #[case("foob", "Zm9vYg==")]
+    #[case("fooba", "Zm9vYmE=")]
+    #[case("foobar", "Zm9vYmFy")]
+    fn base64_encode(#[case] data: &str, #[case] encoded: &str) {
+        assert_eq!(base64(data.as_bytes()), encoded);
+    }
+
+    #[test]
+    fn encode_non_ascii_header() {
+        assert_eq!(encode_header("café"), "=?UTF-8?B?Y2Fmw6k=?=");
+        assert_eq!(encode_header("cafe"), "cafe");
+    }
+}
@@ -1,46 +1,819 @@
+mod caffeinate;
+mod clock;
+mod debug;
+mod doctor;
+mod energy;
+mod flood;
 mod format;
-use crate::format::Format;
+mod linebuf;
+mod linestamp;
+mod logfile;
+mod mail;
+mod man;
+mod passthrough;
+mod pattern;
+mod periodic;
+mod porcelain;
+mod procinfo;
+mod progress;
+mod redact;
+mod runbook;
+mod runid;
+mod sdnotify;
+mod shellwords;
+mod sink;
+mod slowlog;
+mod stdin;
+mod stopwatch;
+mod summary;
+mod suspend;
+mod term;
+#[cfg(unix)]
+mod termquery;
+#[cfg(unix)]
+mod termseq;
+mod ticker;
+mod timings;
+mod tmux;
+mod units;
+mod watch;
+use crate::caffeinate::SleepInhibitor;
+use crate::clock::{WallClock, Zone};
+use crate::debug::DEFAULT_DEBUG_FILE;
+use crate::energy::EnergyMeter;
+use crate::flood::FloodDetector;
+use crate::format::{Format, Snapshot, spoken_duration};
+use crate::linebuf::LineBuffering;
+use crate::linestamp::{LineStamper, StampStyle};
+use crate::passthrough::PassthroughCheck;
+use crate::pattern::Pattern;
+use crate::periodic::{Periodic, PeriodicHook};
+use crate::porcelain::{Porcelain, PorcelainStyle, PorcelainTarget};
+use crate::procinfo::{ChildUsage, ProcStats, StuckDetector, WaitReasons};
+use crate::progress::Report;
+use crate::redact::Redactor;
+use crate::runid::run_id;
+use crate::sdnotify::SystemdNotifier;
+use crate::sink::{Line, Overflow, Queueing, Sinks, Tee};
+use crate::slowlog::SlowLog;
+use crate::stopwatch::{Preset, Stopwatch};
+use crate::summary::{LineGaps, Summary, format_duration};
+use crate::suspend::{Change, PausableClock, SuspendWatcher};
+#[cfg(unix)]
+use crate::termquery::QueryResponder;
+#[cfg(unix)]
+use crate::termseq::TermSequence;
+use crate::ticker::Ticker;
+use crate::timings::LineTimings;
+use crate::tmux::Tmux;
+use crate::units::{NumberStyle, format_bytes, format_count, parse_duration};
+use crate::watch::{DiskWatcher, MinFree, PathWatcher};
 use cfg_if::cfg_if;
+use elapsed_cmd::bytelines::ByteLines;
 use lexopt::{Arg, Parser, ValueExt};
+use serde::Serialize;
 use std::borrow::Cow;
-use std::ffi::OsString;
-use std::future::Future;
+use std::cell::Cell;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+use std::fs::File;
 use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
 use std::pin::{Pin, pin};
 use std::process::{ExitCode, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use std::task::{Context, Poll, ready};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 use tokio::{
     io::{AsyncRead, ReadBuf},
     process::{Child, ChildStderr, ChildStdout, Command},
-    time::{Interval, interval},
+    time::{Interval, interval, sleep_until},
 };
+use tracing::debug;
 
-const READ_BUFFER_SIZE: usize = 2048;
+/// How long to keep reading the child process's output after it exits before
+/// giving up on reaching EOF (which can happen if the child left behind a
+/// background process that inherited its stdout or stderr)
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long to wait for the command to exit after passing on a Ctrl-C before
+/// stopping it with the `--term-sequence`
+const INTERRUPT_GRACE: Duration = Duration::from_secs(2);
+
+/// How often to ask systemd to extend the service's timeout when running
+/// under a service manager
+const SYSTEMD_EXTEND_PERIOD: Duration = Duration::from_secs(10);
+
+/// How far to extend the service's timeout by each time
+const SYSTEMD_EXTEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The environment variable that the command is run with set to the process
+/// ID of `elapsed`, so that any instance of `elapsed` that the command runs in
+/// turn can tell that it's nested inside another
+const NESTING_ENV_VAR: &str = "ELAPSED_PID";
+
+/// The environment variable giving how many instances of `elapsed` the
+/// current process is nested inside of.  `elapsed` runs the command with this
+/// set to one more than its own value.
+const DEPTH_ENV_VAR: &str = "ELAPSED_DEPTH";
+
+/// The environment variable that the command is run with set to the ID of the
+/// current run, so that its own records can be correlated with `elapsed`'s
+const RUN_ID_ENV_VAR: &str = "ELAPSED_RUN_ID";
+
+/// The return code to exit with after being cancelled by `SIGTERM`, following
+/// the shell convention of 128 plus the signal number
+const CANCELLED_EXIT_CODE: u8 = 128 + 15;
+
+/// The return code to exit with after stopping the command for exceeding
+/// `--timeout` (or when a `--runbook` step times out), following the
+/// convention of timeout(1)
+const TIMED_OUT_EXIT_CODE: u8 = 124;
+
+/// The default interval between status line updates
+const DEFAULT_REFRESH_PERIOD: Duration = Duration::from_secs(1);
+
+/// The default interval between status line updates under `--precise`
+const PRECISE_REFRESH_PERIOD: Duration = Duration::from_millis(50);
+
+/// The default number of lines of output to include in a `--mail` message
+const DEFAULT_MAIL_LINES: usize = 10;
+
+/// The default minimum gap before a line for `--slowlog` to record it
+const DEFAULT_SLOWLOG_THRESHOLD: Duration = Duration::from_secs(10);
+
+/// The default number of lines & events that may wait in each sink's queue
+/// when `--sink-overflow=drop` is given without `--sink-queue`
+const DEFAULT_SINK_QUEUE: usize = 1024;
+
+/// The default interval between announcements in `--a11y` mode
+const DEFAULT_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The default length of time that the command must go without output while
+/// using CPU before `--auto-tty` restarts it
+const DEFAULT_AUTO_TTY_WINDOW: Duration = Duration::from_secs(5);
+
+const HELP: &str = concat!(
+    "Usage: elapsed [<options>] <command> [<arg> ...]\n",
+    "\n",
+    "Show runtime while a command runs\n",
+    "\n",
+    "Visit <https://github.com/jwodder/elapsed> for more information.\n",
+    "\n",
+    "Options:\n",
+    "  -f <TEMPLATE>, --format <TEMPLATE>\n",
+    "                    Set the format of the status line\n",
+    "\n",
+    "                    Supported escapes:\n",
+    "                    - %H - hours\n",
+    "                    - %M - minutes\n",
+    "                    - %S - seconds in minute\n",
+    "                    - %s - total seconds\n",
+    "                    - %f - subseconds; can take a decimal precision\n",
+    "                    - %Cu - CPU time used by the command & its\n",
+    "                      descendants (Linux only)\n",
+    "                    - %ps - state of the command's process, e.g., R for\n",
+    "                      running or S for sleeping (Linux only)\n",
+    "                    - %{fd} - number of open file descriptors in the\n",
+    "                      command's process (Linux only)\n",
+    "                    - %{th} - number of threads in the command's process\n",
+    "                      (Linux only)\n",
+    "                    - %rss-peak - largest total RSS of the command & its\n",
+    "                      descendants seen so far (Linux only)\n",
+    "                    - %{ttfo} - time from the start of the command until its\n",
+    "                      first output\n",
+    "                    - %P - percentage done, as last reported by the\n",
+    "                      command, or else of the --expect time elapsed\n",
+    "                    - %{eta} - estimated time remaining, from the\n",
+    "                      command's reported progress or else the --expect\n",
+    "                      time\n",
+    "                    - %{tl} - time left until the command is stopped by\n",
+    "                      --timeout\n",
+    "                    - %missed - number of refreshes delayed because elapsed\n",
+    "                      was busy handling output\n",
+    "                    - %n or \\n - newline\n",
+    "                    - %t or \\t - tab\n",
+    "                    - %e or \\e - escape character\n",
+    "                    - %% - percent sign\n",
+    "                    - \\\\ - backslash\n",
+    "\n",
+    "  --a11y[=<SECONDS>]\n",
+    "                    Instead of redrawing the status line in place, print a\n",
+    "                    plain \"Elapsed: 5 minutes\" line every <SECONDS>\n",
+    "                    seconds and a summary sentence at the end, for use\n",
+    "                    with screen readers  [default: 60]\n",
+    "\n",
+    "  --append          Append to the --line-timings and --slowlog files instead\n",
+    "                    of overwriting them, locking each file while writing\n",
+    "                    each record so that parallel runs can share it\n",
+    "\n",
+    "  --ascii           Erase the status line by backspacing over it instead of\n",
+    "                    with escape sequences, for dumb terminals; implied if\n",
+    "                    $TERM is \"dumb\"\n",
+    "\n",
+    "  --auto-hide-at <RATE>\n",
+    "                    Stop showing the status line while the command is\n",
+    "                    producing more than <RATE> lines of output per second\n",
+    "\n",
+    "  --auto-tty[=<SECONDS>]\n",
+    "                    If the command produces no output in its first <SECONDS>\n",
+    "                    seconds while using CPU, restart it via a\n",
+    "                    pseudo-terminal in case it's buffering its output\n",
+    "                    [default: 5] [Unix only]\n",
+    "\n",
+    "  --banner          Print a line to stderr with the command & its start time\n",
+    "                    before running it, and another with its exit status &\n",
+    "                    duration after it finishes\n",
+    "\n",
+    "  --caffeinate      Prevent the system from going to sleep while the command\n",
+    "                    is running [Unix only]\n",
+    "\n",
+    "  --color-stderr[=<WHEN>]\n",
+    "                    Whether to color the command's stderr red.  <WHEN> can be\n",
+    "                    \"auto\" (color if elapsed's stderr is a terminal),\n",
+    "                    \"always\", or \"never\".  [default: auto; \"always\" if\n",
+    "                    given without a value]\n",
+    "\n",
+    "  --decimal-sep <SEP>\n",
+    "                    Use <SEP> as the decimal separator in byte counts and\n",
+    "                    other fractional numbers  [default: \".\"]\n",
+    "\n",
+    "  --debug[=<FILE>]  Write a log of elapsed's internal events (reads, redraws,\n",
+    "                    exit handling, etc.) to <FILE> for troubleshooting\n",
+    "                    [default: elapsed-debug.log]\n",
+    "\n",
+    "  --delimiter <DELIM>\n",
+    "                    Split the command's output into records ending with\n",
+    "                    <DELIM> instead of lines.  <DELIM> can be \"nul\",\n",
+    "                    \"newline\", a single ASCII character, or a byte written\n",
+    "                    as 0xHH.  [default: newline]\n",
+    "\n",
+    "  --energy          Include the energy consumed by the CPU during the run (as\n",
+    "                    measured by RAPL counters) in the --summary table [Linux\n",
+    "                    only]\n",
+    "\n",
+    "  --exclude-suspended\n",
+    "                    Stop the clock while the command is suspended (e.g., by\n",
+    "                    Ctrl-Z or SIGSTOP), leaving that time out of the status\n",
+    "                    line & the total\n",
+    "\n",
+    "  --exit-map <FROM>=<TO>\n",
+    "                    If the command exits with return code <FROM>, exit with\n",
+    "                    return code <TO> instead.  Can be specified multiple\n",
+    "                    times.\n",
+    "\n",
+    "  --expect <DURATION>\n",
+    "                    Expect the command to run for about <DURATION> (e.g.,\n",
+    "                    \"90\", \"1.5s\", or \"30m\"), for showing progress with\n",
+    "                    %P and %{eta}\n",
+    "\n",
+    "  --fail-on-stderr[=<CODE>]\n",
+    "                    If the command exits successfully but wrote anything to\n",
+    "                    stderr, exit with return code <CODE> [default: 1]\n",
+    "\n",
+    "  --failure-regex <REGEX>\n",
+    "                    Exit with return code 1 if any line of the command's\n",
+    "                    output matches <REGEX>\n",
+    "\n",
+    "  -G, --gather-stderr\n",
+    "                    Hold back the command's stderr until it exits instead of\n",
+    "                    printing it as it's received\n",
+    "\n",
+    "  --json[=<FD>]     Like --porcelain, but write the records as JSON objects,\n",
+    "                    including the text of each line of output\n",
+    "\n",
+    "  --kill-on-max-descendants\n",
+    "                    Kill the command and all of its descendants instead of\n",
+    "                    just warning when --max-descendants is exceeded\n",
+    "\n",
+    "  -L, --line-buffer Try to make the command line-buffer its stdout, running\n",
+    "                    it via a pseudo-terminal if there is no better way\n",
+    "\n",
+    "  --line-timings <FILE>\n",
+    "                    Write a JSON Lines record for each line of output from\n",
+    "                    the command to <FILE>\n",
+    "\n",
+    "  --mail <ADDR>     When the command finishes, email a report on it (including\n",
+    "                    the last lines of output) to <ADDR> via sendmail (or the\n",
+    "                    --mail-smtp relay)\n",
+    "\n",
+    "  --mail-lines <INT>\n",
+    "                    Include the last <INT> lines of output in the --mail\n",
+    "                    message [default: 10]\n",
+    "\n",
+    "  --mail-smtp <HOST[:PORT]>\n",
+    "                    Send the --mail message via the SMTP relay at <HOST>\n",
+    "                    instead of sendmail [default port: 25]\n",
+    "\n",
+    "  --max-descendants <INT>\n",
+    "                    Print a warning if the command has more than <INT>\n",
+    "                    descendant processes at once, e.g., due to a fork bomb\n",
+    "                    [Linux only]\n",
+    "\n",
+    "  --merge           Send the command's stdout & stderr to the same pipe so that\n",
+    "                    their relative order is preserved without using a\n",
+    "                    pseudo-terminal; all output is then written to stdout\n",
+    "                    [Unix only]\n",
+    "\n",
+    "  --min-free <SIZE>\n",
+    "                    Warn when a filesystem given with --watch-disk has less\n",
+    "                    than <SIZE> free; <SIZE> is a number of bytes with an\n",
+    "                    optional unit (e.g., \"500M\" or \"2G\") or a percentage\n",
+    "                    of the filesystem's size (e.g., \"10%\")  [default: 5%]\n",
+    "\n",
+    "  --no-stdin        Connect the command's stdin to /dev/null so that it can't\n",
+    "                    wait for input from the terminal\n",
+    "\n",
+    "  --notify          When the command finishes, send a desktop notification\n",
+    "                    via the terminal (OSC 9, or OSC 99 on kitty)\n",
+    "\n",
+    "  --on-low-disk <COMMAND>\n",
+    "                    Run <COMMAND> via the shell whenever a --watch-disk\n",
+    "                    filesystem drops below the --min-free threshold\n",
+    "\n",
+    "  --osc133          When stdout is a terminal, mark the start & end of the\n",
+    "                    command's output with OSC 133 escape sequences so that\n",
+    "                    the terminal can treat it as a separate command\n",
+    "\n",
+    "  --periodic <INTERVAL>:<COMMAND>\n",
+    "                    Run <COMMAND> via the shell every <INTERVAL> (e.g.,\n",
+    "                    \"30s\") while the command runs\n",
+    "\n",
+    "  --pipe-stdin      Give the command a pipe for its stdin and copy elapsed's\n",
+    "                    stdin into it, rather than letting it inherit elapsed's\n",
+    "                    stdin directly\n",
+    "\n",
+    "  --plain           Don't mark the --total line with a check or cross (and\n",
+    "                    the reason for failure) according to how the command\n",
+    "                    finished\n",
+    "\n",
+    "  --porcelain[=<FD>]\n",
+    "                    Instead of showing a status line, write machine-readable\n",
+    "                    records of the command's progress to stderr or to the\n",
+    "                    inherited file descriptor <FD> [<FD> is Unix only]\n",
+    "\n",
+    "  --precise         Show milliseconds in the status line (and --total line)\n",
+    "                    by default, and refresh it every 50 milliseconds unless\n",
+    "                    --refresh is given\n",
+    "\n",
+    "  --preset <TOOL>   Time the phases of a run of a common build or test tool\n",
+    "                    (\"cargo\", \"make\", \"pytest\", or \"npm\") using\n",
+    "                    built-in stopwatches (see --stopwatch-regex).  Can be\n",
+    "                    specified multiple times.\n",
+    "\n",
+    "  --pty-size <COLS>x<ROWS>\n",
+    "                    Give the pseudo-terminal used by --tty (or otherwise) a\n",
+    "                    fixed size instead of that of elapsed's terminal, e.g.,\n",
+    "                    \"120x40\" [Unix only]\n",
+    "\n",
+    "  --pty-term <TERM> Set $TERM to <TERM> for a command run via a\n",
+    "                    pseudo-terminal [default: inherited] [Unix only]\n",
+    "\n",
+    "  --quiet-errors    Don't print a message when the command is killed by a\n",
+    "                    signal; elapsed still exits with return code 1\n",
+    "\n",
+    "  --redact <REGEX>\n",
+    "                    Replace all matches of <REGEX> in the command's output\n",
+    "                    with \"***\" before it's used by any other option.  The\n",
+    "                    output relayed to the terminal is left as-is unless\n",
+    "                    --redact-relay is given.  Can be specified multiple\n",
+    "                    times.\n",
+    "\n",
+    "  --redact-relay    Also apply --redact and --redact-secrets to the output\n",
+    "                    relayed to the terminal\n",
+    "\n",
+    "  --redact-secrets  Replace common kinds of secrets (API tokens, passwords,\n",
+    "                    etc.) in the command's output with \"***\"\n",
+    "\n",
+    "  -r <INT>, --refresh <INT>\n",
+    "                    Update the status line after every <INT> milliseconds\n",
+    "                    [default: 1000 (once per second), or 50 with --precise]\n",
+    "\n",
+    "  --setup <COMMAND> Run <COMMAND> via the shell before starting the command;\n",
+    "                    it isn't timed, and if it fails, elapsed exits\n",
+    "                    without running the command\n",
+    "\n",
+    "  --sink-overflow <POLICY>\n",
+    "                    When a --sink-queue is full, either wait for room in it\n",
+    "                    (\"block\", the default) or drop the line & note the\n",
+    "                    gap (\"drop\") [default queue size with \"drop\": 1024]\n",
+    "\n",
+    "  --sink-queue <INT>\n",
+    "                    Write the --line-timings, --slowlog, --tee-fd, and\n",
+    "                    --porcelain/--json output from threads of their own,\n",
+    "                    each fed through a queue of up to <INT> lines\n",
+    "\n",
+    "  --slowlog <FILE>  Write each line of output from the command that arrived\n",
+    "                    after a long stretch without output (see\n",
+    "                    --slowlog-threshold) to <FILE>, along with when it\n",
+    "                    arrived and how long the stretch was\n",
+    "\n",
+    "  --slowlog-threshold <DURATION>\n",
+    "                    Set the minimum stretch without output before a line\n",
+    "                    for --slowlog to record it (e.g., \"90\", \"1.5s\", or\n",
+    "                    \"30m\") [default: 10s]\n",
+    "\n",
+    "  --stopwatch-regex <NAME>:<START_RE>:<STOP_RE>\n",
+    "                    Time the stretches of output running from a line\n",
+    "                    matching <START_RE> to a line matching <STOP_RE>, and\n",
+    "                    report their total as <NAME> at exit.  Can be specified\n",
+    "                    multiple times.\n",
+    "\n",
+    "  --strip-progress  Remove the progress reports (OSC 9;4 escape sequences)\n",
+    "                    that the command emits from its output instead of\n",
+    "                    passing them through\n",
+    "\n",
+    "  --success-regex <REGEX>\n",
+    "                    Exit with return code 0 if any line of the command's\n",
+    "                    output matches <REGEX> (and no line matches any\n",
+    "                    --failure-regex), or 1 otherwise\n",
+    "\n",
+    "  --summary         After the command exits, print a table of statistics about\n",
+    "                    the run: duration, exit status, CPU time & memory usage,\n",
+    "                    amount of output, longest stretch without output, and\n",
+    "                    statistics on the gaps between lines of output\n",
+    "\n",
+    "  --systemd-extend[=<REGEX>]\n",
+    "                    When run as a systemd service, keep extending the\n",
+    "                    service's start timeout until the command has started\n",
+    "                    up (i.e., until a line of its output matches <REGEX>, or\n",
+    "                    until it exits successfully if no <REGEX> is given),\n",
+    "                    then notify systemd that the service is ready\n",
+    "\n",
+    "  --teardown <COMMAND>\n",
+    "                    Run <COMMAND> via the shell after the command has\n",
+    "                    exited; it isn't timed\n",
+    "\n",
+    "  --tee-fd <FD>     Also write all of the command's output to the inherited\n",
+    "                    file descriptor <FD> [Unix only]\n",
+    "\n",
+    "  --term-sequence <SEQ>\n",
+    "                    Signals to send to stop the command on SIGTERM or\n",
+    "                    Ctrl-C, with waits between them, e.g.,\n",
+    "                    \"INT:5s,TERM:10s,KILL\"\n",
+    "                    [default: TERM:2s,KILL] [Unix only]\n",
+    "\n",
+    "  --thousands-sep <SEP>\n",
+    "                    Separate groups of three digits in %s, byte counts, and\n",
+    "                    line counts with <SEP>, e.g., \",\" or \"'\"\n",
+    "\n",
+    "  --timeout <DURATION>\n",
+    "                    Stop the command (as with SIGTERM; see --term-sequence)\n",
+    "                    if it's still running after <DURATION> (e.g., \"90\",\n",
+    "                    \"1.5s\", or \"30m\"), and exit with code 124\n",
+    "\n",
+    "  --timestamps[=<STYLE>]\n",
+    "                    Include the wall-clock start & end times in the --total\n",
+    "                    line and --a11y summary.  If <STYLE> is given, also\n",
+    "                    prefix each line of the command's output with the time\n",
+    "                    elapsed (\"elapsed\") or the wall-clock time\n",
+    "                    (\"wallclock\") at which it was received.\n",
+    "\n",
+    "  --tmux            When running inside tmux, show the status line in the\n",
+    "                    pane's title and display a message when the command\n",
+    "                    finishes\n",
+    "\n",
+    "  -t, --total       Leave total elapsed time behind after command finishes,\n",
+    "                    marked with whether it succeeded (see --plain)\n",
+    "\n",
+    "  -T, --tty         Run command via a pseudo-terminal [Unix only]\n",
+    "\n",
+    "  -S, --split-stderr\n",
+    "                    When used with --tty, send the command's stderr directly to\n",
+    "                    elapsed's stderr instead of unifying with stdout via the\n",
+    "                    pseudo-terminal [Unix only]\n",
+    "\n",
+    "  --user-var[=<NAME>]\n",
+    "                    When stderr is a terminal, publish the status line text\n",
+    "                    as the terminal user variable <NAME> on every refresh\n",
+    "                    using OSC 1337 (supported by iTerm2 and WezTerm)\n",
+    "                    [default: elapsed]\n",
+    "\n",
+    "  --utc             Show wall-clock times (for --banner, --summary, and\n",
+    "                    --timestamps) in UTC instead of local time\n",
+    "\n",
+    "  --verify-passthrough\n",
+    "                    Checksum the command's output as it's read and as it's\n",
+    "                    written back out, and warn if the two ever differ\n",
+    "\n",
+    "  --wait-reasons    Sample what the command's process is waiting on at every\n",
+    "                    refresh and include the most common wait reasons in the\n",
+    "                    --summary table [Linux only]\n",
+    "\n",
+    "  --warn-fds <INT>  Print a warning if the command's process has more than\n",
+    "                    <INT> open file descriptors [Linux only]\n",
+    "\n",
+    "  --watch-disk <PATH>\n",
+    "                    Show the free space on the filesystem containing <PATH>\n",
+    "                    in the status line, and warn when it drops below the\n",
+    "                    --min-free threshold [Unix only].  Can be specified\n",
+    "                    multiple times.\n",
+    "\n",
+    "  --watch-path <PATH>\n",
+    "                    Show the size & growth rate of the given file or\n",
+    "                    directory tree in the status line.  Can be specified\n",
+    "                    multiple times.\n",
+    "\n",
+    "  --runbook <FILE>  Instead of running a single command, run the steps defined\n",
+    "                    in the TOML file <FILE>, each under its own elapsed, and\n",
+    "                    print a table of how long each one took\n",
+    "\n",
+    "  --doctor          Report on the terminal environment and which terminal\n",
+    "                    features elapsed will use, and exit\n",
+    "\n",
+    "  --man             Output a man page in roff format and exit\n",
+    "\n",
+    "  -h, --help        Display this help message and exit\n",
+    "  -V, --version     Show the program version and exit\n",
+);
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum Arguments {
-    Run(Elapsed),
+    Run(Box<Elapsed>),
+    Runbook(PathBuf),
+    Doctor,
     Help,
+    Man,
     Version,
 }
 
 impl Arguments {
+    /// Parse the program's command-line arguments, preceded by any default
+    /// options set in the `ELAPSED_OPTS` environment variable
+    fn from_env() -> Result<Arguments, lexopt::Error> {
+        let defaults = match std::env::var("ELAPSED_OPTS") {
+            Ok(s) => {
+                shellwords::split(&s).map_err(|e| format!("invalid ELAPSED_OPTS value: {e}"))?
+            }
+            Err(std::env::VarError::NotPresent) => Vec::new(),
+            Err(std::env::VarError::NotUnicode(_)) => {
+                return Err("ELAPSED_OPTS value is not valid UTF-8".into());
+            }
+        };
+        let mut args = std::env::args_os();
+        let bin = args.next().unwrap_or_default();
+        Arguments::from_parser(Parser::from_iter(
+            std::iter::once(bin)
+                .chain(defaults.into_iter().map(OsString::from))
+                .chain(args),
+        ))
+    }
+
     fn from_parser(mut parser: Parser) -> Result<Arguments, lexopt::Error> {
         let mut format: Option<Format> = None;
-        let mut refresh_period = Duration::from_secs(1);
+        let mut refresh_period: Option<Duration> = None;
+        let mut precise = false;
+        let mut pipe_stdin = false;
+        let mut no_stdin = false;
+        let mut banner = false;
+        let mut porcelain: Option<PorcelainTarget> = None;
+        let mut porcelain_style = PorcelainStyle::default();
         let mut total = false;
+        let mut plain = false;
+        let mut gather_stderr = false;
+        let mut color_stderr = ColorWhen::Auto;
+        let mut fail_on_stderr: Option<u8> = None;
+        let mut exit_map = Vec::new();
+        let mut success_regex: Option<Pattern> = None;
+        let mut failure_regex: Option<Pattern> = None;
+        let mut redact = Vec::new();
+        let mut redact_secrets = false;
+        let mut redact_relay = false;
+        let mut stopwatches = Vec::new();
+        let mut caffeinate = false;
+        let mut line_timings: Option<PathBuf> = None;
+        let mut slowlog: Option<PathBuf> = None;
+        let mut append = false;
+        let mut mail: Option<String> = None;
+        let mut mail_lines = DEFAULT_MAIL_LINES;
+        let mut mail_transport = mail::Transport::Sendmail;
+        let mut slowlog_threshold: Option<Duration> = None;
+        let mut sink_queue: Option<usize> = None;
+        let mut sink_overflow = Overflow::default();
+        let mut expect: Option<Duration> = None;
+        let mut exclude_suspended = false;
+        let mut timeout: Option<Duration> = None;
+        let mut auto_hide_at: Option<u64> = None;
+        let mut line_buffer = false;
+        let mut warn_fds: Option<usize> = None;
+        let mut max_descendants: Option<usize> = None;
+        let mut kill_on_max_descendants = false;
+        let mut watch_paths = Vec::new();
+        let mut watch_disks = Vec::new();
+        let mut min_free = MinFree::default();
+        let mut on_low_disk: Option<OsString> = None;
+        let mut periodic: Option<Periodic> = None;
+        let mut setup: Option<OsString> = None;
+        let mut teardown: Option<OsString> = None;
+        let mut osc133 = false;
+        let mut user_var: Option<String> = None;
+        let mut tmux = false;
+        let mut notify = false;
+        let mut a11y: Option<Duration> = None;
+        let mut ascii = false;
+        let mut debug: Option<PathBuf> = None;
+        let mut timestamps = false;
+        let mut utc = false;
+        let mut line_stamps: Option<StampStyle> = None;
+        let mut summary = false;
+        let mut systemd_extend = false;
+        let mut systemd_ready: Option<Pattern> = None;
+        let mut quiet_errors = false;
+        let mut energy = false;
+        let mut wait_reasons = false;
+        let mut verify_passthrough = false;
+        let mut strip_progress = false;
+        let mut number_style = NumberStyle::default();
+        let mut delimiter = Delimiter::default();
+        #[cfg(unix)]
+        let mut merge = false;
         #[cfg(unix)]
         let mut tty = false;
         #[cfg(unix)]
         let mut split_stderr = false;
+        #[cfg(unix)]
+        let mut tee_fd: Option<u32> = None;
+        #[cfg(unix)]
+        let mut auto_tty: Option<Duration> = None;
+        #[cfg(unix)]
+        let mut pty_size: Option<PtySize> = None;
+        #[cfg(unix)]
+        let mut pty_term: Option<OsString> = None;
+        #[cfg(unix)]
+        let mut term_sequence = TermSequence::default();
         while let Some(arg) = parser.next()? {
             match arg {
                 Arg::Short('f') | Arg::Long("format") => format = Some(parser.value()?.parse()?),
+                Arg::Long("a11y") => {
+                    a11y = Some(match parser.optional_value() {
+                        Some(secs) => match secs.parse()? {
+                            0 => return Err("--a11y interval must be positive".into()),
+                            secs => Duration::from_secs(secs),
+                        },
+                        None => DEFAULT_ANNOUNCE_INTERVAL,
+                    });
+                }
+                Arg::Long("append") => append = true,
+                Arg::Long("ascii") => ascii = true,
+                Arg::Long("auto-tty") => {
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            auto_tty = Some(match parser.optional_value() {
+                                Some(secs) => match secs.parse()? {
+                                    0 => return Err("--auto-tty window must be positive".into()),
+                                    secs => Duration::from_secs(secs),
+                                },
+                                None => DEFAULT_AUTO_TTY_WINDOW,
+                            });
+                        } else {
+                            return Err("--auto-tty is not supported on this system".into());
+                        }
+                    }
+                }
+                Arg::Long("caffeinate") => {
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            caffeinate = true;
+                        } else {
+                            return Err("--caffeinate is not supported on this system".into());
+                        }
+                    }
+                }
+                Arg::Long("color-stderr") => {
+                    color_stderr = match parser.optional_value() {
+                        Some(when) => when.parse()?,
+                        None => ColorWhen::Always,
+                    };
+                }
+                Arg::Long("decimal-sep") => number_style.decimal_sep = parser.value()?.string()?,
+                Arg::Long("debug") => {
+                    debug = Some(match parser.optional_value() {
+                        Some(path) => PathBuf::from(path),
+                        None => PathBuf::from(DEFAULT_DEBUG_FILE),
+                    });
+                }
+                Arg::Long("delimiter") => delimiter = parser.value()?.parse()?,
+                Arg::Long("energy") => energy = true,
+                Arg::Long("wait-reasons") => wait_reasons = true,
+                Arg::Long("verify-passthrough") => verify_passthrough = true,
+                Arg::Long("exit-map") => exit_map.push(parser.value()?.parse()?),
+                Arg::Long("fail-on-stderr") => {
+                    fail_on_stderr = Some(match parser.optional_value() {
+                        Some(code) => code.parse()?,
+                        None => 1,
+                    });
+                }
+                Arg::Long("failure-regex") => failure_regex = Some(parser.value()?.parse()?),
+                Arg::Short('G') | Arg::Long("gather-stderr") => gather_stderr = true,
+                Arg::Long("kill-on-max-descendants") => kill_on_max_descendants = true,
+                Arg::Short('L') | Arg::Long("line-buffer") => line_buffer = true,
+                Arg::Long("line-timings") => line_timings = Some(parser.value()?.into()),
+                Arg::Long("slowlog") => slowlog = Some(parser.value()?.into()),
+                Arg::Long("mail") => mail = Some(parser.value()?.string()?),
+                Arg::Long("mail-lines") => mail_lines = parser.value()?.parse()?,
+                Arg::Long("mail-smtp") => {
+                    mail_transport = mail::Transport::smtp(&parser.value()?.string()?);
+                }
+                Arg::Long("slowlog-threshold") => {
+                    let s = parser.value()?.string()?;
+                    match parse_duration(&s) {
+                        Some(d) => slowlog_threshold = Some(d),
+                        None => {
+                            return Err(
+                                format!("invalid --slowlog-threshold duration: {s:?}").into()
+                            );
+                        }
+                    }
+                }
+                Arg::Long("sink-queue") => match parser.value()?.parse()? {
+                    0 => return Err("--sink-queue size must be positive".into()),
+                    n => sink_queue = Some(n),
+                },
+                Arg::Long("sink-overflow") => sink_overflow = parser.value()?.parse()?,
+                Arg::Long("auto-hide-at") => match parser.value()?.parse()? {
+                    0 => return Err("--auto-hide-at rate must be positive".into()),
+                    rate => auto_hide_at = Some(rate),
+                },
+                Arg::Long("exclude-suspended") => exclude_suspended = true,
+                Arg::Long("expect") => {
+                    let s = parser.value()?.string()?;
+                    match parse_duration(&s) {
+                        Some(d) => expect = Some(d),
+                        None => return Err(format!("invalid --expect duration: {s:?}").into()),
+                    }
+                }
+                Arg::Long("timeout") => {
+                    let s = parser.value()?.string()?;
+                    match parse_duration(&s) {
+                        Some(Duration::ZERO) => {
+                            return Err("--timeout duration must be positive".into());
+                        }
+                        Some(d) => timeout = Some(d),
+                        None => return Err(format!("invalid --timeout duration: {s:?}").into()),
+                    }
+                }
+                Arg::Long("max-descendants") => max_descendants = Some(parser.value()?.parse()?),
+                Arg::Long("merge") => {
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            merge = true;
+                        } else {
+                            return Err("--merge is not supported on this system".into());
+                        }
+                    }
+                }
+                Arg::Long("min-free") => min_free = parser.value()?.parse()?,
+                Arg::Long("on-low-disk") => on_low_disk = Some(parser.value()?),
+                Arg::Long("osc133") => osc133 = true,
+                Arg::Long("periodic") => periodic = Some(parser.value()?.parse()?),
+                Arg::Long("pty-size") => {
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            pty_size = Some(parser.value()?.parse()?);
+                        } else {
+                            return Err("--pty-size is not supported on this system".into());
+                        }
+                    }
+                }
+                Arg::Long("pty-term") => {
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            pty_term = Some(parser.value()?);
+                        } else {
+                            return Err("--pty-term is not supported on this system".into());
+                        }
+                    }
+                }
+                Arg::Long("term-sequence") => {
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            term_sequence = parser.value()?.parse()?;
+                        } else {
+                            return Err("--term-sequence is not supported on this system".into());
+                        }
+                    }
+                }
+                Arg::Long("redact") => redact.push(parser.value()?.parse()?),
+                Arg::Long("stopwatch-regex") => stopwatches.push(parser.value()?.parse()?),
+                Arg::Long("preset") => {
+                    let preset = parser.value()?.parse::<Preset>()?;
+                    stopwatches.extend(preset.stopwatches());
+                }
+                Arg::Long("redact-secrets") => redact_secrets = true,
+                Arg::Long("redact-relay") => redact_relay = true,
                 Arg::Short('r') | Arg::Long("refresh") => {
-                    refresh_period = Duration::from_millis(parser.value()?.parse()?);
+                    refresh_period = Some(Duration::from_millis(parser.value()?.parse()?));
+                }
+                Arg::Long("setup") => setup = Some(parser.value()?),
+                Arg::Long("strip-progress") => strip_progress = true,
+                Arg::Long("success-regex") => success_regex = Some(parser.value()?.parse()?),
+                Arg::Long("summary") => summary = true,
+                Arg::Long("systemd-extend") => {
+                    systemd_extend = true;
+                    systemd_ready = parser.optional_value().map(|s| s.parse()).transpose()?;
                 }
+                Arg::Long("quiet-errors") => quiet_errors = true,
                 Arg::Short('S') | Arg::Long("split-stderr") => {
                     cfg_if! {
                         if #[cfg(unix)] {
@@ -50,7 +823,42 @@ impl Arguments {
                         }
                     }
                 }
+                Arg::Long("teardown") => teardown = Some(parser.value()?),
+                Arg::Long("tee-fd") => {
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            tee_fd = Some(parser.value()?.parse()?);
+                        } else {
+                            return Err("--tee-fd is not supported on this system".into());
+                        }
+                    }
+                }
+                Arg::Long("thousands-sep") => {
+                    number_style.thousands_sep = parser.value()?.string()?;
+                }
+                Arg::Long("timestamps") => {
+                    timestamps = true;
+                    if let Some(style) = parser.optional_value() {
+                        line_stamps = Some(style.parse()?);
+                    }
+                }
+                Arg::Long("utc") => utc = true,
+                Arg::Long("precise") => precise = true,
+                Arg::Long("pipe-stdin") => pipe_stdin = true,
+                Arg::Long("no-stdin") => no_stdin = true,
+                Arg::Long("banner") => banner = true,
+                Arg::Long("porcelain") => {
+                    porcelain = Some(porcelain_target(parser.optional_value(), "--porcelain")?);
+                    porcelain_style = PorcelainStyle::Plain;
+                }
+                Arg::Long("json") => {
+                    porcelain = Some(porcelain_target(parser.optional_value(), "--json")?);
+                    porcelain_style = PorcelainStyle::Json;
+                }
+                Arg::Long("tmux") => tmux = true,
+                Arg::Long("notify") => notify = true,
                 Arg::Short('t') | Arg::Long("total") => total = true,
+                Arg::Long("plain") => plain = true,
                 Arg::Short('T') | Arg::Long("tty") => {
                     cfg_if! {
                         if #[cfg(unix)] {
@@ -60,18 +868,149 @@ impl Arguments {
                         }
                     }
                 }
+                Arg::Long("user-var") => {
+                    user_var = Some(match parser.optional_value() {
+                        Some(name) => name.string()?,
+                        None => String::from("elapsed"),
+                    });
+                }
+                Arg::Long("warn-fds") => warn_fds = Some(parser.value()?.parse()?),
+                Arg::Long("watch-disk") => watch_disks.push(PathBuf::from(parser.value()?)),
+                Arg::Long("watch-path") => watch_paths.push(PathBuf::from(parser.value()?)),
                 Arg::Short('h') | Arg::Long("help") => return Ok(Arguments::Help),
+                Arg::Long("doctor") => return Ok(Arguments::Doctor),
+                Arg::Long("runbook") => {
+                    let path = parser.value()?.into();
+                    if let Some(arg) = parser.next()? {
+                        return Err(arg.unexpected());
+                    }
+                    return Ok(Arguments::Runbook(path));
+                }
+                Arg::Long("man") => return Ok(Arguments::Man),
                 Arg::Short('V') | Arg::Long("version") => return Ok(Arguments::Version),
                 Arg::Value(cmd) => {
+                    if pipe_stdin && no_stdin {
+                        return Err("--pipe-stdin cannot be combined with --no-stdin".into());
+                    }
+                    #[cfg(unix)]
+                    if pipe_stdin && (tty || auto_tty.is_some()) {
+                        return Err(
+                            "--pipe-stdin cannot be combined with --tty or --auto-tty".into()
+                        );
+                    }
+                    if verify_passthrough && redact_relay {
+                        return Err(
+                            "--verify-passthrough cannot be combined with --redact-relay".into(),
+                        );
+                    }
+                    if verify_passthrough && strip_progress {
+                        return Err(
+                            "--verify-passthrough cannot be combined with --strip-progress".into(),
+                        );
+                    }
+                    if verify_passthrough && line_stamps.is_some() {
+                        return Err(
+                            "--verify-passthrough cannot be combined with --timestamps=<STYLE>"
+                                .into(),
+                        );
+                    }
                     let args = parser.raw_args()?.collect::<Vec<_>>();
-                    let format = format.unwrap_or_default();
-                    cfg_if! {
-                        if #[cfg(unix)] {
-                            return Ok(Arguments::Run(Elapsed { cmd, args, format, refresh_period, total, tty, split_stderr }));
+                    let format = format.unwrap_or_else(|| {
+                        if precise {
+                            Format::precise()
                         } else {
-                            return Ok(Arguments::Run(Elapsed { cmd, args, format, refresh_period, total }));
+                            Format::default()
                         }
-                    }
+                    });
+                    let refresh_period = refresh_period.unwrap_or(if precise {
+                        PRECISE_REFRESH_PERIOD
+                    } else {
+                        DEFAULT_REFRESH_PERIOD
+                    });
+                    return Ok(Arguments::Run(Box::new(Elapsed {
+                        cmd,
+                        args,
+                        format,
+                        refresh_period,
+                        total,
+                        plain,
+                        gather_stderr,
+                        color_stderr,
+                        fail_on_stderr,
+                        exit_map,
+                        success_regex,
+                        failure_regex,
+                        redact,
+                        stopwatches,
+                        pipe_stdin,
+                        no_stdin,
+                        banner,
+                        porcelain,
+                        porcelain_style,
+                        redact_secrets,
+                        redact_relay,
+                        caffeinate,
+                        line_timings,
+                        slowlog,
+                        append,
+                        mail,
+                        mail_lines,
+                        mail_transport,
+                        slowlog_threshold: slowlog_threshold.unwrap_or(DEFAULT_SLOWLOG_THRESHOLD),
+                        sink_queue,
+                        sink_overflow,
+                        expect,
+                        exclude_suspended,
+                        timeout,
+                        auto_hide_at,
+                        line_buffer,
+                        warn_fds,
+                        max_descendants,
+                        kill_on_max_descendants,
+                        watch_paths,
+                        watch_disks,
+                        min_free,
+                        on_low_disk,
+                        periodic,
+                        setup,
+                        teardown,
+                        osc133,
+                        user_var,
+                        tmux,
+                        notify,
+                        a11y,
+                        ascii,
+                        debug,
+                        timestamps,
+                        utc,
+                        line_stamps,
+                        summary,
+                        systemd_extend,
+                        systemd_ready,
+                        quiet_errors,
+                        energy,
+                        wait_reasons,
+                        verify_passthrough,
+                        strip_progress,
+                        number_style,
+                        delimiter,
+                        #[cfg(unix)]
+                        merge,
+                        #[cfg(unix)]
+                        tty,
+                        #[cfg(unix)]
+                        split_stderr,
+                        #[cfg(unix)]
+                        tee_fd,
+                        #[cfg(unix)]
+                        auto_tty,
+                        #[cfg(unix)]
+                        pty_size,
+                        #[cfg(unix)]
+                        pty_term,
+                        #[cfg(unix)]
+                        term_sequence,
+                    })));
                 }
                 _ => return Err(arg.unexpected()),
             }
@@ -81,51 +1020,27 @@ impl Arguments {
 
     fn run(self) -> Result<ExitCode, Error> {
         match self {
-            Arguments::Run(elapsed) => run(elapsed),
+            Arguments::Run(elapsed) => run(*elapsed),
+            Arguments::Runbook(path) => {
+                let runbook = runbook::Runbook::load(&path)?;
+                Ok(ExitCode::from(runbook::run(runbook)?))
+            }
             Arguments::Help => {
-                write!(
-                    io::stdout().lock(),
-                    concat!(
-                        "Usage: elapsed [<options>] <command> [<arg> ...]\n",
-                        "\n",
-                        "Show runtime while a command runs\n",
-                        "\n",
-                        "Visit <https://github.com/jwodder/elapsed> for more information.\n",
-                        "\n",
-                        "Options:\n",
-                        "  -f <TEMPLATE>, --format <TEMPLATE>\n",
-                        "                    Set the format of the status line\n",
-                        "\n",
-                        "                    Supported escapes:\n",
-                        "                    - %H - hours\n",
-                        "                    - %M - minutes\n",
-                        "                    - %S - seconds in minute\n",
-                        "                    - %s - total seconds\n",
-                        "                    - %f - subseconds; can take a decimal precision\n",
-                        "                    - %n or \\n - newline\n",
-                        "                    - %t or \\t - tab\n",
-                        "                    - %e or \\e - escape character\n",
-                        "                    - %% - percent sign\n",
-                        "                    - \\\\ - backslash\n",
-                        "\n",
-                        "  -r <INT>, --refresh <INT>\n",
-                        "                    Update the status line after every <INT> milliseconds\n",
-                        "                    [default: 1000 (once per second)]\n",
-                        "\n",
-                        "  -t, --total       Leave total elapsed time behind after command finishes\n",
-                        "\n",
-                        "  -T, --tty         Run command via a pseudo-terminal [Unix only]\n",
-                        "\n",
-                        "  -S, --split-stderr\n",
-                        "                    When used with --tty, send the command's stderr directly to\n",
-                        "                    elapsed's stderr instead of unifying with stdout via the\n",
-                        "                    pseudo-terminal [Unix only]\n",
-                        "\n",
-                        "  -h, --help        Display this help message and exit\n",
-                        "  -V, --version     Show the program version and exit\n",
-                    )
-                )
-                .map_err(Error::Write)?;
+                io::stdout()
+                    .lock()
+                    .write_all(HELP.as_bytes())
+                    .map_err(Error::Write)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Arguments::Doctor => {
+                doctor::run(io::stdout().lock()).map_err(Error::Write)?;
+                Ok(ExitCode::SUCCESS)
+            }
+            Arguments::Man => {
+                io::stdout()
+                    .lock()
+                    .write_all(man::render(HELP, env!("CARGO_PKG_VERSION")).as_bytes())
+                    .map_err(Error::Write)?;
                 Ok(ExitCode::SUCCESS)
             }
             Arguments::Version => {
@@ -149,37 +1064,250 @@ struct Elapsed {
     format: Format,
     refresh_period: Duration,
     total: bool,
+    /// Don't mark the `--total` line with how the command finished
+    plain: bool,
+    gather_stderr: bool,
+    color_stderr: ColorWhen,
+    fail_on_stderr: Option<u8>,
+    exit_map: Vec<ExitMapping>,
+    success_regex: Option<Pattern>,
+    failure_regex: Option<Pattern>,
+    redact: Vec<Pattern>,
+    /// Sub-timers started & stopped by lines of output
+    stopwatches: Vec<Stopwatch>,
+    /// Copy `elapsed`'s stdin to the command via a pipe
+    pipe_stdin: bool,
+    /// Connect the command's stdin to the null device
+    no_stdin: bool,
+    /// Print lines marking the start & end of the command
+    banner: bool,
+    /// Where to write machine-readable status records to instead of showing
+    /// a status line
+    porcelain: Option<PorcelainTarget>,
+    /// Whether to write the status records as plain text (`--porcelain`) or
+    /// JSON (`--json`)
+    porcelain_style: PorcelainStyle,
+    redact_secrets: bool,
+    /// Also apply redaction to the output relayed to stdout & stderr
+    redact_relay: bool,
+    caffeinate: bool,
+    line_timings: Option<PathBuf>,
+    /// File to write lines preceded by long stretches of silence to
+    slowlog: Option<PathBuf>,
+    /// Append to `line_timings` & `slowlog` instead of truncating them
+    append: bool,
+    /// Email address to send a report to when the command finishes
+    mail: Option<String>,
+    /// Number of lines of output to include in the `--mail` report
+    mail_lines: usize,
+    /// How to deliver the `--mail` report
+    mail_transport: mail::Transport,
+    /// The minimum stretch of silence before a line for it to be written to
+    /// `slowlog`
+    slowlog_threshold: Duration,
+    /// The size of the queue through which to feed each sink from a thread of
+    /// its own, if any
+    sink_queue: Option<usize>,
+    /// What to do when a sink's queue is full
+    sink_overflow: Overflow,
+    /// How long the command is expected to run for, for `%P` and `%{eta}`
+    expect: Option<Duration>,
+    /// Whether to leave the time during which the command is suspended off
+    /// the clock
+    exclude_suspended: bool,
+    /// How long to let the command run before stopping it
+    timeout: Option<Duration>,
+    /// The output rate, in lines per second, above which the status line is
+    /// hidden
+    auto_hide_at: Option<u64>,
+    line_buffer: bool,
+    warn_fds: Option<usize>,
+    /// Warn when the command has more than this many descendant processes
+    max_descendants: Option<usize>,
+    /// Kill the command's process tree instead of just warning when
+    /// `max_descendants` is exceeded
+    kill_on_max_descendants: bool,
+    watch_paths: Vec<PathBuf>,
+    watch_disks: Vec<PathBuf>,
+    min_free: MinFree,
+    on_low_disk: Option<OsString>,
+    /// A command to run via the shell at a regular interval while the command
+    /// runs
+    periodic: Option<Periodic>,
+    /// A command to run via the shell before starting the timed command
+    setup: Option<OsString>,
+    /// A command to run via the shell after the timed command has exited
+    teardown: Option<OsString>,
+    osc133: bool,
+    user_var: Option<String>,
+    tmux: bool,
+    notify: bool,
+    /// If set, announce the elapsed time in plain lines at this interval
+    /// instead of redrawing the status line
+    a11y: Option<Duration>,
+    /// Erase the status line with backspaces rather than escape sequences
+    ascii: bool,
+    /// File to write a log of internal events to
+    debug: Option<PathBuf>,
+    /// Include wall-clock start & end times in the `--total` line and
+    /// `--a11y` summary
+    timestamps: bool,
+    /// Show wall-clock times in UTC instead of local time
+    utc: bool,
+    /// Prefix each line of the command's output with this kind of timestamp
+    line_stamps: Option<StampStyle>,
+    /// Print a table of statistics about the run after the command exits
+    summary: bool,
+    /// Keep extending the systemd service's start timeout until the command
+    /// has started up, then send `READY=1`
+    systemd_extend: bool,
+    /// The pattern marking the end of the start phase for `--systemd-extend`;
+    /// if `None`, the start phase lasts until the command exits successfully
+    systemd_ready: Option<Pattern>,
+    /// Don't report the command being killed by a signal as an error
+    quiet_errors: bool,
+    /// Measure the energy consumed by the CPU during the run for the
+    /// `--summary` table
+    energy: bool,
+    /// Sample the command's process's wait channel for the `--summary` table
+    wait_reasons: bool,
+    /// Checksum the command's output as it's read & written, and warn if the
+    /// two differ
+    verify_passthrough: bool,
+    /// Remove the command's OSC 9;4 progress reports from its output
+    strip_progress: bool,
+    /// How to punctuate numbers in output
+    number_style: NumberStyle,
+    /// The byte that terminates each record of the command's output
+    delimiter: Delimiter,
+    /// Send the command's stdout & stderr to the same pipe
+    #[cfg(unix)]
+    merge: bool,
     #[cfg(unix)]
     tty: bool,
     #[cfg(unix)]
     split_stderr: bool,
+    /// Inherited file descriptor to write a copy of the command's output to
+    #[cfg(unix)]
+    tee_fd: Option<u32>,
+    /// How long the command must go without output while using CPU before
+    /// it's restarted via a pseudo-terminal
+    #[cfg(unix)]
+    auto_tty: Option<Duration>,
+    /// The size to give the command's pseudo-terminal instead of that of
+    /// `elapsed`'s terminal
+    #[cfg(unix)]
+    pty_size: Option<PtySize>,
+    /// The value of `TERM` to give the command when running it via a
+    /// pseudo-terminal instead of inheriting it
+    #[cfg(unix)]
+    pty_term: Option<OsString>,
+    /// The signals to send to stop the command when `elapsed` is cancelled or
+    /// interrupted
+    #[cfg(unix)]
+    term_sequence: TermSequence,
 }
 
 impl Elapsed {
+    /// Return the time zone in which to show wall-clock times
+    fn zone(&self) -> Zone {
+        if self.utc { Zone::Utc } else { Zone::Local }
+    }
+
+    /// Return how the sinks should be fed through queues, if at all
+    fn sink_queueing(&self) -> Option<Queueing> {
+        match (self.sink_queue, self.sink_overflow) {
+            (Some(capacity), overflow) => Some(Queueing { capacity, overflow }),
+            (None, Overflow::Drop) => Some(Queueing {
+                capacity: DEFAULT_SINK_QUEUE,
+                overflow: Overflow::Drop,
+            }),
+            (None, Overflow::Block) => None,
+        }
+    }
+
+    /// Return true if `--json` records are being written to stderr, in which
+    /// case the command's stderr & `elapsed`'s warnings are only written to
+    /// stderr as records
+    fn json_to_stderr(&self) -> bool {
+        self.porcelain == Some(PorcelainTarget::Stderr)
+            && self.porcelain_style == PorcelainStyle::Json
+    }
+
+    /// Return the command & its arguments as a single string for display
+    fn command_line(&self) -> String {
+        std::iter::once(&self.cmd)
+            .chain(&self.args)
+            .map(|s| s.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
     fn spawn(&self) -> Result<(Child, ByteLines<ChildOutput>, ByteLines<ChildOutput>), Error> {
+        #[cfg(unix)]
+        let tty = self.tty;
+        #[cfg(not(unix))]
+        let tty = false;
+        let linebuf = if self.line_buffer {
+            LineBuffering::new(tty)
+        } else {
+            LineBuffering::default()
+        };
         cfg_if! {
             if #[cfg(unix)] {
-                if self.tty {
-                    self.spawn_tty()
+                if tty {
+                    self.spawn_tty(&linebuf.env, self.split_stderr)
+                } else if linebuf.needs_tty {
+                    // Keep stderr separate (unless merging) so that the only
+                    // visible change is in how the command buffers its output.
+                    self.spawn_tty(&linebuf.env, !self.merge)
+                } else if self.merge {
+                    self.spawn_merged(&linebuf.env)
                 } else {
-                    self.spawn_plain()
+                    self.spawn_plain(&linebuf.env)
                 }
             } else {
-                self.spawn_plain()
+                self.spawn_plain(&linebuf.env)
             }
         }
     }
 
+    /// Return what to connect the command's stdin to when it's not run via a
+    /// pseudo-terminal
+    fn stdin(&self) -> Stdio {
+        if self.no_stdin {
+            Stdio::null()
+        } else if self.pipe_stdin {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        }
+    }
+
+    /// If the command was given a pipe for its stdin, start copying
+    /// `elapsed`'s stdin into it
+    fn pump_stdin(&self, p: &mut Child) -> Result<(), Error> {
+        if let Some(dest) = p.stdin.take() {
+            stdin::pump(dest).map_err(Error::PipeStdin)?;
+        }
+        Ok(())
+    }
+
     fn spawn_plain(
         &self,
+        env: &[(OsString, OsString)],
     ) -> Result<(Child, ByteLines<ChildOutput>, ByteLines<ChildOutput>), Error> {
         let mut p = Command::new(&self.cmd)
             .args(&self.args)
+            .envs(env.iter().cloned())
+            .envs(child_env())
+            .stdin(self.stdin())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .kill_on_drop(true)
             .spawn()
             .map_err(Error::Spawn)?;
+        self.pump_stdin(&mut p)?;
         let pout = ByteLines::new(ChildOutput::Stdout(
             p.stdout.take().expect("Child.stdout should be Some"),
         ));
@@ -189,24 +1317,82 @@ impl Elapsed {
         Ok((p, pout, perr))
     }
 
+    /// Spawn the command with its stdout & stderr both connected to the write
+    /// end of a single pipe
+    #[cfg(unix)]
+    fn spawn_merged(
+        &self,
+        env: &[(OsString, OsString)],
+    ) -> Result<(Child, ByteLines<ChildOutput>, ByteLines<ChildOutput>), Error> {
+        let (tx, rx) = tokio::net::unix::pipe::pipe().map_err(Error::InitPipe)?;
+        let out_fd = tx.into_blocking_fd().map_err(Error::InitPipe)?;
+        let err_fd = out_fd.try_clone().map_err(Error::InitPipe)?;
+        // The `Command` is dropped at the end of this statement, closing our
+        // copies of the pipe's write end so that EOF is seen once the command
+        // (and any descendants) close theirs.
+        let mut p = Command::new(&self.cmd)
+            .args(&self.args)
+            .envs(env.iter().cloned())
+            .envs(child_env())
+            .stdin(self.stdin())
+            .stdout(Stdio::from(out_fd))
+            .stderr(Stdio::from(err_fd))
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(Error::Spawn)?;
+        self.pump_stdin(&mut p)?;
+        Ok((
+            p,
+            ByteLines::new(ChildOutput::Merged(rx)),
+            ByteLines::new(ChildOutput::Null),
+        ))
+    }
+
     #[cfg(unix)]
-    fn spawn_tty(&self) -> Result<(Child, ByteLines<ChildOutput>, ByteLines<ChildOutput>), Error> {
+    fn spawn_tty(
+        &self,
+        env: &[(OsString, OsString)],
+        split_stderr: bool,
+    ) -> Result<(Child, ByteLines<ChildOutput>, ByteLines<ChildOutput>), Error> {
         let (pty, pts) = pty_process::open().map_err(Error::InitPty)?;
-        if let Some((width, height)) = terminal_size::terminal_size() {
-            pty.resize(pty_process::Size::new(width.0, height.0))
-                .map_err(Error::InitPty)?;
-        }
+        let size = self.pty_size.or_else(|| {
+            terminal_size::terminal_size().map(|(width, height)| PtySize {
+                cols: width.0,
+                rows: height.0,
+            })
+        });
         let mut cmd = pty_process::Command::new(&self.cmd)
             .args(&self.args)
-            .stdin(Stdio::inherit())
+            .envs(env.iter().cloned())
+            .envs(child_env())
+            .stdin(if self.no_stdin {
+                Stdio::null()
+            } else {
+                Stdio::inherit()
+            })
             .kill_on_drop(true);
-        if self.split_stderr {
+        // Programs that consult `COLUMNS` & `LINES` should see the same size
+        // as programs that query the pty, so any inherited values that might
+        // disagree with it are replaced or removed.
+        if let Some(PtySize { cols, rows }) = size {
+            pty.resize(pty_process::Size::new(rows, cols))
+                .map_err(Error::InitPty)?;
+            cmd = cmd
+                .env("COLUMNS", cols.to_string())
+                .env("LINES", rows.to_string());
+        } else {
+            cmd = cmd.env_remove("COLUMNS").env_remove("LINES");
+        }
+        if let Some(term) = &self.pty_term {
+            cmd = cmd.env("TERM", term);
+        }
+        if split_stderr {
             cmd = cmd.stderr(Stdio::piped());
         }
         let mut p = cmd.spawn(pts).map_err(Error::SpawnPty)?;
-        let mut pout = ByteLines::new(ChildOutput::Pty(pty));
+        let mut pout = ByteLines::new(ChildOutput::Pty(pty, QueryResponder::new(size)));
         pout.strip_cr = true;
-        let perr = if self.split_stderr {
+        let perr = if split_stderr {
             ChildOutput::Stderr(p.stderr.take().expect("Child.stderr should be Some"))
         } else {
             ChildOutput::Null
@@ -216,7 +1402,7 @@ impl Elapsed {
 }
 
 fn main() -> ExitCode {
-    match Arguments::from_parser(Parser::from_env())
+    match Arguments::from_env()
         .map_err(Error::Usage)
         .and_then(Arguments::run)
     {
@@ -224,40 +1410,459 @@ fn main() -> ExitCode {
         Err(e) if e.is_epipe_write() => ExitCode::SUCCESS,
         Err(e) => {
             let _ = writeln!(io::stderr().lock(), "elapsed: {e}");
-            ExitCode::FAILURE
+            ExitCode::from(e.exit_code())
         }
     }
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn run(app: Elapsed) -> Result<ExitCode, Error> {
-    let statline = StatusLine::new(app.format.clone());
+    app.number_style.clone().install();
+    if let Some(setup) = &app.setup {
+        let rc = shell_command(setup)
+            .status()
+            .await
+            .map_err(Error::RunSetup)?;
+        if !rc.success() {
+            return Err(Error::SetupFailed(rc));
+        }
+    }
+    let mut sinks = Sinks::new(app.sink_queueing());
+    if let Some(path) = &app.line_timings {
+        let timings = LineTimings::create(path, app.append).map_err(Error::OpenLineTimings)?;
+        sinks
+            .push("--line-timings", timings, Error::WriteLineTimings)
+            .map_err(Error::StartSink)?;
+    }
+    if let Some(path) = &app.slowlog {
+        let slowlog =
+            SlowLog::create(path, app.slowlog_threshold, app.append).map_err(Error::OpenSlowLog)?;
+        sinks
+            .push("--slowlog", slowlog, Error::WriteSlowLog)
+            .map_err(Error::StartSink)?;
+    }
+    #[cfg(unix)]
+    if let Some(fd) = app.tee_fd {
+        sinks
+            .push("--tee-fd", Tee(open_tee_fd(fd)?), Error::WriteTee)
+            .map_err(Error::StartSink)?;
+    }
+    let start = Instant::now();
+    let wall_clock = WallClock::start(app.zone());
+    if let Some(path) = &app.debug {
+        debug::init(path, start).map_err(Error::OpenDebugLog)?;
+    }
+    let ascii = app.ascii || term::is_dumb();
+    if let Some(target) = app.porcelain {
+        let name = match app.porcelain_style {
+            PorcelainStyle::Plain => "--porcelain",
+            PorcelainStyle::Json => "--json",
+        };
+        let porcelain = open_porcelain(target, app.porcelain_style)?;
+        sinks
+            .push(name, porcelain, Error::WritePorcelain)
+            .map_err(Error::StartSink)?;
+    }
+    // When a nested `elapsed`'s stderr is a terminal, it's most likely the
+    // outer instance's pseudo-terminal, and two status lines drawn on the same
+    // line would just fight each other, so leave it to the outer instance.
+    let nested = std::env::var_os(NESTING_ENV_VAR).is_some() && io::stderr().is_terminal();
+    if nested {
+        let msg = "running inside another instance of elapsed; not showing a status line";
+        sinks.warning(msg)?;
+        if !app.json_to_stderr() {
+            let _ = writeln!(io::stderr().lock(), "elapsed: warning: {msg}");
+        }
+    }
+    let statline = Arc::new(Mutex::new(SharedStatus {
+        line: if app.porcelain.is_some() || nested {
+            StatusLine::Inactive {
+                format: app.format.clone(),
+                clock: PausableClock::new(start),
+            }
+        } else {
+            StatusLine::new(app.format.clone(), start, app.a11y, ascii)
+        },
+        shown: None,
+        lost: None,
+    }));
     let stdout = io::stdout();
     let stderr = io::stderr();
     let stdout_is_tty = stdout.is_terminal();
-    let ticker = interval(app.refresh_period);
-    let (p, pout, perr) = app.spawn()?;
+    let color_stderr = if ascii {
+        app.color_stderr == ColorWhen::Always
+    } else {
+        app.color_stderr.enabled(&stderr)
+    };
+    let ticker = {
+        let statline = Arc::clone(&statline);
+        Ticker::spawn(app.refresh_period, move || {
+            // The event loop is stalled, so at least keep the clock going.
+            let mut status = lock_status(&statline);
+            if let Some((readings, note)) = &status.shown {
+                let r = status
+                    .line
+                    .clear()
+                    .and_then(|()| status.line.print(*readings, note));
+                status.check_drawn(r, start.elapsed());
+            }
+        })
+        .map_err(Error::StartTicker)?
+    };
+    let inhibitor = if app.caffeinate {
+        let why = format!("Running {}", app.cmd.to_string_lossy());
+        match SleepInhibitor::start(&why).await {
+            Ok(inhibitor) => Some(inhibitor),
+            Err(e) => {
+                let _ = writeln!(
+                    stderr.lock(),
+                    "elapsed: could not prevent system sleep: {e}"
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let systemd = if app.systemd_extend {
+        match SystemdNotifier::from_env() {
+            Ok(notifier) => notifier,
+            Err(e) => {
+                let _ = writeln!(
+                    stderr.lock(),
+                    "elapsed: could not connect to systemd notification socket: {e}"
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let tmux = if app.tmux {
+        match Tmux::connect().await {
+            Ok(Some(tmux)) => Some(tmux),
+            Ok(None) => {
+                let _ = writeln!(
+                    stderr.lock(),
+                    "elapsed: --tmux given but not running inside tmux"
+                );
+                None
+            }
+            Err(e) => {
+                let _ = writeln!(stderr.lock(), "elapsed: could not connect to tmux: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let energy = if app.energy {
+        match EnergyMeter::start() {
+            Ok(meter) => Some(meter),
+            Err(e) => {
+                let _ = writeln!(
+                    stderr.lock(),
+                    "elapsed: could not measure energy usage: {e}"
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    // Output from nested instances of `elapsed` is indented according to how
+    // deeply they're nested.
+    let indent = "  ".repeat(nesting_depth());
+    let banner = app
+        .banner
+        .then(|| Banner::new(app.command_line(), ascii, indent.clone()));
+    if let Some(banner) = &banner {
+        banner
+            .start(stderr.lock(), &wall_clock.start_time_of_day())
+            .map_err(Error::Write)?;
+    }
+    // Processes that were reaped before the command was spawned (e.g., the
+    // `--setup` command) shouldn't count towards its resource usage.
+    let usage_baseline = procinfo::child_usage();
+    let r = app.spawn();
+    cfg_if! {
+        if #[cfg(unix)] {
+            // There's no point in restarting the command via a pty if it's
+            // already running in one.
+            let in_pty = matches!(&r, Ok((_, pout, _)) if matches!(pout.get_ref(), ChildOutput::Pty(..)));
+            let auto_tty = app
+                .auto_tty
+                .filter(|_| !in_pty)
+                .map(|window| AutoTty {
+                    window,
+                    app: app.clone(),
+                    last_cpu_time: Duration::ZERO,
+                });
+            let needs_cpu_time = auto_tty.is_some();
+        } else {
+            let needs_cpu_time = false;
+        }
+    }
+    debug!("run ID: {}", run_id());
+    debug!(
+        "spawning command {:?} with arguments {:?}",
+        app.cmd, app.args
+    );
+    match &r {
+        Ok((p, _, _)) => debug!("spawned process {:?}", p.id()),
+        Err(e) => debug!("{e}"),
+    }
+    let (p, mut pout, mut perr) = r?;
+    pout.delimiter = app.delimiter.0;
+    perr.delimiter = app.delimiter.0;
     let mut elapsing = Elapsing {
         statline,
+        start,
+        clock: PausableClock::new(start),
+        exclude_suspended: app.exclude_suspended,
+        wall_clock,
         p,
         pout,
         perr,
         stdout,
         stderr,
         stdout_is_tty,
+        color_stderr,
         ticker,
+        missed_ticks: 0,
+        sinks,
+        mail_tail: app.mail.is_some().then(|| mail::Tail::new(app.mail_lines)),
+        gathered_stderr: (app.gather_stderr && !app.json_to_stderr()).then(Vec::new),
+        json_to_stderr: app.json_to_stderr(),
+        held_stdout: Vec::new(),
+        held_stderr: Vec::new(),
+        pause: Pause::None,
+        last_partials: (0, 0),
+        output_closed: false,
+        stdout_broken: false,
+        fail_on_stderr: app.fail_on_stderr,
+        exit_map: app.exit_map.clone(),
+        counts: OutputCounts::default(),
+        last_output: start,
+        longest_silence: Duration::ZERO,
+        line_gaps: app.summary.then(LineGaps::new),
+        expected: app.expect,
+        progress: None,
+        strip_progress: app.strip_progress,
+        child_usage: None,
+        usage_baseline,
+        success_regex: app.success_regex.clone(),
+        failure_regex: app.failure_regex.clone(),
+        success_matched: false,
+        failure_matched: false,
+        redactor: Redactor::new(app.redact.clone(), app.redact_secrets),
+        redact_relay: app.redact_relay,
+        stopwatches: app.stopwatches.clone(),
+        systemd,
+        systemd_ready: app.systemd_ready.clone(),
+        systemd_ticker: interval(SYSTEMD_EXTEND_PERIOD),
+        sample_procs: app.format.uses_proc_stats()
+            || app.warn_fds.is_some()
+            || app.max_descendants.is_some()
+            || needs_cpu_time,
+        proc_stats: None,
+        stuck: StuckDetector::default(),
+        flood: app.auto_hide_at.map(|rate| FloodDetector::new(rate, start)),
+        wait_reasons: app.wait_reasons.then(WaitReasons::new),
+        passthrough: app.verify_passthrough.then(PassthroughCheck::default),
+        stamper: app
+            .line_stamps
+            .map(|style| LineStamper::new(style, app.zone())),
+        warn_fds: app.warn_fds,
+        fds_exceeded: false,
+        max_descendants: app.max_descendants,
+        kill_on_max_descendants: app.kill_on_max_descendants,
+        descendants_exceeded: false,
+        watch_paths: app
+            .watch_paths
+            .iter()
+            .map(|path| PathWatcher::new(path.clone(), start))
+            .collect(),
+        watch_disks: app
+            .watch_disks
+            .iter()
+            .cloned()
+            .map(DiskWatcher::new)
+            .collect(),
+        min_free: app.min_free,
+        on_low_disk: app.on_low_disk.clone(),
+        periodic: app.periodic.clone().map(PeriodicHook::new),
+        user_var: app.user_var.clone(),
+        tmux,
+        #[cfg(unix)]
+        auto_tty,
+        #[cfg(unix)]
+        term_sequence: app.term_sequence.clone(),
+        cancelled: false,
+        timeout: app.timeout,
+        timed_out: false,
+        killing: false,
+        relayed: false,
     };
-    elapsing.statline.print()?;
+    let osc133 = app.osc133 && stdout_is_tty;
+    if osc133 {
+        // There's no prompt, so mark an empty one right before the output.
+        term::write_osc133(elapsing.stdout.lock(), &["A", "B", "C"]).map_err(Error::Write)?;
+    }
+    elapsing.print_status();
+    elapsing.update_user_var()?;
+    elapsing.update_tmux_title()?;
     let r = elapsing.event_loop().await;
-    if app.total {
-        elapsing.statline.print_total()?;
+    elapsing.clear_user_var()?;
+    elapsing
+        .sinks
+        .exit(*r.as_ref().unwrap_or(&1), elapsing.run_time())?;
+    let cmd = app.cmd.to_string_lossy();
+    let exit_status = match (elapsing.stopped_because(), &r) {
+        (Some(why), _) => String::from(why),
+        (None, Ok(code)) => format!("exited with code {code}"),
+        (None, Err(_)) => String::from("terminated abnormally"),
+    };
+    let outcome = format!("{cmd} {exit_status}");
+    let tmux = elapsing.tmux.take();
+    if tmux.is_some() || app.notify {
+        let status = elapsing.status().line.render(elapsing.readings());
+        let msg = format!("{outcome} ({status})").replace('\n', " ");
+        if let Some(tmux) = tmux {
+            if let Err(e) = tmux.finish(&msg).await {
+                let _ = writeln!(
+                    elapsing.stderr.lock(),
+                    "elapsed: failed to notify tmux: {e}"
+                );
+            }
+        }
+        if app.notify {
+            let kitty = term::is_kitty();
+            if elapsing.stderr.is_terminal() {
+                term::send_notification(elapsing.stderr.lock(), kitty, "elapsed", &msg)
+                    .map_err(Error::Write)?;
+            } else if stdout_is_tty {
+                term::send_notification(elapsing.stdout.lock(), kitty, "elapsed", &msg)
+                    .map_err(Error::Write)?;
+            }
+        }
+    }
+    if let Some(inhibitor) = inhibitor {
+        // Failing to kill the helper isn't worth reporting, as
+        // `kill_on_drop()` gives it another chance when we exit anyway.
+        let _ = inhibitor.stop().await;
+    }
+    elapsing.dump_gathered_stderr()?;
+    elapsing.report_passthrough()?;
+    if osc133 {
+        let code = r.as_ref().map_or(1, |&rc| rc);
+        term::write_osc133(elapsing.stdout.lock(), &[&format!("D;{code}")])
+            .map_err(Error::Write)?;
+    }
+    let span = app
+        .timestamps
+        .then(|| elapsing.wall_clock.describe_span(elapsing.start.elapsed()));
+    if app.a11y.is_some() {
+        let mut status = elapsing.status();
+        let r = status.line.print_summary(&outcome, span.as_deref());
+        elapsing.check_drawn(&mut status, r);
+    } else if app.total {
+        let verdict = (!app.plain).then(|| {
+            if let Some(why) = elapsing.stopped_because() {
+                Verdict::Failure(String::from(why))
+            } else {
+                Verdict::new(&r)
+            }
+        });
+        let mut note = span.map(|s| format!(" [{s}]")).unwrap_or_default();
+        match &verdict {
+            Some(Verdict::Failure(why)) => {
+                let _ = write!(note, " [{why}]");
+            }
+            None => {
+                if let Some(why) = elapsing.stopped_because() {
+                    let _ = write!(note, " [{why}]");
+                }
+            }
+            _ => (),
+        }
+        let mut status = elapsing.status();
+        let r = status
+            .line
+            .print_total(elapsing.readings(), &note, verdict.as_ref());
+        elapsing.check_drawn(&mut status, r);
+    }
+    if app.summary {
+        let energy = energy.and_then(|meter| match meter.consumed() {
+            Ok(microjoules) => Some(microjoules),
+            Err(e) => {
+                let _ = writeln!(
+                    elapsing.stderr.lock(),
+                    "elapsed: could not measure energy usage: {e}"
+                );
+                None
+            }
+        });
+        let summary = Summary {
+            command: app.command_line(),
+            run_id: run_id().to_owned(),
+            started: elapsing.wall_clock.start_timestamp(),
+            duration: elapsing.run_time(),
+            suspended: elapsing.clock.paused_at(Instant::now()),
+            exit_status: exit_status.clone(),
+            usage: elapsing.child_usage,
+            energy,
+            wait_reasons: elapsing.wait_reasons.clone(),
+            counts: elapsing.counts,
+            merged: elapsing.stdout_stream() == Stream::Merged,
+            first_output: elapsing.readings().first_output,
+            longest_silence: elapsing.longest_silence(),
+            line_gaps: elapsing.line_gaps.as_ref().and_then(LineGaps::stats),
+            clock_jumps: elapsing.wall_clock.jumps().to_vec(),
+            status_lost: elapsing.status().lost.clone(),
+            missed_ticks: elapsing.ticker.missed(),
+            stdout_broken: elapsing.stdout_broken,
+            sink_drops: elapsing.sinks.drops(),
+        };
+        let mut err = elapsing.stderr.lock();
+        for line in summary.to_string().lines() {
+            writeln!(err, "{indent}{line}").map_err(Error::Write)?;
+        }
+        err.flush().map_err(Error::Write)?;
+    }
+    if !elapsing.stopwatches.is_empty() {
+        let now = Instant::now();
+        let mut err = elapsing.stderr.lock();
+        for sw in &mut elapsing.stopwatches {
+            sw.finish(now);
+            writeln!(err, "{indent}{sw}").map_err(Error::Write)?;
+        }
+        err.flush().map_err(Error::Write)?;
+    }
+    if let Some(banner) = &banner {
+        banner
+            .end(elapsing.stderr.lock(), &exit_status, elapsing.run_time())
+            .map_err(Error::Write)?;
+    }
+    // Sending the mail can take a while, so do it once everything's been
+    // shown on the terminal.
+    if let (Some(to), Some(tail)) = (&app.mail, elapsing.mail_tail.take()) {
+        let msg = mail::Message {
+            to: to.clone(),
+            command: app.command_line(),
+            run_id: run_id().to_owned(),
+            exit_status: exit_status.clone(),
+            duration: elapsing.run_time(),
+            tail,
+        };
+        if let Err(e) = mail::send(&msg, &app.mail_transport).await {
+            let _ = writeln!(elapsing.stderr.lock(), "elapsed: failed to send mail: {e}");
+        }
     }
     if r.is_err() {
-        // The child process is still running, so get rid of it.  We do use
-        // `kill_on_drop()`, but here that's only useful for the case where
-        // `elapsed` receives a Ctrl-C, which would normally leave us without a
-        // chance to clean up and, 99% of the time, will be delivered to the
-        // child as well.
+        // The child process may still be running, so get rid of it before
+        // running the teardown.  We do use `kill_on_drop()`, but that only
+        // takes effect once we're done with everything else.
         if let Err(e) = elapsing.p.kill().await {
             let _ = writeln!(
                 elapsing.stderr.lock(),
@@ -265,372 +1870,3574 @@ async fn run(app: Elapsed) -> Result<ExitCode, Error> {
             );
         }
     }
-    r
+    if let Some(teardown) = &app.teardown {
+        match shell_command(teardown).status().await {
+            Ok(rc) if rc.success() => (),
+            Ok(rc) => {
+                let _ = elapsing.write_warning(&format!("--teardown command failed: {rc}"));
+            }
+            Err(e) => {
+                let _ = elapsing.write_warning(&format!("failed to run --teardown command: {e}"));
+            }
+        }
+    }
+    elapsing.sinks.finish()?;
+    match r {
+        Err(Error::Signal(_)) if app.quiet_errors => Ok(ExitCode::from(1)),
+        r => r.map(ExitCode::from),
+    }
 }
 
 struct Elapsing {
-    statline: StatusLine,
+    /// The status line, shared with the ticker thread
+    statline: Arc<Mutex<SharedStatus>>,
+    start: Instant,
+    /// A clock that is stopped whenever the command is suspended, for
+    /// telling how long it spent suspended
+    clock: PausableClock,
+    /// Leave the time during which the command was suspended off the status
+    /// line & the total, for `--exclude-suspended`
+    exclude_suspended: bool,
+    /// The wall-clock time at which the command was started, along with any
+    /// jumps in the system clock seen since then
+    wall_clock: WallClock,
     p: Child,
     pout: ByteLines<ChildOutput>,
     perr: ByteLines<ChildOutput>,
     stdout: io::Stdout,
     stderr: io::Stderr,
     stdout_is_tty: bool,
-    ticker: Interval,
+    color_stderr: bool,
+    ticker: Ticker,
+    /// The number of missed ticks as of the last tick that was picked up
+    missed_ticks: u64,
+    /// The destinations that receive every line of output & the events of
+    /// the run: the `--line-timings` file, the `--slowlog` file, the
+    /// `--tee-fd` file descriptor, and `--porcelain`/`--json` records, in
+    /// that order
+    sinks: Sinks<Error>,
+    /// The most recent lines of output, kept for `--mail`
+    mail_tail: Option<mail::Tail>,
+    gathered_stderr: Option<Vec<Vec<u8>>>,
+    /// Partial lines of stdout & stderr that were relayed early as prompts,
+    /// held back from `observe_line()` until the rest of the line arrives
+    held_stdout: Vec<u8>,
+    held_stderr: Vec<u8>,
+    pause: Pause,
+    last_partials: (usize, usize),
+    /// True if the child process has closed both its stdout and its stderr
+    output_closed: bool,
+    /// True if whatever was reading our stdout has gone away (e.g., `elapsed
+    /// cmd | head`), after which the child process's stdout is discarded
+    stdout_broken: bool,
+    /// True if `--json` records are being written to stderr, in place of the
+    /// command's stderr & our warnings
+    json_to_stderr: bool,
+    fail_on_stderr: Option<u8>,
+    exit_map: Vec<ExitMapping>,
+    /// Amount of output received from the child process on each stream
+    counts: OutputCounts,
+    /// When output was last received from the child process (or when it was
+    /// started, if there hasn't been any output yet)
+    last_output: Instant,
+    /// The longest gap between receipts of output seen so far, not counting
+    /// the current one
+    longest_silence: Duration,
+    /// The gaps between consecutive lines of output, tracked only for
+    /// `--summary`
+    line_gaps: Option<LineGaps>,
+    /// How long the command is expected to run for, from `--expect`
+    expected: Option<Duration>,
+    /// The percentage of its work that the command last reported having
+    /// done via an OSC 9;4 escape sequence
+    progress: Option<u8>,
+    /// Remove OSC 9;4 escape sequences from the command's output instead of
+    /// passing them through
+    strip_progress: bool,
+    /// The resource usage of the child process, sampled when it was reaped
+    child_usage: Option<ChildUsage>,
+    /// The resource usage of `elapsed`'s other child processes that had been
+    /// reaped by the time the command was spawned, to be subtracted from
+    /// `child_usage`
+    usage_baseline: Option<ChildUsage>,
+    success_regex: Option<Pattern>,
+    failure_regex: Option<Pattern>,
+    /// True if a line of output matched `success_regex`
+    success_matched: bool,
+    /// True if a line of output matched `failure_regex`
+    failure_matched: bool,
+    redactor: Redactor,
+    /// Whether to also mask redacted text in the output relayed to the
+    /// terminal
+    redact_relay: bool,
+    stopwatches: Vec<Stopwatch>,
+    /// The systemd notification socket, for as long as the command is still
+    /// starting up under `--systemd-extend`
+    systemd: Option<SystemdNotifier>,
+    systemd_ready: Option<Pattern>,
+    systemd_ticker: Interval,
+    /// True if `proc_stats` needs to be sampled on every tick
+    sample_procs: bool,
+    proc_stats: Option<ProcStats>,
+    stuck: StuckDetector,
+    /// Tracks the rate of output for `--auto-hide-at`
+    flood: Option<FloodDetector>,
+    /// What the command's process was waiting on at each tick, tracked only
+    /// for `--wait-reasons`
+    wait_reasons: Option<WaitReasons>,
+    /// Checksums of the output read & written, tracked only for
+    /// `--verify-passthrough`
+    passthrough: Option<PassthroughCheck>,
+    /// Prefixes lines of output with timestamps for `--timestamps=<STYLE>`
+    stamper: Option<LineStamper>,
+    warn_fds: Option<usize>,
+    /// True if the command's process's open file descriptor count is currently
+    /// above `warn_fds` and this has already been reported
+    fds_exceeded: bool,
+    max_descendants: Option<usize>,
+    kill_on_max_descendants: bool,
+    /// True if the command's number of descendant processes is currently
+    /// above `max_descendants` and this has already been reported
+    descendants_exceeded: bool,
+    watch_paths: Vec<PathWatcher>,
+    watch_disks: Vec<DiskWatcher>,
+    min_free: MinFree,
+    on_low_disk: Option<OsString>,
+    periodic: Option<PeriodicHook>,
+    /// The name of the terminal user variable to publish the status text as
+    user_var: Option<String>,
+    tmux: Option<Tmux>,
+    /// The `--auto-tty` settings, as long as the command may still be
+    /// restarted
+    #[cfg(unix)]
+    auto_tty: Option<AutoTty>,
+    /// The signals to send to stop the command when `elapsed` is cancelled or
+    /// interrupted
+    #[cfg(unix)]
+    term_sequence: TermSequence,
+    /// True if `elapsed` was cancelled by `SIGTERM`
+    cancelled: bool,
+    /// How long to let the command run before stopping it
+    timeout: Option<Duration>,
+    /// True if the command was stopped for exceeding `timeout`
+    timed_out: bool,
+    /// True from when the command is stopped for exceeding `timeout` until
+    /// it exits
+    killing: bool,
+    /// True if a signal received by `elapsed` has been relayed to the command
+    relayed: bool,
 }
 
-impl Elapsing {
-    async fn event_loop(&mut self) -> Result<ExitCode, Error> {
-        let mut stdout_eof = false;
-        let mut stderr_eof = false;
-        let mut exit_code = None;
-        loop {
-            if stdout_eof && stderr_eof {
-                if let Some(rc) = exit_code {
-                    self.statline.clear()?;
-                    return Ok(rc);
-                }
+/// State for restarting the command via a pseudo-terminal for `--auto-tty`
+#[cfg(unix)]
+#[derive(Debug)]
+struct AutoTty {
+    /// How long the command must go without output while using CPU before
+    /// it's restarted
+    window: Duration,
+    /// The arguments to restart the command with
+    app: Elapsed,
+    /// The command's CPU time as of the previous tick
+    last_cpu_time: Duration,
+}
+
+/// A listener for `SIGTERM` sent to `elapsed`.  On platforms without
+/// `SIGTERM`, or if the listener couldn't be set up, it never fires.
+#[derive(Debug)]
+struct Terminate {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Terminate {
+    fn new() -> Terminate {
+        Terminate {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).ok(),
+        }
+    }
+
+    /// Wait for `elapsed` to receive `SIGTERM`
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
             }
-            tokio::select! {
-                _ = self.ticker.tick() => {
-                    self.statline.clear()?;
-                    self.statline.print()?;
-                },
-                r = self.pout.next_line(), if !stdout_eof => {
-                    if self.stdout_is_tty {
-                        self.statline.clear()?;
-                    }
-                    if let Some(line) = r.map_err(Error::ReadStdout)? {
-                        self.stdout.lock().write_all(&line).map_err(Error::Write)?;
-                    } else {
-                        stdout_eof = true;
-                    }
-                    if self.stdout_is_tty {
-                        self.statline.print()?;
+        }
+        std::future::pending::<()>().await;
+    }
+}
+
+/// The type of the signals that [`Relay`] listens for
+#[cfg(unix)]
+type RelayedSignal = nix::sys::signal::Signal;
+
+/// The type of the signals that [`Relay`] listens for (of which there are
+/// none on this platform)
+#[cfg(not(unix))]
+type RelayedSignal = std::convert::Infallible;
+
+/// A listener for the signals that `elapsed` passes on to the command as-is
+/// (see [`Elapsing::relay_signal()`]): `SIGHUP` and `SIGQUIT`.  On platforms without these signals, or if none of
+/// the listeners could be set up, it never fires.
+#[derive(Debug)]
+struct Relay {
+    #[cfg(unix)]
+    listeners: Vec<(RelayedSignal, tokio::signal::unix::Signal)>,
+}
+
+impl Relay {
+    fn new() -> Relay {
+        Relay {
+            #[cfg(unix)]
+            listeners: {
+                use nix::sys::signal::Signal;
+                use tokio::signal::unix::{SignalKind, signal};
+                [
+                    (Signal::SIGHUP, SignalKind::hangup()),
+                    (Signal::SIGQUIT, SignalKind::quit()),
+                ]
+                .into_iter()
+                .filter_map(|(sig, kind)| Some((sig, signal(kind).ok()?)))
+                .collect()
+            },
+        }
+    }
+
+    /// Wait for `elapsed` to receive one of the relayed signals and return it
+    async fn recv(&mut self) -> RelayedSignal {
+        #[cfg(unix)]
+        while !self.listeners.is_empty() {
+            let (i, r) = std::future::poll_fn(|cx| {
+                for (i, (_, listener)) in self.listeners.iter_mut().enumerate() {
+                    if let Poll::Ready(r) = listener.poll_recv(cx) {
+                        return Poll::Ready((i, r));
                     }
                 }
-                r = self.perr.next_line(), if !stderr_eof => {
-                    self.statline.clear()?;
-                    if let Some(line) = r.map_err(Error::ReadStderr)? {
-                        self.stderr.lock().write_all(&line).map_err(Error::Write)?;
+                Poll::Pending
+            })
+            .await;
+            if r.is_some() {
+                return self.listeners[i].0;
+            }
+            // This listener has shut down and won't fire again.
+            drop(self.listeners.remove(i));
+        }
+        std::future::pending().await
+    }
+}
+
+/// Whether & why display of the status line is currently paused
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Pause {
+    #[default]
+    None,
+    /// The child process wrote a partial line that looks like a prompt
+    Prompt,
+    /// The child process's pseudo-terminal has echo disabled
+    NoEcho,
+    /// The command's output is split into records on something other than
+    /// newlines, and the last record written to the terminal did not end with
+    /// a newline, leaving the cursor in the middle of a line
+    MidLine,
+    /// The command is producing output faster than the `--auto-hide-at` rate
+    Flood,
+}
+
+impl Elapsing {
+    async fn event_loop(&mut self) -> Result<u8, Error> {
+        let mut stdout_eof = false;
+        let mut stderr_eof = false;
+        let mut exit_code = None;
+        let mut drain_deadline = None;
+        // The Ctrl-C listener is created once up front (and recreated right
+        // after it fires) so that an interrupt received while handling
+        // another event isn't missed.
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+        let mut ctrl_c_failed = false;
+        let mut interrupted = false;
+        // When to stop the command with the `--term-sequence` if it's still
+        // running after a Ctrl-C
+        let mut interrupt_deadline = None;
+        let mut sigterm = Terminate::new();
+        let mut relay = Relay::new();
+        let mut suspension = SuspendWatcher::new();
+        // The index of the current step of the `--term-sequence` and when to
+        // move on to the next one
+        let mut escalation = None;
+        let deadline = self
+            .timeout
+            .map(|d| tokio::time::Instant::from_std(self.start + d));
+        loop {
+            if stdout_eof && stderr_eof {
+                if let Some(rc) = exit_code {
+                    self.clear_status();
+                    return Ok(self.final_exit_code(rc));
+                }
+            }
+            let pid = self.p.id();
+            // Branches are polled in order so that Ctrl-C and the command's
+            // exit are acted on promptly even when the command is producing
+            // output faster than we can relay it.
+            tokio::select! {
+                biased;
+                r = &mut ctrl_c, if !ctrl_c_failed => {
+                    if r.is_err() {
+                        // Don't poll the completed future again.
+                        ctrl_c_failed = true;
+                    } else if interrupted || exit_code.is_some() {
+                        debug!("received Ctrl-C; exiting");
+                        self.clear_status();
+                        if exit_code.is_none() {
+                            // Don't leave the command running through the
+                            // rest of our shutdown.
+                            debug!("killing command");
+                            if let Err(e) = self.p.kill().await {
+                                let _ = writeln!(
+                                    self.stderr.lock(),
+                                    "elapsed: failed to kill child process: {e}"
+                                );
+                            }
+                        }
+                        return Ok(1);
                     } else {
-                        stderr_eof = true;
+                        interrupted = true;
+                        self.interrupt();
+                        interrupt_deadline = Some(tokio::time::Instant::now() + INTERRUPT_GRACE);
+                        ctrl_c.set(tokio::signal::ctrl_c());
+                    }
+                }
+                () = sigterm.recv(), if exit_code.is_none() && !self.cancelled && !self.timed_out => {
+                    debug!("received SIGTERM; stopping command");
+                    self.cancelled = true;
+                    escalation = self.escalate(0);
+                }
+                sig = relay.recv(), if exit_code.is_none() => {
+                    self.relay_signal(sig);
+                }
+                change = suspension.recv(pid), if exit_code.is_none() => {
+                    self.suspension_changed(change);
+                }
+                () = sleep_until(deadline.unwrap_or_else(tokio::time::Instant::now)), if deadline.is_some() && exit_code.is_none() && !self.cancelled && !self.timed_out => {
+                    debug!("--timeout reached; stopping command");
+                    self.timed_out = true;
+                    self.killing = true;
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            escalation = self.escalate(0);
+                        } else {
+                            let _ = self.p.start_kill();
+                        }
+                    }
+                    self.clear_status();
+                    self.print_status();
+                }
+                () = sleep_until(interrupt_deadline.unwrap_or_else(tokio::time::Instant::now)), if interrupt_deadline.is_some() && exit_code.is_none() && !self.cancelled && !self.timed_out => {
+                    debug!("command still running {INTERRUPT_GRACE:?} after Ctrl-C; stopping command");
+                    interrupt_deadline = None;
+                    cfg_if! {
+                        if #[cfg(unix)] {
+                            escalation = self.escalate(0);
+                        } else {
+                            let _ = self.p.start_kill();
+                        }
                     }
-                    self.statline.print()?;
+                }
+                () = sleep_until(escalation.map_or_else(tokio::time::Instant::now, |(_, t)| t)), if escalation.is_some() && exit_code.is_none() => {
+                    let next = escalation.map_or(0, |(i, _)| i + 1);
+                    debug!("command still running; escalating");
+                    escalation = self.escalate(next);
                 }
                 r = self.p.wait(), if exit_code.is_none() => {
-                    self.statline.clear()?;
+                    self.clear_status();
+                    self.killing = false;
                     let rc = r.map_err(Error::Wait)?;
+                    debug!("command exited: {rc}");
+                    if self.systemd_ready.is_none() && rc.success() {
+                        self.notify_systemd_ready()?;
+                    }
+                    self.child_usage = procinfo::child_usage().map(|usage| {
+                        self.usage_baseline
+                            .map_or(usage, |baseline| usage.since(&baseline))
+                    });
                     if let Some(ret) = rc.code() {
-                        let ret = u8::try_from(ret & 255).unwrap_or(1);
-                        exit_code = Some(ExitCode::from(ret));
+                        exit_code = Some(u8::try_from(ret & 255).unwrap_or(1));
+                        drain_deadline = Some(tokio::time::Instant::now() + DRAIN_TIMEOUT);
+                    } else if self.cancelled || self.timed_out {
+                        // Dying from one of the signals sent to stop it is
+                        // expected.
+                        exit_code = Some(CANCELLED_EXIT_CODE);
+                        drain_deadline = Some(tokio::time::Instant::now() + DRAIN_TIMEOUT);
+                    } else if self.relayed {
+                        // The command was killed by (or in response to) a
+                        // signal that we passed on to it, so exit the way a
+                        // shell would report that.
+                        exit_code = Some(signal_exit_code(rc));
+                        drain_deadline = Some(tokio::time::Instant::now() + DRAIN_TIMEOUT);
                     } else {
                         return Err(Error::Signal(rc));
                     }
-                    self.statline.print()?;
+                    self.print_status();
+                }
+                () = sleep_until(drain_deadline.unwrap_or_else(tokio::time::Instant::now)), if drain_deadline.is_some() => {
+                    if let Some(rc) = exit_code {
+                        debug!(
+                            "output still open {DRAIN_TIMEOUT:?} after exit; no longer waiting for EOF"
+                        );
+                        self.clear_status();
+                        return Ok(self.final_exit_code(rc));
+                    }
+                }
+                () = self.ticker.tick() => {
+                    debug!("tick");
+                    let missed = self.ticker.missed();
+                    if missed > self.missed_ticks {
+                        debug!(
+                            "event loop stalled; {} tick(s) missed since the last one",
+                            missed - self.missed_ticks
+                        );
+                        self.missed_ticks = missed;
+                    }
+                    self.wall_clock.check(self.start.elapsed());
+                    self.sinks.tick(self.start.elapsed())?;
+                    self.sample_proc_stats()?;
+                    #[cfg(unix)]
+                    if exit_code.is_none() && self.auto_tty_due() {
+                        self.restart_via_pty().await?;
+                        stdout_eof = false;
+                        stderr_eof = false;
+                    }
+                    let now = Instant::now();
+                    for watcher in &mut self.watch_paths {
+                        watcher.sample(now);
+                    }
+                    self.check_disks()?;
+                    if exit_code.is_none() {
+                        self.run_periodic()?;
+                    }
+                    self.check_flood(false);
+                    self.check_for_prompt()?;
+                    self.clear_status();
+                    self.print_status();
+                    self.update_user_var()?;
+                    self.update_tmux_title()?;
+                },
+                _ = self.systemd_ticker.tick(), if self.systemd.is_some() && exit_code.is_none() => {
+                    self.extend_systemd_timeout()?;
+                }
+                r = self.pout.next_line(), if !stdout_eof => {
+                    if self.stdout_is_tty {
+                        self.clear_status();
+                    }
+                    if let Some(line) = r.map_err(Error::ReadStdout)? {
+                        debug!("read {} bytes from stdout", line.len());
+                        self.note_read(self.stdout_stream(), &line);
+                        let line = self.check_progress(line);
+                        let (line, masked) = self.redact(line);
+                        self.observe_record(
+                            self.stdout_stream(),
+                            masked.as_deref().unwrap_or(&line),
+                        )?;
+                        let line = self.stamp(self.stdout_stream(), line);
+                        // Stdout is line-buffered, so records not ending in a
+                        // newline need to be flushed explicitly in order to be
+                        // relayed promptly.
+                        self.relay_stdout(&line, !line.ends_with(b"\n"))?;
+                        self.after_record(&line, self.stdout_is_tty);
+                    } else {
+                        debug!("reached EOF on stdout");
+                        self.observe_held(self.stdout_stream())?;
+                        stdout_eof = true;
+                        self.output_closed = stderr_eof;
+                    }
+                    if self.stdout_is_tty {
+                        self.print_status();
+                    }
                 }
-                r = tokio::signal::ctrl_c() => {
-                    if r.is_ok() {
-                        self.statline.clear()?;
-                        return Ok(ExitCode::FAILURE);
-                    } // Else: Keep your mouth shut?
+                r = self.perr.next_line(), if !stderr_eof => {
+                    self.clear_status();
+                    if let Some(line) = r.map_err(Error::ReadStderr)? {
+                        debug!("read {} bytes from stderr", line.len());
+                        self.note_read(Stream::Stderr, &line);
+                        let line = self.check_progress(line);
+                        let (line, masked) = self.redact(line);
+                        self.observe_record(Stream::Stderr, masked.as_deref().unwrap_or(&line))?;
+                        let line = self.stamp(Stream::Stderr, line);
+                        if let Some(gathered) = &mut self.gathered_stderr {
+                            gathered.push(line);
+                        } else if !self.json_to_stderr {
+                            write_stderr_line(&mut self.stderr.lock(), &line, self.color_stderr)
+                                .map_err(Error::Write)?;
+                            self.note_written(Stream::Stderr, &line);
+                            self.after_record(&line, true);
+                        }
+                    } else {
+                        debug!("reached EOF on stderr");
+                        self.observe_held(Stream::Stderr)?;
+                        stderr_eof = true;
+                        self.output_closed = stdout_eof;
+                    }
+                    self.print_status();
                 }
             }
         }
     }
-}
 
-#[derive(Debug)]
-enum StatusLine {
-    Active {
-        format: Format,
-        start: Instant,
-        err: io::Stderr,
-    },
-    Inactive,
-}
+    /// Send the command the signal for step `i` of the `--term-sequence`,
+    /// returning `i` along with when to move on to the next step, if there is
+    /// one
+    fn escalate(&self, i: usize) -> Option<(usize, tokio::time::Instant)> {
+        cfg_if! {
+            if #[cfg(unix)] {
+                let step = self.term_sequence.get(i)?;
+                debug!("sending {} to command", step.signal);
+                if let Some(pid) = self.p.id().and_then(|pid| i32::try_from(pid).ok()) {
+                    let _ = nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), step.signal);
+                }
+                Some((i, tokio::time::Instant::now() + step.wait?))
+            } else {
+                // There's no `SIGTERM` to be cancelled by on this platform.
+                let _ = i;
+                None
+            }
+        }
+    }
 
-impl StatusLine {
-    fn new(format: Format) -> StatusLine {
-        let err = io::stderr();
-        if err.is_terminal() {
-            StatusLine::Active {
-                format,
-                start: Instant::now(),
-                err,
+    /// Pass a signal received by `elapsed` on to the command, unless the
+    /// terminal has already delivered it there
+    fn relay_signal(&mut self, sig: RelayedSignal) {
+        cfg_if! {
+            if #[cfg(unix)] {
+                self.relayed = true;
+                if self.shares_terminal_signals() {
+                    // Sending it again could cut short the command's cleanup.
+                    debug!("received {sig}; command already has it from the terminal");
+                    return;
+                }
+                debug!("received {sig}; relaying to command");
+                if let Some(pid) = self.p.id().and_then(|pid| i32::try_from(pid).ok()) {
+                    let _ = nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), sig);
+                }
+            } else {
+                match sig {}
+            }
+        }
+    }
+
+    /// Return true if signals generated by `elapsed`'s terminal (Ctrl-C,
+    /// Ctrl-\, and hangups, which go to the whole foreground process group)
+    /// reach the command without our help.  This is the case when `elapsed`
+    /// is in the foreground process group and the command shares its process
+    /// group rather than running in its own session on a pty.  When `elapsed`
+    /// was signalled some other way, e.g., by a supervisor with `kill`, the
+    /// command has to be sent the signal explicitly.
+    #[cfg(unix)]
+    fn shares_terminal_signals(&self) -> bool {
+        use nix::unistd::{getpgrp, tcgetpgrp};
+        if matches!(self.pout.get_ref(), ChildOutput::Pty(..)) {
+            return false;
+        }
+        let foreground = tcgetpgrp(io::stdin()).or_else(|_| tcgetpgrp(io::stderr()));
+        foreground.is_ok_and(|pgrp| pgrp == getpgrp())
+    }
+
+    /// Make sure that a Ctrl-C received by `elapsed` reaches the command
+    fn interrupt(&mut self) {
+        cfg_if! {
+            if #[cfg(unix)] {
+                self.relay_signal(nix::sys::signal::Signal::SIGINT);
+            } else {
+                // The console delivers Ctrl-C to every process attached to
+                // it, so the command already has it.
+                debug!("received Ctrl-C; waiting for command to exit");
+                self.relayed = true;
+            }
+        }
+    }
+
+    /// If `elapsed` stopped the command itself, return why, for reporting in
+    /// place of the command's exit status
+    fn stopped_because(&self) -> Option<&'static str> {
+        if self.timed_out {
+            Some("timed out")
+        } else if self.cancelled {
+            Some("cancelled")
+        } else {
+            None
+        }
+    }
+
+    /// Determine the exit code for `elapsed` given the child process's exit
+    /// code
+    fn final_exit_code(&self, rc: u8) -> u8 {
+        if self.timed_out {
+            debug!("output received: {}", self.counts);
+            debug!("timed out; final exit code: {TIMED_OUT_EXIT_CODE}");
+            return TIMED_OUT_EXIT_CODE;
+        }
+        if self.cancelled {
+            debug!("output received: {}", self.counts);
+            debug!("cancelled; final exit code: {CANCELLED_EXIT_CODE}");
+            return CANCELLED_EXIT_CODE;
+        }
+        let rc = self
+            .exit_map
+            .iter()
+            .find(|m| m.from == rc)
+            .map_or(rc, |m| m.to);
+        let rc = if self.failure_matched {
+            1
+        } else if self.success_regex.is_some() {
+            u8::from(!self.success_matched)
+        } else {
+            rc
+        };
+        let rc = match self.fail_on_stderr {
+            Some(code) if rc == 0 && self.counts.stderr.bytes > 0 => code,
+            _ => rc,
+        };
+        debug!("output received: {}", self.counts);
+        debug!("final exit code: {rc}");
+        rc
+    }
+
+    /// Return the stream to attribute output read from the child process's
+    /// stdout to
+    fn stdout_stream(&self) -> Stream {
+        cfg_if! {
+            if #[cfg(unix)] {
+                if matches!(self.pout.get_ref(), ChildOutput::Merged(_)) {
+                    return Stream::Merged;
+                }
+            }
+        }
+        Stream::Stdout
+    }
+
+    /// Return how long the command has been running for, less the time it
+    /// spent suspended if `--exclude-suspended` was given
+    fn run_time(&self) -> Duration {
+        if self.exclude_suspended {
+            self.clock.elapsed()
+        } else {
+            self.start.elapsed()
+        }
+    }
+
+    /// Return the longest stretch of time so far during which no output was
+    /// received from the child process
+    fn longest_silence(&self) -> Duration {
+        self.longest_silence.max(self.last_output.elapsed())
+    }
+
+    /// Write a record of the child process's output to stdout, flushing
+    /// afterwards if `flush` is true.  If the reader on the other end of
+    /// stdout has gone away, stop relaying stdout from then on instead of
+    /// failing, so that the command can still be run to completion and timed.
+    fn relay_stdout(&mut self, record: &[u8], flush: bool) -> Result<(), Error> {
+        if self.stdout_broken {
+            return Ok(());
+        }
+        let mut out = self.stdout.lock();
+        let r = out
+            .write_all(record)
+            .and_then(|()| if flush { out.flush() } else { Ok(()) });
+        drop(out);
+        match r {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                debug!("stdout closed by reader; no longer relaying stdout");
+                self.stdout_broken = true;
+                Ok(())
+            }
+            Ok(()) => {
+                self.note_written(Stream::Stdout, record);
+                Ok(())
+            }
+            Err(e) => Err(Error::Write(e)),
+        }
+    }
+
+    /// Take note of any progress reports (OSC 9;4 escape sequences) in
+    /// `record`, returning it with them removed if `--strip-progress` was
+    /// given
+    fn check_progress(&mut self, record: Vec<u8>) -> Vec<u8> {
+        if !record.contains(&b'\x1B') {
+            return record;
+        }
+        let sequences = progress::scan(&record);
+        for seq in &sequences {
+            match seq.report {
+                Report::Percent(pct) => self.progress = Some(pct),
+                Report::Unknown => self.progress = None,
+                Report::Unchanged => (),
+            }
+        }
+        if sequences.is_empty() {
+            return record;
+        }
+        debug!(
+            "command reported progress: {}",
+            self.progress
+                .map_or_else(|| String::from("unknown"), |pct| format!("{pct}%"))
+        );
+        if self.strip_progress {
+            progress::strip(&record, &sequences)
+        } else {
+            record
+        }
+    }
+
+    /// Mask matches of the redaction patterns in `record`.  The record to
+    /// relay is returned first; it is only masked if `--redact-relay` was
+    /// given.  If it wasn't, the masked version for the sinks & regexes is
+    /// returned second.
+    fn redact(&self, record: Vec<u8>) -> (Vec<u8>, Option<Vec<u8>>) {
+        if self.redact_relay {
+            (self.redactor.redact(record), None)
+        } else if self.redactor.is_empty() {
+            (record, None)
+        } else {
+            let masked = self.redactor.redact(record.clone());
+            (record, Some(masked))
+        }
+    }
+
+    /// If `--timestamps=<STYLE>` is in effect, prefix `record` (received on
+    /// `stream`) with a timestamp if it starts a new line
+    fn stamp(&mut self, stream: Stream, record: Vec<u8>) -> Vec<u8> {
+        match &mut self.stamper {
+            Some(stamper) => stamper.stamp(
+                stream,
+                record,
+                self.pout.delimiter,
+                self.start.elapsed(),
+                SystemTime::now(),
+            ),
+            None => record,
+        }
+    }
+
+    /// Note that `record` was read from the command on `stream`, for
+    /// `--verify-passthrough`
+    fn note_read(&mut self, stream: Stream, record: &[u8]) {
+        if let Some(check) = &mut self.passthrough {
+            check.read(stream, record);
+        }
+    }
+
+    /// Note that `record` was written out on behalf of `stream`, for
+    /// `--verify-passthrough`
+    fn note_written(&mut self, stream: Stream, record: &[u8]) {
+        if let Some(check) = &mut self.passthrough {
+            check.written(stream, record);
+        }
+    }
+
+    /// If `--verify-passthrough` is in effect, print a warning for each output
+    /// stream on which what was written differs from what was read from the
+    /// command.  This should never happen.
+    fn report_passthrough(&mut self) -> Result<(), Error> {
+        let Some(check) = self.passthrough else {
+            return Ok(());
+        };
+        for (name, pt) in [("stdout", check.stdout), ("stderr", check.stderr)] {
+            debug!(
+                "passthrough on {name}: read {}; wrote {}",
+                pt.read, pt.written
+            );
+            if pt.matches() {
+                continue;
+            }
+            if name == "stdout" && self.stdout_broken {
+                debug!("stdout was closed by reader; not verifying passthrough on stdout");
+                continue;
+            }
+            if name == "stderr" && self.json_to_stderr {
+                debug!(
+                    "stderr was only written as --json records; not verifying passthrough on stderr"
+                );
+                continue;
+            }
+            self.write_warning(&format!(
+                "output written to {name} does not match output read from command (read {}; wrote {})",
+                pt.read, pt.written
+            ))?;
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> MutexGuard<'_, SharedStatus> {
+        lock_status(&self.statline)
+    }
+
+    /// Return the measurements of the run that the status line is rendered
+    /// from
+    fn readings(&self) -> Readings {
+        let first_read = match (self.pout.first_read(), self.perr.first_read()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Readings {
+            proc_stats: self.proc_stats,
+            first_output: first_read.map(|t| t.saturating_duration_since(self.start)),
+            expected: self.expected,
+            progress: self.progress,
+            missed_ticks: self.ticker.missed(),
+            deadline: self.timeout.map(|d| self.start + d),
+        }
+    }
+
+    /// Stop or restart the clocks when the command is stopped or continued
+    fn suspension_changed(&mut self, change: Change) {
+        let now = Instant::now();
+        self.clear_status();
+        match change {
+            Change::Stopped => {
+                debug!("command stopped");
+                self.clock.pause(now);
+                if self.exclude_suspended {
+                    self.status().line.pause(now);
+                }
+            }
+            Change::Continued => {
+                debug!("command continued");
+                self.clock.resume(now);
+                if self.exclude_suspended {
+                    self.status().line.resume(now);
+                }
+            }
+        }
+        self.print_status();
+    }
+
+    fn clear_status(&self) {
+        if self.pause == Pause::None {
+            debug!("clearing status line");
+            let mut status = self.status();
+            status.shown = None;
+            let r = status.line.clear();
+            self.check_drawn(&mut status, r);
+        }
+    }
+
+    fn print_status(&self) {
+        if self.pause != Pause::None {
+            return;
+        }
+        let mut note = String::new();
+        if let Some(gathered) = self.gathered_stderr.as_ref().filter(|g| !g.is_empty()) {
+            let n = gathered.len();
+            let _ = write!(
+                note,
+                " [stderr: {} line{}]",
+                format_count(u64::try_from(n).unwrap_or(u64::MAX)),
+                if n == 1 { "" } else { "s" }
+            );
+        }
+        for watcher in &self.watch_paths {
+            let _ = write!(note, " [{watcher}]");
+        }
+        for watcher in &self.watch_disks {
+            let _ = write!(note, " [{watcher}]");
+        }
+        if self.output_closed {
+            note.push_str(" [output closed]");
+        }
+        if self.clock.is_paused() {
+            note.push_str(" [suspended]");
+        }
+        if self.killing {
+            if self.color_stderr && self.status().line.is_ansi() {
+                note.push_str(" \x1B[31m[killing...]\x1B[m");
+            } else {
+                note.push_str(" [killing...]");
+            }
+        }
+        debug!("printing status line with note {note:?}");
+        let mut status = self.status();
+        let readings = self.readings();
+        let r = status.line.print(readings, &note);
+        status.shown = Some((readings, note));
+        self.check_drawn(&mut status, r);
+    }
+
+    /// Handle the result of drawing or erasing the status line, deactivating
+    /// it if that failed
+    fn check_drawn(&self, status: &mut SharedStatus, r: Result<(), Error>) {
+        if status.check_drawn(r, self.start.elapsed()) {
+            if let Some((_, e)) = &status.lost {
+                debug!("failed to draw status line: {e}; no longer displaying it");
+            }
+        }
+    }
+
+    /// Set the `--user-var` terminal user variable (if any) to the current
+    /// status text
+    fn update_user_var(&self) -> Result<(), Error> {
+        if let Some(name) = &self.user_var {
+            if self.status().line.is_active() {
+                let text = self.status().line.render(self.readings());
+                term::set_user_var(self.stderr.lock(), name, &text).map_err(Error::Write)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the tmux pane title (if `--tmux` is in effect) to the current status
+    /// text.  If this fails, print a warning and stop updating the title.
+    fn update_tmux_title(&mut self) -> Result<(), Error> {
+        if let Some(tmux) = &self.tmux {
+            let title = self
+                .status()
+                .line
+                .render(self.readings())
+                .replace('\n', " ");
+            if let Err(e) = tmux.set_title(&title) {
+                self.tmux = None;
+                self.warn(&format!("failed to set tmux pane title: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the `--user-var` terminal user variable (if any) to the empty
+    /// string so that it doesn't show a stale time after the command exits
+    fn clear_user_var(&self) -> Result<(), Error> {
+        if let Some(name) = &self.user_var {
+            if self.status().line.is_active() {
+                term::set_user_var(self.stderr.lock(), name, "").map_err(Error::Write)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Update `proc_stats` with fresh statistics about the command's process
+    /// tree, if they're needed & available, and warn if the command's process
+    /// appears to be stuck
+    fn sample_proc_stats(&mut self) -> Result<(), Error> {
+        let Some(pid) = self.p.id() else {
+            return Ok(());
+        };
+        let state = if self.sample_procs {
+            let stats = procinfo::sample(pid).map(|st| st.with_peak_from(self.proc_stats));
+            if stats.is_some() {
+                self.proc_stats = stats;
+            }
+            if let Some((limit, fds)) = self.warn_fds.zip(stats.and_then(|st| st.fds)) {
+                if fds <= limit {
+                    self.fds_exceeded = false;
+                } else if !self.fds_exceeded {
+                    self.fds_exceeded = true;
+                    self.warn(&format!(
+                        "command has {fds} open file descriptors (more than {limit})"
+                    ))?;
+                }
+            }
+            if let Some((limit, n)) = self.max_descendants.zip(stats.map(|st| st.descendants)) {
+                if n <= limit {
+                    self.descendants_exceeded = false;
+                } else if !self.descendants_exceeded {
+                    self.descendants_exceeded = true;
+                    let msg = format!("command has {n} descendant processes (more than {limit})");
+                    if self.kill_on_max_descendants {
+                        self.warn(&format!("{msg}; killing it and all of its descendants"))?;
+                        procinfo::kill_tree(pid);
+                    } else {
+                        self.warn(&msg)?;
+                    }
+                }
+            }
+            stats.map(|st| st.state)
+        } else {
+            procinfo::state(pid)
+        };
+        if let Some(st) = self.stuck.observe(state, Instant::now()) {
+            self.warn(&format!(
+                "command has been {} (state {st}) for a while; it may be wedged",
+                procinfo::describe_state(st),
+            ))?;
+        }
+        if let Some(reasons) = &mut self.wait_reasons {
+            if let Some(wchan) = procinfo::wait_channel(pid) {
+                reasons.record(&wchan);
             }
+        }
+        Ok(())
+    }
+
+    /// Returns true if `--auto-tty` is in effect and the command has used CPU
+    /// since the previous tick without having produced any output within the
+    /// window.  Once the command produces output, `--auto-tty` is disabled,
+    /// as restarting the command then would duplicate that output.
+    #[cfg(unix)]
+    fn auto_tty_due(&mut self) -> bool {
+        let Some(auto) = &mut self.auto_tty else {
+            return false;
+        };
+        if self.last_output != self.start
+            || self.pout.partial_len() != 0
+            || self.perr.partial_len() != 0
+        {
+            debug!("command produced output; no longer considering restarting it via a pty");
+            self.auto_tty = None;
+            return false;
+        }
+        let Some(cpu_time) = self.proc_stats.map(|st| st.cpu_time) else {
+            return false;
+        };
+        let busy = cpu_time > auto.last_cpu_time;
+        auto.last_cpu_time = cpu_time;
+        busy && self.start.elapsed() >= auto.window
+    }
+
+    /// Kill the command and start it again via a pseudo-terminal, for
+    /// `--auto-tty`
+    #[cfg(unix)]
+    async fn restart_via_pty(&mut self) -> Result<(), Error> {
+        let Some(auto) = self.auto_tty.take() else {
+            return Ok(());
+        };
+        self.warn(&format!(
+            "command has produced no output in {} while using CPU; restarting it via a pseudo-terminal in case it's buffering its output",
+            spoken_duration(auto.window)
+        ))?;
+        self.p.kill().await.map_err(Error::Restart)?;
+        // `kill()` also reaps the original process, so this leaves its usage
+        // out of the restarted command's.
+        self.usage_baseline = procinfo::child_usage();
+        let linebuf = if auto.app.line_buffer {
+            LineBuffering::new(true)
         } else {
-            StatusLine::Inactive
+            LineBuffering::default()
+        };
+        let (p, mut pout, mut perr) = auto.app.spawn_tty(&linebuf.env, !auto.app.merge)?;
+        debug!("restarted command as process {:?}", p.id());
+        pout.delimiter = self.pout.delimiter;
+        perr.delimiter = self.perr.delimiter;
+        self.p = p;
+        self.pout = pout;
+        self.perr = perr;
+        self.proc_stats = None;
+        self.last_partials = (0, 0);
+        Ok(())
+    }
+
+    /// Measure the free space on each `--watch-disk` filesystem, and warn &
+    /// run the `--on-low-disk` hook for any that have newly dropped below the
+    /// `--min-free` threshold
+    fn check_disks(&mut self) -> Result<(), Error> {
+        let mut low = Vec::new();
+        for watcher in &mut self.watch_disks {
+            if let Some(free) = watcher.check(self.min_free) {
+                low.push((watcher.path().to_path_buf(), free));
+            }
+        }
+        for (path, free) in low {
+            self.warn(&format!(
+                "only {} free on the filesystem containing {} (below {})",
+                format_bytes(free),
+                path.display(),
+                self.min_free
+            ))?;
+            if let Some(hook) = &self.on_low_disk {
+                // The hook is left to run on its own; Tokio reaps it in the
+                // background once it exits.
+                let r = shell_command(hook)
+                    .env("ELAPSED_DISK_PATH", &path)
+                    .env("ELAPSED_DISK_FREE", free.to_string())
+                    .stdin(Stdio::null())
+                    .spawn();
+                if let Err(e) = r {
+                    self.warn(&format!("failed to run --on-low-disk command: {e}"))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// If the `--periodic` command is due, run it, unless its last run is
+    /// still going
+    fn run_periodic(&mut self) -> Result<(), Error> {
+        let elapsed = self.start.elapsed();
+        let Some(hook) = &mut self.periodic else {
+            return Ok(());
+        };
+        if !hook.due(elapsed) {
+            return Ok(());
+        }
+        if hook.is_running() {
+            debug!("previous --periodic command still running; skipping");
+            return Ok(());
+        }
+        let lines = self.counts.stdout.lines + self.counts.stderr.lines;
+        let r = shell_command(OsStr::new(hook.command()))
+            .env(RUN_ID_ENV_VAR, run_id())
+            .env("ELAPSED_SECONDS", elapsed.as_secs().to_string())
+            .env("ELAPSED_LINES", lines.to_string())
+            .stdin(Stdio::null())
+            .spawn();
+        match r {
+            Ok(child) => {
+                hook.started(child);
+                debug!("ran --periodic command");
+            }
+            Err(e) => self.warn(&format!("failed to run --periodic command: {e}"))?,
+        }
+        Ok(())
+    }
+
+    /// Print a warning message to stderr above the status line
+    fn warn(&mut self, msg: &str) -> Result<(), Error> {
+        self.clear_status();
+        self.write_warning(msg)?;
+        self.print_status();
+        Ok(())
+    }
+
+    /// Give a warning message to the sinks and, unless `--json` records are
+    /// being written to stderr, print it to stderr
+    fn write_warning(&mut self, msg: &str) -> Result<(), Error> {
+        self.sinks.warning(msg)?;
+        if !self.json_to_stderr {
+            writeln!(self.stderr.lock(), "elapsed: warning: {msg}").map_err(Error::Write)?;
+        }
+        Ok(())
+    }
+
+    /// Tell systemd that the command is still starting up so that the service
+    /// doesn't get killed for exceeding its start timeout.  If this fails,
+    /// print a warning and stop sending notifications.
+    fn extend_systemd_timeout(&mut self) -> Result<(), Error> {
+        let Some(notifier) = &self.systemd else {
+            return Ok(());
+        };
+        if let Err(e) = notifier.extend_timeout(SYSTEMD_EXTEND_TIMEOUT) {
+            self.systemd = None;
+            self.clear_status();
+            writeln!(self.stderr.lock(), "elapsed: failed to notify systemd: {e}")
+                .map_err(Error::Write)?;
+            self.print_status();
+        }
+        Ok(())
+    }
+
+    /// Tell systemd that the command has finished starting up, and stop
+    /// extending the service's timeout
+    fn notify_systemd_ready(&mut self) -> Result<(), Error> {
+        let Some(notifier) = self.systemd.take() else {
+            return Ok(());
+        };
+        debug!("command has started up; notifying systemd");
+        if let Err(e) = notifier.ready() {
+            self.clear_status();
+            writeln!(self.stderr.lock(), "elapsed: failed to notify systemd: {e}")
+                .map_err(Error::Write)?;
+            self.print_status();
+        }
+        Ok(())
+    }
+
+    /// Check whether the child process appears to be waiting for input from
+    /// the user, and, if so, stop displaying the status line so that it
+    /// doesn't get in the way of the user's typing.
+    ///
+    /// If a partial line of output has been sitting unchanged in a buffer for
+    /// an entire tick, it's most likely a prompt, so it is written out.  When
+    /// running via a pseudo-terminal, the terminal having echo disabled (as is
+    /// done when prompting for a password) is also treated as a sign of
+    /// waiting for input.
+    fn check_for_prompt(&mut self) -> Result<(), Error> {
+        if !self.status().line.is_active() {
+            return Ok(());
+        }
+        let out_partial = if self.stdout_is_tty {
+            self.pout.partial_len()
+        } else {
+            0
+        };
+        let err_partial = if self.gathered_stderr.is_none() && !self.json_to_stderr {
+            self.perr.partial_len()
+        } else {
+            0
+        };
+        let partials = (out_partial, err_partial);
+        if partials != (0, 0) && partials == self.last_partials {
+            self.clear_status();
+            // Partial output consisting only of progress reports doesn't
+            // leave the cursor anywhere new and so isn't a prompt.
+            let mut prompted = false;
+            // The partial lines are held back from the sinks & regexes until
+            // the rest of their lines arrive.
+            let out_prompt = if out_partial != 0 {
+                self.pout.take_partial()
+            } else {
+                None
+            };
+            if let Some(prompt) = out_prompt {
+                self.note_read(self.stdout_stream(), &prompt);
+                let prompt = self.check_progress(prompt);
+                let (prompt, masked) = self.redact(prompt);
+                self.held_stdout
+                    .extend_from_slice(masked.as_deref().unwrap_or(&prompt));
+                let prompt = if progress::is_progress_only(&prompt) {
+                    prompt
+                } else {
+                    prompted = true;
+                    self.stamp(self.stdout_stream(), prompt)
+                };
+                self.relay_stdout(&prompt, true)?;
+            }
+            let err_prompt = if err_partial != 0 {
+                self.perr.take_partial()
+            } else {
+                None
+            };
+            if let Some(prompt) = err_prompt {
+                self.note_read(Stream::Stderr, &prompt);
+                let prompt = self.check_progress(prompt);
+                let (prompt, masked) = self.redact(prompt);
+                self.held_stderr
+                    .extend_from_slice(masked.as_deref().unwrap_or(&prompt));
+                let prompt = if progress::is_progress_only(&prompt) {
+                    prompt
+                } else {
+                    prompted = true;
+                    self.stamp(Stream::Stderr, prompt)
+                };
+                let mut err = self.stderr.lock();
+                write_stderr_line(&mut err, &prompt, self.color_stderr).map_err(Error::Write)?;
+                err.flush().map_err(Error::Write)?;
+                drop(err);
+                self.note_written(Stream::Stderr, &prompt);
+            }
+            if prompted {
+                debug!(
+                    "partial output {partials:?} unchanged for a tick; pausing status line for prompt"
+                );
+                self.pause = Pause::Prompt;
+            } else {
+                debug!("partial output {partials:?} is only progress reports; relayed it");
+                self.print_status();
+            }
+            self.last_partials = (0, 0);
+        } else {
+            self.last_partials = partials;
+        }
+        #[cfg(unix)]
+        match (self.pause, self.pout.get_ref().echo_disabled()) {
+            (Pause::None, true) => {
+                debug!("echo disabled on pty; pausing status line");
+                self.clear_status();
+                self.pause = Pause::NoEcho;
+            }
+            (Pause::NoEcho, false) => {
+                debug!("echo re-enabled on pty; resuming status line");
+                self.pause = Pause::None;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Called when a complete line or record of output from the child
+    /// process has been written out, to the terminal if `to_terminal` is
+    /// true.  Resume displaying the status line if it was paused due to a
+    /// prompt, or pause it if the record left the terminal's cursor in the
+    /// middle of a line.
+    fn after_record(&mut self, record: &[u8], to_terminal: bool) {
+        // A NUL delimiter doesn't move the cursor, so a record ending in a
+        // newline followed by a NUL still ends the line.
+        let ends_line =
+            record.ends_with(b"\n") || (self.pout.delimiter == 0 && record.ends_with(b"\n\0"));
+        let mid_line = to_terminal && !ends_line;
+        match self.pause {
+            Pause::None | Pause::Prompt if mid_line && self.pout.delimiter != b'\n' => {
+                debug!("record left cursor mid-line; pausing status line");
+                self.pause = Pause::MidLine;
+            }
+            Pause::Prompt => {
+                debug!("received complete line; resuming status line");
+                self.pause = Pause::None;
+            }
+            Pause::MidLine if to_terminal && !mid_line => {
+                debug!("record ended line; resuming status line");
+                self.pause = Pause::None;
+            }
+            _ => (),
+        }
+    }
+
+    /// If `--auto-hide-at` is in effect, pause the status line if the command
+    /// has started flooding the terminal with output, or resume it if the
+    /// flood has subsided.  `line` is true if this is being called because a
+    /// line of output was received.
+    fn check_flood(&mut self, line: bool) {
+        let Some(flood) = &mut self.flood else {
+            return;
+        };
+        let now = Instant::now();
+        let flooding = if line {
+            flood.record(now)
+        } else {
+            flood.check(now)
+        };
+        match (self.pause, flooding) {
+            (Pause::None, true) => {
+                debug!("output rate exceeds --auto-hide-at; pausing status line");
+                self.clear_status();
+                self.pause = Pause::Flood;
+            }
+            (Pause::Flood, false) => {
+                debug!("output rate has dropped; resuming status line");
+                self.pause = Pause::None;
+            }
+            _ => (),
+        }
+    }
+
+    fn dump_gathered_stderr(&mut self) -> Result<(), Error> {
+        if let Some(gathered) = self.gathered_stderr.take() {
+            let mut err = self.stderr.lock();
+            for line in gathered {
+                write_stderr_line(&mut err, &line, self.color_stderr).map_err(Error::Write)?;
+                if let Some(check) = &mut self.passthrough {
+                    check.written(Stream::Stderr, &line);
+                }
+            }
+            err.flush().map_err(Error::Write)?;
+        }
+        Ok(())
+    }
+
+    /// Note that `record` was received from the child process on `stream`,
+    /// completing any partial line on that stream that was held back after
+    /// being relayed as a prompt
+    fn observe_record(&mut self, stream: Stream, record: &[u8]) -> Result<(), Error> {
+        let held = match stream {
+            Stream::Stdout | Stream::Merged => &mut self.held_stdout,
+            Stream::Stderr => &mut self.held_stderr,
+        };
+        if held.is_empty() {
+            self.observe_line(stream, record)
+        } else {
+            let mut line = std::mem::take(held);
+            line.extend_from_slice(record);
+            self.observe_line(stream, &line)
+        }
+    }
+
+    /// Observe any partial line on `stream` that was held back after being
+    /// relayed as a prompt and that will now never be completed
+    fn observe_held(&mut self, stream: Stream) -> Result<(), Error> {
+        let held = match stream {
+            Stream::Stdout | Stream::Merged => std::mem::take(&mut self.held_stdout),
+            Stream::Stderr => std::mem::take(&mut self.held_stderr),
+        };
+        if held.is_empty() {
+            Ok(())
+        } else {
+            self.observe_line(stream, &held)
+        }
+    }
+
+    /// Note that `line` was received from the child process on `stream`
+    fn observe_line(&mut self, stream: Stream, line: &[u8]) -> Result<(), Error> {
+        self.counts.record(stream, line, self.pout.delimiter);
+        // Don't make regexes deal with a custom delimiter; a trailing newline
+        // is already ignored by `Pattern::is_match()`.
+        let text = match line.split_last() {
+            Some((&b, rest)) if b == self.pout.delimiter && b != b'\n' => rest,
+            _ => line,
+        };
+        self.check_flood(true);
+        let now = Instant::now();
+        let gap = now.saturating_duration_since(self.last_output);
+        self.longest_silence = self.longest_silence.max(gap);
+        self.last_output = now;
+        if let Some(gaps) = &mut self.line_gaps {
+            gaps.observe(now);
+        }
+        for sw in &mut self.stopwatches {
+            sw.observe(text, now);
+        }
+        if !self.success_matched
+            && self
+                .success_regex
+                .as_ref()
+                .is_some_and(|rgx| rgx.is_match(text))
+        {
+            self.success_matched = true;
+        }
+        if !self.failure_matched
+            && self
+                .failure_regex
+                .as_ref()
+                .is_some_and(|rgx| rgx.is_match(text))
+        {
+            self.failure_matched = true;
+        }
+        if self
+            .systemd_ready
+            .as_ref()
+            .is_some_and(|rgx| rgx.is_match(text))
+        {
+            self.notify_systemd_ready()?;
+        }
+        if let Some(tail) = &mut self.mail_tail {
+            tail.push(text);
+        }
+        self.sinks.line(&Line {
+            stream,
+            record: line,
+            text,
+            elapsed: now.saturating_duration_since(self.start),
+            gap,
+        })
+    }
+}
+
+/// Open the inherited file descriptor `fd` for writing.  Opening it via
+/// `/dev/fd` gives us a new descriptor for the same file without resorting to
+/// unsafe code.
+#[cfg(unix)]
+fn open_inherited_fd(fd: u32) -> io::Result<File> {
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(format!("/dev/fd/{fd}"))
+}
+
+/// Open the inherited file descriptor `fd` for writing a copy of the
+/// command's output to
+#[cfg(unix)]
+fn open_tee_fd(fd: u32) -> Result<File, Error> {
+    open_inherited_fd(fd).map_err(|e| Error::OpenTee(fd, e))
+}
+
+/// Determine where to write `--porcelain` or `--json` (as given by `opt`)
+/// records to given the option's value, if any
+fn porcelain_target(value: Option<OsString>, opt: &str) -> Result<PorcelainTarget, lexopt::Error> {
+    match value {
+        Some(fd) => {
+            cfg_if! {
+                if #[cfg(unix)] {
+                    let _ = opt;
+                    Ok(PorcelainTarget::Fd(fd.parse()?))
+                } else {
+                    let _ = fd;
+                    Err(format!("{opt}=<FD> is not supported on this system").into())
+                }
+            }
+        }
+        None => Ok(PorcelainTarget::Stderr),
+    }
+}
+
+/// Open the destination for `--porcelain` or `--json` records
+fn open_porcelain(
+    target: PorcelainTarget,
+    style: PorcelainStyle,
+) -> Result<Porcelain<Box<dyn Write + Send>>, Error> {
+    let out: Box<dyn Write + Send> = match target {
+        PorcelainTarget::Stderr => Box::new(io::stderr()),
+        #[cfg(unix)]
+        PorcelainTarget::Fd(fd) => {
+            Box::new(open_inherited_fd(fd).map_err(|e| Error::OpenPorcelain(fd, e))?)
+        }
+        #[cfg(not(unix))]
+        PorcelainTarget::Fd(_) => unreachable!("--porcelain=<FD> is rejected on this system"),
+    };
+    Ok(Porcelain::new(out, style))
+}
+
+/// Write a line of the child process's stderr to `w`, optionally colored red
+fn write_stderr_line<W: Write>(w: &mut W, line: &[u8], color: bool) -> io::Result<()> {
+    if color {
+        let (body, eol) = match line.strip_suffix(b"\n") {
+            Some(body) => (body, &b"\n"[..]),
+            None => (line, &b""[..]),
+        };
+        w.write_all(b"\x1B[31m")?;
+        w.write_all(body)?;
+        w.write_all(b"\x1B[m")?;
+        w.write_all(eol)
+    } else {
+        w.write_all(line)
+    }
+}
+
+/// When to apply color to output
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum ColorWhen {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorWhen {
+    fn enabled<S: IsTerminal>(self, stream: &S) -> bool {
+        match self {
+            ColorWhen::Auto => stream.is_terminal(),
+            ColorWhen::Always => true,
+            ColorWhen::Never => false,
+        }
+    }
+}
+
+impl std::str::FromStr for ColorWhen {
+    type Err = ParseColorWhenError;
+
+    fn from_str(s: &str) -> Result<ColorWhen, ParseColorWhenError> {
+        match s {
+            "auto" => Ok(ColorWhen::Auto),
+            "always" => Ok(ColorWhen::Always),
+            "never" => Ok(ColorWhen::Never),
+            _ => Err(ParseColorWhenError),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid color setting; expected \"auto\", \"always\", or \"never\"")]
+struct ParseColorWhenError;
+
+/// A replacement of one of the command's exit codes with another, as given by
+/// `--exit-map`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct ExitMapping {
+    from: u8,
+    to: u8,
+}
+
+impl std::str::FromStr for ExitMapping {
+    type Err = ParseExitMappingError;
+
+    fn from_str(s: &str) -> Result<ExitMapping, ParseExitMappingError> {
+        let (from, to) = s.split_once('=').ok_or(ParseExitMappingError)?;
+        let from = from.trim().parse().map_err(|_| ParseExitMappingError)?;
+        let to = to.trim().parse().map_err(|_| ParseExitMappingError)?;
+        Ok(ExitMapping { from, to })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid exit code mapping; expected \"<FROM>=<TO>\" with codes from 0 to 255")]
+struct ParseExitMappingError;
+
+/// The byte that terminates each record of the child process's output, as
+/// given by `--delimiter`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Delimiter(u8);
+
+impl Default for Delimiter {
+    fn default() -> Delimiter {
+        Delimiter(b'\n')
+    }
+}
+
+impl std::str::FromStr for Delimiter {
+    type Err = ParseDelimiterError;
+
+    fn from_str(s: &str) -> Result<Delimiter, ParseDelimiterError> {
+        match s {
+            "nul" => Ok(Delimiter(0)),
+            "newline" => Ok(Delimiter(b'\n')),
+            _ => {
+                if let Some(hex) = s.strip_prefix("0x").filter(|h| h.len() == 2) {
+                    u8::from_str_radix(hex, 16)
+                        .map(Delimiter)
+                        .map_err(|_| ParseDelimiterError)
+                } else if let &[b] = s.as_bytes() {
+                    Ok(Delimiter(b))
+                } else {
+                    Err(ParseDelimiterError)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid delimiter; expected \"nul\", \"newline\", a single ASCII character, or \"0xHH\"")]
+struct ParseDelimiterError;
+
+/// The size of a pseudo-terminal, as given by `--pty-size`
+#[cfg_attr(not(unix), allow(dead_code))]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct PtySize {
+    cols: u16,
+    rows: u16,
+}
+
+impl std::str::FromStr for PtySize {
+    type Err = ParsePtySizeError;
+
+    fn from_str(s: &str) -> Result<PtySize, ParsePtySizeError> {
+        let (cols, rows) = s.split_once('x').ok_or(ParsePtySizeError)?;
+        let parse = |n: &str| {
+            n.parse::<u16>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or(ParsePtySizeError)
+        };
+        Ok(PtySize {
+            cols: parse(cols)?,
+            rows: parse(rows)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid pseudo-terminal size; expected <COLS>x<ROWS>, e.g., \"80x24\"")]
+struct ParsePtySizeError;
+
+/// Which of the child process's output streams a line was read from
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Stream {
+    Stdout,
+    Stderr,
+    /// Both stdout & stderr, sent to the same pipe due to `--merge`
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Merged,
+}
+
+/// Counts of the output received from the child process on each stream
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct OutputCounts {
+    stdout: StreamCounts,
+    stderr: StreamCounts,
+}
+
+impl OutputCounts {
+    /// Count a line or partial line received on `stream`, where lines are
+    /// terminated by `delimiter`
+    fn record(&mut self, stream: Stream, line: &[u8], delimiter: u8) {
+        let counts = match stream {
+            // Merged output is mostly stdout, and it all gets written to
+            // stdout.
+            Stream::Stdout | Stream::Merged => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        };
+        counts.bytes += u64::try_from(line.len()).unwrap_or(u64::MAX);
+        // Like `wc -l`, count line terminators, so that a partial line (e.g.,
+        // a prompt) and its completion are only counted once.
+        if line.last() == Some(&delimiter) {
+            counts.lines += 1;
+        }
+    }
+}
+
+impl std::fmt::Display for OutputCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "stdout: {}; stderr: {}", self.stdout, self.stderr)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct StreamCounts {
+    lines: u64,
+    bytes: u64,
+}
+
+impl std::fmt::Display for StreamCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} line{}, {}",
+            format_count(self.lines),
+            if self.lines == 1 { "" } else { "s" },
+            format_bytes(self.bytes)
+        )
+    }
+}
+
+/// Return how many instances of `elapsed` the current process is nested
+/// inside of, according to the environment
+fn nesting_depth() -> usize {
+    std::env::var(DEPTH_ENV_VAR)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Return the environment variables to run the command with in order to let
+/// any instance of `elapsed` that it runs know that it's nested and to tell
+/// it the current run's ID
+fn child_env() -> [(&'static str, String); 3] {
+    [
+        (NESTING_ENV_VAR, std::process::id().to_string()),
+        (DEPTH_ENV_VAR, nesting_depth().saturating_add(1).to_string()),
+        (RUN_ID_ENV_VAR, run_id().to_owned()),
+    ]
+}
+
+/// The lines printed before & after running the command for `--banner`
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Banner {
+    /// The command line being run
+    command: String,
+    /// Use only ASCII characters
+    ascii: bool,
+    /// Whitespace to put at the start of each line
+    indent: String,
+}
+
+impl Banner {
+    fn new(command: String, ascii: bool, indent: String) -> Banner {
+        Banner {
+            command,
+            ascii,
+            indent,
+        }
+    }
+
+    /// Write the line marking the start of the command, which was started at
+    /// the time of day `started`
+    fn start<W: Write>(&self, mut out: W, started: &str) -> io::Result<()> {
+        let (mark, sep) = if self.ascii {
+            (">", "|")
+        } else {
+            ("\u{25B6}", "\u{00B7}")
+        };
+        writeln!(
+            out,
+            "{}{mark} {} {sep} started {started}",
+            self.indent, self.command
+        )?;
+        out.flush()
+    }
+
+    /// Write the line marking the end of the command, giving its exit status
+    /// & how long it ran for
+    fn end<W: Write>(&self, mut out: W, exit_status: &str, duration: Duration) -> io::Result<()> {
+        let (mark, sep) = if self.ascii {
+            ("<", "|")
+        } else {
+            ("\u{25C0}", "\u{00B7}")
+        };
+        writeln!(
+            out,
+            "{}{mark} {} {sep} {exit_status} after {}",
+            self.indent,
+            self.command,
+            format_duration(duration)
+        )?;
+        out.flush()
+    }
+}
+
+/// How the command finished, for marking the `--total` line
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Verdict {
+    Success,
+    /// The command failed for the given reason (e.g., "exit code 2")
+    Failure(String),
+}
+
+impl Verdict {
+    /// Determine how the command finished from the result of the event loop
+    fn new(r: &Result<u8, Error>) -> Verdict {
+        match r {
+            Ok(0) => Verdict::Success,
+            Ok(code) => Verdict::Failure(format!("exit code {code}")),
+            Err(Error::Signal(rc)) => Verdict::Failure(describe_signal(*rc)),
+            Err(_) => Verdict::Failure(String::from("terminated abnormally")),
+        }
+    }
+
+    /// A green check mark or red cross to put before the `--total` line
+    fn mark(&self) -> &'static str {
+        match self {
+            Verdict::Success => "\x1B[32m\u{2714}\x1B[m ",
+            Verdict::Failure(_) => "\x1B[31m\u{2718}\x1B[m ",
+        }
+    }
+
+    /// The equivalent of [`Verdict::mark()`] for terminals that can't show
+    /// color or non-ASCII characters
+    fn ascii_mark(&self) -> &'static str {
+        match self {
+            Verdict::Success => "OK ",
+            Verdict::Failure(_) => "FAILED ",
+        }
+    }
+}
+
+/// Return the exit code that a shell would report for a process that
+/// terminated with status `rc` (i.e., 128 plus the number of the signal that
+/// killed it)
+fn signal_exit_code(rc: ExitStatus) -> u8 {
+    cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::process::ExitStatusExt;
+            rc.signal()
+                .and_then(|n| u8::try_from(n).ok())
+                .map_or(1, |n| n.saturating_add(128))
+        } else {
+            let _ = rc;
+            1
+        }
+    }
+}
+
+/// Return a command that runs `script` via the shell
+fn shell_command(script: &OsStr) -> Command {
+    let mut cmd;
+    cfg_if! {
+        if #[cfg(windows)] {
+            cmd = Command::new("cmd");
+            cmd.arg("/C");
+        } else {
+            cmd = Command::new("sh");
+            cmd.arg("-c");
+        }
+    }
+    cmd.arg(script);
+    cmd
+}
+
+/// Describe how a process with exit status `rc` was killed by a signal
+fn describe_signal(rc: ExitStatus) -> String {
+    cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::process::ExitStatusExt;
+            match rc.signal() {
+                Some(n) => match nix::sys::signal::Signal::try_from(n) {
+                    Ok(sig) => format!("killed by {sig}"),
+                    Err(_) => format!("killed by signal {n}"),
+                },
+                None => String::from("terminated abnormally"),
+            }
+        } else {
+            format!("terminated abnormally ({rc})")
+        }
+    }
+}
+
+/// The status line along with what it's currently displaying, shared between
+/// the event loop and the ticker thread so that the latter can redraw it
+/// while the former is stalled
+#[derive(Debug)]
+struct SharedStatus {
+    line: StatusLine,
+    /// The readings & note that the status line was last drawn with, or
+    /// `None` if it's not currently displayed
+    shown: Option<(Readings, String)>,
+    /// If drawing the status line has failed, the elapsed time at which that
+    /// happened and the error message
+    lost: Option<(Duration, String)>,
+}
+
+impl SharedStatus {
+    /// Handle the result of drawing or erasing the status line at elapsed
+    /// time `elapsed`.  If writing to stderr failed (e.g., because the
+    /// terminal went away), the status line is deactivated for the rest of
+    /// the run rather than the run being aborted, and true is returned.
+    fn check_drawn(&mut self, r: Result<(), Error>, elapsed: Duration) -> bool {
+        let Err(e) = r else {
+            return false;
+        };
+        self.line.deactivate();
+        self.shown = None;
+        self.lost = Some((elapsed, e.to_string()));
+        true
+    }
+}
+
+/// The measurements of the run, other than the elapsed time, that the status
+/// line is rendered from
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct Readings {
+    /// The most recent statistics sampled for the command's process tree, if
+    /// any
+    proc_stats: Option<ProcStats>,
+    /// How long after the command was started it first produced any output,
+    /// if it has done so yet
+    first_output: Option<Duration>,
+    /// How long the command is expected to run for in total, if known
+    expected: Option<Duration>,
+    /// The percentage of its work that the command last reported having
+    /// done, if any
+    progress: Option<u8>,
+    /// The number of refreshes so far that were delayed because the event
+    /// loop was stalled
+    missed_ticks: u64,
+    /// When the command will be stopped for exceeding `--timeout`, if a
+    /// timeout was given
+    deadline: Option<Instant>,
+}
+
+/// Lock the shared status line.  A panic while the lock was held can't have
+/// left the status in an inconsistent state, so poisoning is ignored.
+fn lock_status(status: &Mutex<SharedStatus>) -> MutexGuard<'_, SharedStatus> {
+    status.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[derive(Debug)]
+enum StatusLine {
+    Active {
+        format: Format,
+        clock: PausableClock,
+        err: io::Stderr,
+        redraw: Redraw,
+    },
+    /// The status line is not displayed, but its text can still be rendered
+    /// for use elsewhere
+    Inactive {
+        format: Format,
+        clock: PausableClock,
+    },
+}
+
+impl StatusLine {
+    fn new(format: Format, start: Instant, a11y: Option<Duration>, ascii: bool) -> StatusLine {
+        let err = io::stderr();
+        if err.is_terminal() {
+            let redraw = if let Some(interval) = a11y {
+                Redraw::Announce(Announcer {
+                    interval,
+                    next: Cell::new(interval),
+                })
+            } else if ascii {
+                Redraw::Backspace(Cell::new(0))
+            } else {
+                Redraw::Ansi
+            };
+            StatusLine::Active {
+                format,
+                clock: PausableClock::new(start),
+                err,
+                redraw,
+            }
+        } else {
+            StatusLine::Inactive {
+                format,
+                clock: PausableClock::new(start),
+            }
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        matches!(self, StatusLine::Active { .. })
+    }
+
+    /// Returns true if the status line is redrawn using ANSI escape sequences,
+    /// in which case it may contain colors
+    fn is_ansi(&self) -> bool {
+        matches!(
+            self,
+            StatusLine::Active {
+                redraw: Redraw::Ansi,
+                ..
+            }
+        )
+    }
+
+    /// Stop displaying the status line
+    fn deactivate(&mut self) {
+        if let StatusLine::Active { format, clock, .. } = self {
+            *self = StatusLine::Inactive {
+                format: format.clone(),
+                clock: *clock,
+            };
+        }
+    }
+
+    /// Stop the status line's clock at time `now`, for when the command is
+    /// suspended
+    fn pause(&mut self, now: Instant) {
+        let (StatusLine::Active { clock, .. } | StatusLine::Inactive { clock, .. }) = self;
+        clock.pause(now);
+    }
+
+    /// Restart the status line's clock at time `now`, for when the command
+    /// is continued
+    fn resume(&mut self, now: Instant) {
+        let (StatusLine::Active { clock, .. } | StatusLine::Inactive { clock, .. }) = self;
+        clock.resume(now);
+    }
+
+    fn clear(&self) -> Result<(), Error> {
+        if let StatusLine::Active {
+            format,
+            err,
+            redraw,
+            ..
+        } = self
+        {
+            let s = match redraw {
+                Redraw::Ansi if format.newlines() == 0 => Cow::from("\r\x1B[K"),
+                Redraw::Ansi => Cow::from(format!(
+                    "\x1B[{newlines}F\x1B[J",
+                    newlines = format.newlines()
+                )),
+                Redraw::Backspace(width) => {
+                    let n = width.replace(0);
+                    Cow::from(format!("{0}{1}{0}", "\x08".repeat(n), " ".repeat(n)))
+                }
+                Redraw::Announce(_) => return Ok(()),
+            };
+            let mut err = err.lock();
+            err.write_all(s.as_bytes()).map_err(Error::Write)?;
+            err.flush().map_err(Error::Write)?;
+        }
+        Ok(())
+    }
+
+    /// Display the status line using the given readings, followed by `note`
+    fn print(&self, readings: Readings, note: &str) -> Result<(), Error> {
+        self.inner_print(readings, note, "", false)
+    }
+
+    /// Display the final status line using the given readings, followed by
+    /// `note` and a newline, and preceded by a mark for `verdict`, if given
+    fn print_total(
+        &self,
+        readings: Readings,
+        note: &str,
+        verdict: Option<&Verdict>,
+    ) -> Result<(), Error> {
+        let mark = match (self, verdict) {
+            (
+                StatusLine::Active {
+                    redraw: Redraw::Backspace(_),
+                    ..
+                },
+                Some(v),
+            ) => v.ascii_mark(),
+            (_, Some(v)) => v.mark(),
+            (_, None) => "",
+        };
+        self.inner_print(readings, note, mark, true)
+    }
+
+    /// In `--a11y` mode, print a plain sentence stating the outcome of the
+    /// command and how long it ran for, plus the span of wall-clock time it
+    /// ran over, if given
+    fn print_summary(&self, outcome: &str, span: Option<&str>) -> Result<(), Error> {
+        if let StatusLine::Active {
+            err,
+            clock,
+            redraw: Redraw::Announce(_),
+            ..
+        } = self
+        {
+            let mut err = err.lock();
+            let duration = spoken_duration(clock.elapsed());
+            match span {
+                Some(span) => writeln!(err, "{outcome} after {duration} ({span})."),
+                None => writeln!(err, "{outcome} after {duration}."),
+            }
+            .map_err(Error::Write)?;
+            err.flush().map_err(Error::Write)?;
+        }
+        Ok(())
+    }
+
+    /// Return the text of the status line using the given readings,
+    /// regardless of whether the status line is active
+    fn render(&self, readings: Readings) -> String {
+        let (StatusLine::Active { format, clock, .. } | StatusLine::Inactive { format, clock }) =
+            self;
+        let snap = Snapshot {
+            elapsed: clock.elapsed(),
+            proc_stats: readings.proc_stats,
+            first_output: readings.first_output,
+            expected: readings.expected,
+            progress: readings.progress,
+            missed_ticks: readings.missed_ticks,
+            time_left: readings
+                .deadline
+                .map(|t| t.saturating_duration_since(Instant::now())),
+        };
+        format.display(&snap)
+    }
+
+    fn inner_print(
+        &self,
+        readings: Readings,
+        note: &str,
+        mark: &str,
+        nl: bool,
+    ) -> Result<(), Error> {
+        if let StatusLine::Active {
+            err, clock, redraw, ..
+        } = self
+        {
+            let s = match redraw {
+                Redraw::Ansi => {
+                    let mut s = String::from(mark);
+                    s.push_str(&self.render(readings));
+                    s.push_str(note);
+                    if nl {
+                        s.push('\n');
+                    }
+                    s
+                }
+                Redraw::Backspace(width) => {
+                    // Backspacing can't move up to a previous line, so the
+                    // status has to be kept to a single line.
+                    let mut s = String::from(mark);
+                    s.push_str(&self.render(readings).replace('\n', " "));
+                    s.push_str(note);
+                    if nl {
+                        s.push('\n');
+                    } else {
+                        width.set(s.chars().count());
+                    }
+                    s
+                }
+                Redraw::Announce(announcer) => {
+                    let elapsed = clock.elapsed();
+                    if !announcer.due(elapsed) {
+                        return Ok(());
+                    }
+                    format!("Elapsed: {}{note}\n", spoken_duration(elapsed))
+                }
+            };
+            let mut err = err.lock();
+            err.write_all(s.as_bytes()).map_err(Error::Write)?;
+            err.flush().map_err(Error::Write)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the status line is displayed & erased
+#[derive(Debug)]
+enum Redraw {
+    /// Redraw the status line in place using ANSI escape sequences
+    Ansi,
+    /// Erase the status line by backspacing over it & overwriting it with
+    /// spaces, for dumb terminals that don't support escape sequences.  The
+    /// field holds the number of characters currently displayed.
+    Backspace(Cell<usize>),
+    /// Print periodic plain-text announcements instead (`--a11y` mode)
+    Announce(Announcer),
+}
+
+/// Schedule for the periodic plain-text announcements made in place of the
+/// status line in `--a11y` mode
+#[derive(Debug)]
+struct Announcer {
+    interval: Duration,
+    /// The elapsed time at which the next announcement is due
+    next: Cell<Duration>,
+}
+
+impl Announcer {
+    /// Returns true if an announcement is due at the given elapsed time, in
+    /// which case the next one is scheduled.  Announcements that were missed
+    /// (e.g., because the status line was paused) are skipped rather than
+    /// made up for.
+    fn due(&self, elapsed: Duration) -> bool {
+        let mut next = self.next.get();
+        if elapsed < next {
+            return false;
+        }
+        while next <= elapsed {
+            next += self.interval;
+        }
+        self.next.set(next);
+        true
+    }
+}
+
+enum ChildOutput {
+    Stdout(ChildStdout),
+    Stderr(ChildStderr),
+    /// A pseudo-terminal, along with the answerer of the terminal queries
+    /// that the command writes to it
+    #[cfg(unix)]
+    Pty(pty_process::Pty, QueryResponder),
+    /// A pipe to which both the child process's stdout & stderr are written
+    #[cfg(unix)]
+    Merged(tokio::net::unix::pipe::Receiver),
+    #[cfg(unix)]
+    Null,
+}
+
+impl ChildOutput {
+    /// Returns true if this is a pseudo-terminal with echo currently disabled
+    #[cfg(unix)]
+    fn echo_disabled(&self) -> bool {
+        use nix::sys::termios::{LocalFlags, tcgetattr};
+        if let ChildOutput::Pty(pty, _) = self {
+            tcgetattr(pty).is_ok_and(|t| !t.local_flags.contains(LocalFlags::ECHO))
+        } else {
+            false
+        }
+    }
+}
+
+impl AsyncRead for ChildOutput {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match &mut *self {
+            ChildOutput::Stdout(out) => {
+                let out = pin!(out);
+                out.poll_read(cx, buf)
+            }
+            ChildOutput::Stderr(err) => {
+                let err = pin!(err);
+                err.poll_read(cx, buf)
+            }
+            #[cfg(unix)]
+            ChildOutput::Pty(pty, responder) => {
+                let start = buf.filled().len();
+                // On Linux, attempting to read from a pty master after the
+                // slave closes (due, e.g., to the child process exiting)
+                // results in EIO (which Rust currently represents with the
+                // undocumented ErrorKind::Uncategorized).
+                match ready!(pin!(&mut *pty).poll_read(cx, buf)) {
+                    Err(e) if e.raw_os_error() == Some(5) => return Ok(()).into(),
+                    Err(e) => return Err(e).into(),
+                    Ok(()) => (),
+                }
+                let answered = responder.answer(&buf.filled()[start..]);
+                if !answered.replies.is_empty() {
+                    answer_queries(pty, &answered.replies);
+                }
+                if let Some(output) = answered.output {
+                    buf.set_filled(start);
+                    buf.put_slice(&output);
+                    if output.is_empty() {
+                        // The read consisted entirely of queries; returning
+                        // nothing would look like EOF, so read again instead.
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                }
+                Ok(()).into()
+            }
+            #[cfg(unix)]
+            ChildOutput::Merged(rx) => {
+                let rx = pin!(rx);
+                rx.poll_read(cx, buf)
+            }
+            #[cfg(unix)]
+            ChildOutput::Null => Ok(()).into(),
+        }
+    }
+}
+
+/// Write the replies to the command's terminal queries to the pty master.
+/// The replies are small enough that the write won't block in practice; if it
+/// fails, there's nothing better to do than to leave the command to time out
+/// waiting for them, just as if they hadn't been answered.
+#[cfg(unix)]
+fn answer_queries(pty: &pty_process::Pty, mut replies: &[u8]) {
+    while !replies.is_empty() {
+        match nix::unistd::write(pty, replies) {
+            Ok(0) => break,
+            Ok(n) => replies = &replies[n..],
+            Err(nix::errno::Errno::EINTR) => (),
+            Err(_) => break,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error(transparent)]
+    Usage(lexopt::Error),
+    #[error("failed to spawn child process: {0}")]
+    Spawn(io::Error),
+    #[error("failed to run --setup command: {0}")]
+    RunSetup(io::Error),
+    #[error("--setup command failed: {0}")]
+    SetupFailed(ExitStatus),
+    #[error(transparent)]
+    Write(io::Error),
+    #[error("error reading from child process's stdout: {0}")]
+    ReadStdout(io::Error),
+    #[error("error reading from child process's stderr: {0}")]
+    ReadStderr(io::Error),
+    #[error("error waiting for child process to terminate: {0}")]
+    Wait(io::Error),
+    #[error("child process killed by signal: {0}")]
+    Signal(ExitStatus),
+    #[error("failed to open line timings file: {0}")]
+    OpenLineTimings(io::Error),
+    #[error("failed to write to line timings file: {0}")]
+    WriteLineTimings(io::Error),
+    #[error(transparent)]
+    Runbook(#[from] runbook::RunbookError),
+    #[error("failed to open slow log file: {0}")]
+    OpenSlowLog(io::Error),
+    #[error("failed to write to slow log file: {0}")]
+    WriteSlowLog(io::Error),
+    #[error("failed to open debug log file: {0}")]
+    OpenDebugLog(io::Error),
+    #[cfg(unix)]
+    #[error("failed to open file descriptor {0} for --tee-fd: {1}")]
+    OpenTee(u32, io::Error),
+    #[error("failed to write to --tee-fd file descriptor: {0}")]
+    WriteTee(io::Error),
+    #[cfg(unix)]
+    #[error("failed to open file descriptor {0} for --porcelain or --json: {1}")]
+    OpenPorcelain(u32, io::Error),
+    #[error("failed to write --porcelain or --json record: {0}")]
+    WritePorcelain(io::Error),
+    #[error("failed to start ticker thread: {0}")]
+    StartTicker(io::Error),
+    #[error("failed to start sink thread: {0}")]
+    StartSink(io::Error),
+    #[cfg(unix)]
+    #[error("failed to kill command in order to restart it: {0}")]
+    Restart(io::Error),
+    #[cfg(unix)]
+    #[error("failed to create pipe for child process's output: {0}")]
+    InitPipe(io::Error),
+    #[error("failed to start forwarding stdin to child process: {0}")]
+    PipeStdin(io::Error),
+    #[cfg(unix)]
+    #[error("error initializing pty: {0}")]
+    InitPty(pty_process::Error),
+    #[cfg(unix)]
+    #[error("failed to spawn child process on pty: {0}")]
+    SpawnPty(pty_process::Error),
+}
+
+impl Error {
+    fn is_epipe_write(&self) -> bool {
+        matches!(self, Error::Write(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+
+    /// The return code to exit with after reporting this error.  Following
+    /// shell conventions, if the command could not be run because it wasn't
+    /// found, this is 127, and if it could not be run because it wasn't
+    /// executable, this is 126.  All other errors result in 1.
+    fn exit_code(&self) -> u8 {
+        let spawn_err: Option<&io::Error> = match self {
+            Error::Spawn(e) => Some(e),
+            #[cfg(unix)]
+            Error::SpawnPty(e) => std::error::Error::source(e).and_then(|e| e.downcast_ref()),
+            _ => None,
+        };
+        match spawn_err.map(io::Error::kind) {
+            Some(io::ErrorKind::NotFound) => 127,
+            Some(io::ErrorKind::PermissionDenied) => 126,
+            _ => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse_args {
+        use super::*;
+        use assert_matches::assert_matches;
+        use rstest::rstest;
+
+        #[test]
+        fn command_only() {
+            let parser = Parser::from_iter(["elapsed", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.args.is_empty());
+                assert!(!app.total);
+            });
+        }
+
+        #[test]
+        fn command_with_arg() {
+            let parser = Parser::from_iter(["elapsed", "foo", "bar"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.args, ["bar"]);
+            });
+        }
+
+        #[test]
+        fn command_with_opt() {
+            let parser = Parser::from_iter(["elapsed", "foo", "--bar"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.args, ["--bar"]);
+            });
+        }
+
+        #[test]
+        fn command_with_my_opt() {
+            let parser = Parser::from_iter(["elapsed", "foo", "--help"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.args, ["--help"]);
+            });
+        }
+
+        #[test]
+        fn help() {
+            let parser = Parser::from_iter(["elapsed", "--help"]);
+            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Help);
+        }
+
+        #[test]
+        fn doctor() {
+            let parser = Parser::from_iter(["elapsed", "--doctor"]);
+            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Doctor);
+        }
+
+        #[test]
+        fn runbook() {
+            let parser = Parser::from_iter(["elapsed", "--runbook", "ci.toml"]);
+            assert_eq!(
+                Arguments::from_parser(parser).unwrap(),
+                Arguments::Runbook(PathBuf::from("ci.toml"))
+            );
+        }
+
+        #[test]
+        fn runbook_and_command() {
+            let parser = Parser::from_iter(["elapsed", "--runbook", "ci.toml", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn man() {
+            let parser = Parser::from_iter(["elapsed", "--man"]);
+            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Man);
+        }
+
+        #[test]
+        fn version() {
+            let parser = Parser::from_iter(["elapsed", "--version"]);
+            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Version);
+        }
+
+        #[test]
+        fn help_and_command() {
+            let parser = Parser::from_iter(["elapsed", "--help", "foo"]);
+            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Help);
+        }
+
+        #[test]
+        fn total() {
+            let parser = Parser::from_iter(["elapsed", "--total", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.args.is_empty());
+                assert!(app.total);
+                assert!(!app.plain);
+            });
+        }
+
+        #[test]
+        fn total_plain() {
+            let parser = Parser::from_iter(["elapsed", "--total", "--plain", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.total);
+                assert!(app.plain);
+            });
+        }
+
+        #[test]
+        fn debug_default() {
+            let parser = Parser::from_iter(["elapsed", "--debug", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.debug, Some(PathBuf::from("elapsed-debug.log")));
+            });
+        }
+
+        #[test]
+        fn debug_path() {
+            let parser = Parser::from_iter(["elapsed", "--debug=/tmp/debug.txt", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.debug, Some(PathBuf::from("/tmp/debug.txt")));
+            });
+        }
+
+        #[test]
+        fn exit_map() {
+            let parser =
+                Parser::from_iter(["elapsed", "--exit-map", "1=0", "--exit-map=2=42", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(
+                    app.exit_map,
+                    [
+                        ExitMapping { from: 1, to: 0 },
+                        ExitMapping { from: 2, to: 42 },
+                    ]
+                );
+            });
+        }
+
+        #[rstest]
+        #[case("1")]
+        #[case("1=")]
+        #[case("=1")]
+        #[case("x=1")]
+        #[case("1=256")]
+        #[case("-1=0")]
+        fn exit_map_invalid(#[case] mapping: &str) {
+            let parser = Parser::from_iter(["elapsed", "--exit-map", mapping, "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[rstest]
+        #[case("nul", 0)]
+        #[case("newline", b'\n')]
+        #[case(";", b';')]
+        #[case("0x1e", 0x1E)]
+        #[case("0x1E", 0x1E)]
+        fn delimiter(#[case] delim: &str, #[case] byte: u8) {
+            let parser = Parser::from_iter(["elapsed", "--delimiter", delim, "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.delimiter, Delimiter(byte));
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn merge() {
+            let parser = Parser::from_iter(["elapsed", "--merge", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.merge);
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn tee_fd() {
+            let parser = Parser::from_iter(["elapsed", "--tee-fd", "3", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.tee_fd, Some(3));
+            });
+        }
+
+        #[cfg(unix)]
+        #[rstest]
+        #[case(&["--auto-tty"], Some(Duration::from_secs(5)))]
+        #[case(&["--auto-tty=2"], Some(Duration::from_secs(2)))]
+        #[case(&[], None)]
+        fn auto_tty(#[case] opts: &[&str], #[case] window: Option<Duration>) {
+            let parser = Parser::from_iter(
+                std::iter::once("elapsed")
+                    .chain(opts.iter().copied())
+                    .chain(["foo"]),
+            );
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.auto_tty, window);
+            });
+        }
+
+        #[test]
+        fn banner() {
+            let parser = Parser::from_iter(["elapsed", "--banner", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.banner);
+            });
+        }
+
+        #[test]
+        fn preset() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--stopwatch-regex",
+                "dl:^Downloading:^Compiling",
+                "--preset",
+                "cargo",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                let mut expected = vec!["dl:^Downloading:^Compiling".parse::<Stopwatch>().unwrap()];
+                expected.extend(Preset::Cargo.stopwatches());
+                assert_eq!(app.stopwatches, expected);
+            });
+        }
+
+        #[test]
+        fn bad_preset() {
+            let parser = Parser::from_iter(["elapsed", "--preset", "ant", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn auto_hide_at() {
+            let parser = Parser::from_iter(["elapsed", "--auto-hide-at", "500", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.auto_hide_at, Some(500));
+            });
+        }
+
+        #[test]
+        fn auto_hide_at_zero() {
+            let parser = Parser::from_iter(["elapsed", "--auto-hide-at", "0", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn expect() {
+            let parser = Parser::from_iter(["elapsed", "--expect", "90", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.expect, Some(Duration::from_secs(90)));
+            });
+        }
+
+        #[rstest]
+        #[case("1.5m", Duration::from_secs(90))]
+        #[case("2.5", Duration::from_millis(2500))]
+        #[case("500ms", Duration::from_millis(500))]
+        fn expect_units(#[case] value: &str, #[case] d: Duration) {
+            let parser = Parser::from_iter(["elapsed", "--expect", value, "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.expect, Some(d));
+            });
+        }
+
+        #[test]
+        fn expect_invalid() {
+            let parser = Parser::from_iter(["elapsed", "--expect", "soon", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn timeout() {
+            let parser = Parser::from_iter(["elapsed", "--timeout", "1.5m", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.timeout, Some(Duration::from_secs(90)));
+            });
+        }
+
+        #[rstest]
+        #[case("0")]
+        #[case("0ms")]
+        #[case("soon")]
+        fn timeout_invalid(#[case] value: &str) {
+            let parser = Parser::from_iter(["elapsed", "--timeout", value, "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn slowlog() {
+            let parser = Parser::from_iter(["elapsed", "--slowlog", "slow.log", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.slowlog, Some(PathBuf::from("slow.log")));
+                assert_eq!(app.slowlog_threshold, DEFAULT_SLOWLOG_THRESHOLD);
+            });
+        }
+
+        #[test]
+        fn slowlog_threshold() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--slowlog-threshold",
+                "60",
+                "--slowlog",
+                "slow.log",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.slowlog, Some(PathBuf::from("slow.log")));
+                assert_eq!(app.slowlog_threshold, Duration::from_secs(60));
+            });
+        }
+
+        #[rstest]
+        #[case("1.5", Duration::from_millis(1500))]
+        #[case("500ms", Duration::from_millis(500))]
+        #[case("2m", Duration::from_secs(120))]
+        fn slowlog_threshold_units(#[case] value: &str, #[case] d: Duration) {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--slowlog-threshold",
+                value,
+                "--slowlog",
+                "slow.log",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.slowlog_threshold, d);
+            });
+        }
+
+        #[test]
+        fn slowlog_threshold_invalid() {
+            let parser = Parser::from_iter(["elapsed", "--slowlog-threshold", "long", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn sink_queue() {
+            let parser = Parser::from_iter(["elapsed", "--sink-queue", "64", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.sink_queueing(), Some(Queueing {
+                    capacity: 64,
+                    overflow: Overflow::Block,
+                }));
+            });
+        }
+
+        #[rstest]
+        #[case(&[], Some(DEFAULT_SINK_QUEUE))]
+        #[case(&["--sink-queue", "8"], Some(8))]
+        fn sink_overflow_drop(#[case] opts: &[&str], #[case] capacity: Option<usize>) {
+            let parser = Parser::from_iter(
+                ["elapsed", "--sink-overflow", "drop"]
+                    .into_iter()
+                    .chain(opts.iter().copied())
+                    .chain(["foo"]),
+            );
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.sink_queueing(), capacity.map(|capacity| Queueing {
+                    capacity,
+                    overflow: Overflow::Drop,
+                }));
+            });
+        }
+
+        #[test]
+        fn no_sink_queue() {
+            let parser = Parser::from_iter(["elapsed", "--sink-overflow", "block", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.sink_queueing(), None);
+            });
+        }
+
+        #[rstest]
+        #[case("--sink-queue", "0")]
+        #[case("--sink-queue", "lots")]
+        #[case("--sink-overflow", "ignore")]
+        fn sink_invalid(#[case] opt: &str, #[case] value: &str) {
+            let parser = Parser::from_iter(["elapsed", opt, value, "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn mail() {
+            let parser = Parser::from_iter(["elapsed", "--mail", "me@example.com", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.mail.as_deref(), Some("me@example.com"));
+                assert_eq!(app.mail_lines, DEFAULT_MAIL_LINES);
+                assert_eq!(app.mail_transport, mail::Transport::Sendmail);
+            });
+        }
+
+        #[test]
+        fn mail_lines() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--mail-lines",
+                "3",
+                "--mail",
+                "me@example.com",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.mail.as_deref(), Some("me@example.com"));
+                assert_eq!(app.mail_lines, 3);
+            });
+        }
+
+        #[rstest]
+        #[case("mail.example.com", "mail.example.com:25")]
+        #[case("mail.example.com:2525", "mail.example.com:2525")]
+        #[case("127.0.0.1", "127.0.0.1:25")]
+        #[case("[::1]", "[::1]:25")]
+        #[case("[::1]:587", "[::1]:587")]
+        fn mail_smtp(#[case] relay: &str, #[case] address: &str) {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--mail",
+                "me@example.com",
+                "--mail-smtp",
+                relay,
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.mail_transport, mail::Transport::Smtp(address.to_owned()));
+            });
+        }
+
+        #[test]
+        fn porcelain() {
+            let parser = Parser::from_iter(["elapsed", "--porcelain", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.porcelain, Some(PorcelainTarget::Stderr));
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn porcelain_fd() {
+            let parser = Parser::from_iter(["elapsed", "--porcelain=3", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.porcelain, Some(PorcelainTarget::Fd(3)));
+            });
+        }
+
+        #[test]
+        fn porcelain_then_command() {
+            let parser = Parser::from_iter(["elapsed", "--porcelain", "3"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "3");
+                assert_eq!(app.porcelain, Some(PorcelainTarget::Stderr));
+            });
+        }
+
+        #[test]
+        fn json() {
+            let parser = Parser::from_iter(["elapsed", "--json", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.porcelain, Some(PorcelainTarget::Stderr));
+                assert_eq!(app.porcelain_style, PorcelainStyle::Json);
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn json_fd() {
+            let parser = Parser::from_iter(["elapsed", "--porcelain", "--json=3", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.porcelain, Some(PorcelainTarget::Fd(3)));
+                assert_eq!(app.porcelain_style, PorcelainStyle::Json);
+            });
+        }
+
+        #[test]
+        fn no_stdin() {
+            let parser = Parser::from_iter(["elapsed", "--no-stdin", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.no_stdin);
+            });
+        }
+
+        #[test]
+        fn no_stdin_pipe_stdin() {
+            let parser = Parser::from_iter(["elapsed", "--no-stdin", "--pipe-stdin", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn pipe_stdin() {
+            let parser = Parser::from_iter(["elapsed", "--pipe-stdin", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.pipe_stdin);
+            });
+        }
+
+        #[cfg(unix)]
+        #[rstest]
+        #[case(&["--pipe-stdin", "--tty"])]
+        #[case(&["--auto-tty", "--pipe-stdin"])]
+        fn pipe_stdin_with_tty(#[case] opts: &[&str]) {
+            let parser = Parser::from_iter(
+                std::iter::once("elapsed")
+                    .chain(opts.iter().copied())
+                    .chain(["foo"]),
+            );
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn auto_tty_zero() {
+            let parser = Parser::from_iter(["elapsed", "--auto-tty=0", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[rstest]
+        #[case("")]
+        #[case("NUL")]
+        #[case("ab")]
+        #[case("é")]
+        #[case("0x")]
+        #[case("0x1")]
+        #[case("0xzz")]
+        fn delimiter_invalid(#[case] delim: &str) {
+            let parser = Parser::from_iter(["elapsed", "--delimiter", delim, "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn pty_size() {
+            let parser = Parser::from_iter(["elapsed", "--pty-size", "120x40", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.pty_size, Some(PtySize { cols: 120, rows: 40 }));
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn term_sequence_default() {
+            let parser = Parser::from_iter(["elapsed", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.term_sequence, TermSequence::default());
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn term_sequence() {
+            let parser =
+                Parser::from_iter(["elapsed", "--term-sequence", "INT:5s,TERM:10s,KILL", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.term_sequence, "INT:5s,TERM:10s,KILL".parse().unwrap());
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn bad_term_sequence() {
+            let parser = Parser::from_iter(["elapsed", "--term-sequence", "TERM,KILL", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn pty_term() {
+            let parser = Parser::from_iter(["elapsed", "--pty-term", "xterm-256color", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.pty_term, Some(OsString::from("xterm-256color")));
+            });
+        }
+
+        #[cfg(unix)]
+        #[rstest]
+        #[case("")]
+        #[case("120")]
+        #[case("120x")]
+        #[case("x40")]
+        #[case("0x40")]
+        #[case("120x0")]
+        #[case("120X40")]
+        #[case("120x40x2")]
+        #[case("70000x40")]
+        fn pty_size_invalid(#[case] size: &str) {
+            let parser = Parser::from_iter(["elapsed", "--pty-size", size, "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn fail_on_stderr_no_value() {
+            let parser = Parser::from_iter(["elapsed", "--fail-on-stderr", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.fail_on_stderr, Some(1));
+            });
+        }
+
+        #[test]
+        fn fail_on_stderr_code() {
+            let parser = Parser::from_iter(["elapsed", "--fail-on-stderr=23", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.fail_on_stderr, Some(23));
+            });
+        }
+
+        #[test]
+        fn output_regexes() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--success-regex",
+                "^OK$",
+                "--failure-regex",
+                "FAIL",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.success_regex, Some("^OK$".parse().unwrap()));
+                assert_eq!(app.failure_regex, Some("FAIL".parse().unwrap()));
+            });
+        }
+
+        #[test]
+        fn bad_regex() {
+            let parser = Parser::from_iter(["elapsed", "--success-regex", "(", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[cfg(unix)]
+        #[rstest]
+        #[case(&["--systemd-extend"], true, None)]
+        #[case(&["--systemd-extend=^Listening"], true, Some("^Listening"))]
+        #[case(&[], false, None)]
+        fn systemd_extend(
+            #[case] opts: &[&str],
+            #[case] extend: bool,
+            #[case] ready: Option<&str>,
+        ) {
+            let parser = Parser::from_iter(
+                std::iter::once("elapsed")
+                    .chain(opts.iter().copied())
+                    .chain(["foo"]),
+            );
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.systemd_extend, extend);
+                assert_eq!(app.systemd_ready, ready.map(|s| s.parse().unwrap()));
+            });
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn caffeinate() {
+            let parser = Parser::from_iter(["elapsed", "--caffeinate", "foo", "--caffeinate"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.args, ["--caffeinate"]);
+                assert!(app.caffeinate);
+            });
+        }
+
+        #[test]
+        fn exclude_suspended() {
+            let parser = Parser::from_iter(["elapsed", "--exclude-suspended", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.exclude_suspended);
+            });
+        }
+
+        #[test]
+        fn watch_path() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--watch-path",
+                "backup.tar",
+                "--watch-path",
+                "/var/tmp/out",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(
+                    app.watch_paths,
+                    [PathBuf::from("backup.tar"), PathBuf::from("/var/tmp/out")]
+                );
+            });
+        }
+
+        #[test]
+        fn watch_disk() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--watch-disk",
+                "/",
+                "--min-free",
+                "2G",
+                "--on-low-disk",
+                "notify-send 'Disk is full'",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.watch_disks, [PathBuf::from("/")]);
+                assert_eq!(app.min_free, MinFree::Bytes(2 << 30));
+                assert_eq!(app.on_low_disk.unwrap(), "notify-send 'Disk is full'");
+            });
+        }
+
+        #[test]
+        fn periodic() {
+            let parser = Parser::from_iter(["elapsed", "--periodic", "1m:./heartbeat.sh", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.periodic, Some(Periodic {
+                    interval: Duration::from_secs(60),
+                    command: String::from("./heartbeat.sh"),
+                }));
+            });
+        }
+
+        #[test]
+        fn setup_teardown() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--setup",
+                "make clean",
+                "--teardown",
+                "rm -rf build",
+                "make",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "make");
+                assert_eq!(app.setup.unwrap(), "make clean");
+                assert_eq!(app.teardown.unwrap(), "rm -rf build");
+            });
+        }
+
+        #[test]
+        fn min_free_default() {
+            let parser = Parser::from_iter(["elapsed", "--watch-disk", ".", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.min_free, MinFree::Percent(5));
+                assert_eq!(app.on_low_disk, None);
+            });
+        }
+
+        #[test]
+        fn min_free_percent() {
+            let parser = Parser::from_iter(["elapsed", "--min-free=10%", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.min_free, MinFree::Percent(10));
+            });
+        }
+
+        #[test]
+        fn min_free_invalid() {
+            let parser = Parser::from_iter(["elapsed", "--min-free", "lots", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn osc133() {
+            let parser = Parser::from_iter(["elapsed", "--osc133", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.osc133);
+            });
+        }
+
+        #[test]
+        fn user_var() {
+            let parser = Parser::from_iter(["elapsed", "--user-var", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.user_var.as_deref(), Some("elapsed"));
+            });
+        }
+
+        #[test]
+        fn user_var_name() {
+            let parser = Parser::from_iter(["elapsed", "--user-var=build_time", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.user_var.as_deref(), Some("build_time"));
+            });
+        }
+
+        #[test]
+        fn a11y() {
+            let parser = Parser::from_iter(["elapsed", "--a11y", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.a11y, Some(Duration::from_secs(60)));
+            });
+        }
+
+        #[test]
+        fn a11y_interval() {
+            let parser = Parser::from_iter(["elapsed", "--a11y=300", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.a11y, Some(Duration::from_secs(300)));
+            });
+        }
+
+        #[test]
+        fn a11y_zero() {
+            let parser = Parser::from_iter(["elapsed", "--a11y=0", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn ascii() {
+            let parser = Parser::from_iter(["elapsed", "--ascii", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.ascii);
+            });
+        }
+
+        #[test]
+        fn notify() {
+            let parser = Parser::from_iter(["elapsed", "--notify", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.notify);
+            });
+        }
+
+        #[test]
+        fn utc() {
+            let parser = Parser::from_iter(["elapsed", "--utc", "--banner", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.utc);
+                assert!(app.banner);
+            });
+        }
+
+        #[test]
+        fn timestamps() {
+            let parser = Parser::from_iter(["elapsed", "--total", "--timestamps", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.total);
+                assert!(app.timestamps);
+                assert_eq!(app.line_stamps, None);
+            });
+        }
+
+        #[rstest]
+        #[case("elapsed", StampStyle::Elapsed)]
+        #[case("wallclock", StampStyle::WallClock)]
+        fn timestamps_style(#[case] style: &str, #[case] expected: StampStyle) {
+            let opt = format!("--timestamps={style}");
+            let parser = Parser::from_iter(["elapsed", &opt, "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.timestamps);
+                assert_eq!(app.line_stamps, Some(expected));
+            });
+        }
+
+        #[test]
+        fn timestamps_invalid_style() {
+            let parser = Parser::from_iter(["elapsed", "--timestamps=local", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[rstest]
+        #[case("--timestamps=elapsed")]
+        #[case("--strip-progress")]
+        fn verify_passthrough_rewritten(#[case] opt: &str) {
+            let parser = Parser::from_iter(["elapsed", "--verify-passthrough", opt, "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
+
+        #[test]
+        fn strip_progress() {
+            let parser = Parser::from_iter(["elapsed", "--strip-progress", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.strip_progress);
+            });
         }
-    }
 
-    fn clear(&self) -> Result<(), Error> {
-        if let StatusLine::Active { format, err, .. } = self {
-            let s = if format.newlines() == 0 {
-                Cow::from("\r\x1B[K")
-            } else {
-                Cow::from(format!(
-                    "\x1B[{newlines}F\x1B[J",
-                    newlines = format.newlines()
-                ))
-            };
-            let mut err = err.lock();
-            err.write_all(s.as_bytes()).map_err(Error::Write)?;
-            err.flush().map_err(Error::Write)?;
+        #[test]
+        fn stopwatch_regex() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--stopwatch-regex",
+                "dl:^Downloading:^Compiling",
+                "--stopwatch-regex",
+                "build:^Compiling:^Finished",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(
+                    app.stopwatches,
+                    [
+                        "dl:^Downloading:^Compiling".parse::<Stopwatch>().unwrap(),
+                        "build:^Compiling:^Finished".parse::<Stopwatch>().unwrap(),
+                    ]
+                );
+            });
         }
-        Ok(())
-    }
 
-    fn print(&self) -> Result<(), Error> {
-        self.inner_print(false)
-    }
+        #[test]
+        fn stopwatch_regex_invalid() {
+            let parser =
+                Parser::from_iter(["elapsed", "--stopwatch-regex", "dl:^Downloading", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
 
-    fn print_total(&self) -> Result<(), Error> {
-        self.inner_print(true)
-    }
+        #[test]
+        fn precise() {
+            let parser = Parser::from_iter(["elapsed", "--precise", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.format, Format::precise());
+                assert_eq!(app.refresh_period, PRECISE_REFRESH_PERIOD);
+            });
+        }
 
-    fn inner_print(&self, nl: bool) -> Result<(), Error> {
-        if let StatusLine::Active { format, start, err } = self {
-            let mut s = format.display(start.elapsed());
-            if nl {
-                s.push('\n');
-            }
-            let mut err = err.lock();
-            err.write_all(s.as_bytes()).map_err(Error::Write)?;
-            err.flush().map_err(Error::Write)?;
+        #[test]
+        fn precise_explicit() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--format",
+                "%s",
+                "--precise",
+                "--refresh",
+                "250",
+                "foo",
+            ]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.format, "%s".parse::<Format>().unwrap());
+                assert_eq!(app.refresh_period, Duration::from_millis(250));
+            });
         }
-        Ok(())
-    }
-}
 
-enum ChildOutput {
-    Stdout(ChildStdout),
-    Stderr(ChildStderr),
-    #[cfg(unix)]
-    Pty(pty_process::Pty),
-    #[cfg(unix)]
-    Null,
-}
+        #[test]
+        fn summary() {
+            let parser = Parser::from_iter(["elapsed", "--summary", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.summary);
+            });
+        }
 
-impl AsyncRead for ChildOutput {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-        buf: &mut ReadBuf<'_>,
-    ) -> Poll<io::Result<()>> {
-        match &mut *self {
-            ChildOutput::Stdout(out) => {
-                let out = pin!(out);
-                out.poll_read(cx, buf)
-            }
-            ChildOutput::Stderr(err) => {
-                let err = pin!(err);
-                err.poll_read(cx, buf)
-            }
-            #[cfg(unix)]
-            ChildOutput::Pty(pty) => {
-                let pty = pin!(pty);
-                // On Linux, attempting to read from a pty master after the
-                // slave closes (due, e.g., to the child process exiting)
-                // results in EIO (which Rust currently represents with the
-                // undocumented ErrorKind::Uncategorized).
-                match ready!(pty.poll_read(cx, buf)) {
-                    Err(e) if e.raw_os_error() == Some(5) => Ok(()).into(),
-                    r => r.into(),
-                }
-            }
-            #[cfg(unix)]
-            ChildOutput::Null => Ok(()).into(),
+        #[test]
+        fn quiet_errors() {
+            let parser = Parser::from_iter(["elapsed", "--quiet-errors", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.quiet_errors);
+            });
         }
-    }
-}
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-struct ByteLines<R> {
-    reader: R,
-    buffer: Vec<u8>,
-    next_index: usize,
-    eof: bool,
-    strip_cr: bool,
-}
+        #[test]
+        fn energy() {
+            let parser = Parser::from_iter(["elapsed", "--summary", "--energy", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.summary);
+                assert!(app.energy);
+            });
+        }
 
-impl<R> ByteLines<R> {
-    fn new(reader: R) -> Self {
-        ByteLines {
-            reader,
-            buffer: Vec::new(),
-            next_index: 0,
-            eof: false,
-            strip_cr: false,
+        #[test]
+        fn wait_reasons() {
+            let parser = Parser::from_iter(["elapsed", "--summary", "--wait-reasons", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.wait_reasons);
+            });
         }
-    }
 
-    fn get_line(&mut self) -> Option<Vec<u8>> {
-        let mut r = if let Some(i) = self.buffer[self.next_index..]
-            .iter()
-            .position(|&b| b == b'\n')
-        {
-            let newline_pos = self.next_index + i;
-            self.next_index = 0;
-            Some(self.buffer.drain(0..=newline_pos).collect())
-        } else if self.eof {
-            let r = (!self.buffer.is_empty()).then(|| std::mem::take(&mut self.buffer));
-            self.next_index = 0;
-            r
-        } else {
-            self.next_index = self.buffer.len();
-            None
-        };
-        if self.strip_cr {
-            if let Some(buf) = &mut r {
-                let n = buf.len();
-                if n >= 2 && buf[n - 2] == b'\r' && buf[n - 1] == b'\n' {
-                    buf[n - 2] = b'\n';
-                    buf.truncate(n - 1);
-                }
-            }
+        #[test]
+        fn verify_passthrough() {
+            let parser = Parser::from_iter(["elapsed", "--verify-passthrough", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.verify_passthrough);
+            });
         }
-        r
-    }
 
-    fn next_line<'a>(&'a mut self) -> NextLine<'a, R> {
-        NextLine { inner: self }
-    }
-}
+        #[test]
+        fn verify_passthrough_redact() {
+            let parser =
+                Parser::from_iter(["elapsed", "--verify-passthrough", "--redact-secrets", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert!(app.verify_passthrough);
+                assert!(app.redact_secrets);
+                assert!(!app.redact_relay);
+            });
+        }
 
-#[derive(Debug, Eq, PartialEq)]
-struct NextLine<'a, R> {
-    inner: &'a mut ByteLines<R>,
-}
+        #[test]
+        fn verify_passthrough_redact_relay() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--verify-passthrough",
+                "--redact-secrets",
+                "--redact-relay",
+                "foo",
+            ]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
 
-impl<R: AsyncRead + Unpin> Future for NextLine<'_, R> {
-    type Output = io::Result<Option<Vec<u8>>>;
+        #[test]
+        fn number_style() {
+            let parser =
+                Parser::from_iter(["elapsed", "--thousands-sep", "'", "--decimal-sep=,", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.number_style, NumberStyle {
+                    thousands_sep: String::from("'"),
+                    decimal_sep: String::from(","),
+                });
+            });
+        }
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        loop {
-            if let Some(ln) = self.inner.get_line() {
-                return Poll::Ready(Ok(Some(ln)));
-            } else if self.inner.eof {
-                return Poll::Ready(Ok(None));
-            } else {
-                let mut buf0 = vec![0u8; READ_BUFFER_SIZE];
-                let mut buf = ReadBuf::new(&mut buf0);
-                let reader = pin!(&mut self.inner.reader);
-                match ready!(reader.poll_read(cx, &mut buf)) {
-                    Ok(()) => {
-                        if buf.filled().is_empty() {
-                            self.inner.eof = true;
-                        } else {
-                            self.inner.buffer.extend_from_slice(buf.filled());
-                        }
-                    }
-                    Err(e) => return Err(e).into(),
-                }
-            }
+        #[test]
+        fn number_style_default() {
+            let parser = Parser::from_iter(["elapsed", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.number_style, NumberStyle::default());
+            });
         }
-    }
-}
 
-#[derive(Debug, Error)]
-enum Error {
-    #[error(transparent)]
-    Usage(lexopt::Error),
-    #[error("failed to spawn child process: {0}")]
-    Spawn(io::Error),
-    #[error(transparent)]
-    Write(io::Error),
-    #[error("error reading from child process's stdout: {0}")]
-    ReadStdout(io::Error),
-    #[error("error reading from child process's stderr: {0}")]
-    ReadStderr(io::Error),
-    #[error("error waiting for child process to terminate: {0}")]
-    Wait(io::Error),
-    #[error("child process killed by signal: {0}")]
-    Signal(ExitStatus),
-    #[cfg(unix)]
-    #[error("error initializing pty: {0}")]
-    InitPty(pty_process::Error),
-    #[cfg(unix)]
-    #[error("failed to spawn child process on pty: {0}")]
-    SpawnPty(pty_process::Error),
-}
+        #[test]
+        fn tmux() {
+            let parser = Parser::from_iter(["elapsed", "--tmux", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.tmux);
+            });
+        }
 
-impl Error {
-    fn is_epipe_write(&self) -> bool {
-        matches!(self, Error::Write(e) if e.kind() == io::ErrorKind::BrokenPipe)
-    }
-}
+        #[test]
+        fn warn_fds() {
+            let parser = Parser::from_iter(["elapsed", "--warn-fds", "100", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.warn_fds, Some(100));
+            });
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        #[rstest]
+        #[case(&["--max-descendants", "50"], false)]
+        #[case(&["--max-descendants", "50", "--kill-on-max-descendants"], true)]
+        fn max_descendants(#[case] opts: &[&str], #[case] kill: bool) {
+            let parser = Parser::from_iter(
+                std::iter::once("elapsed")
+                    .chain(opts.iter().copied())
+                    .chain(["foo"]),
+            );
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.max_descendants, Some(50));
+                assert_eq!(app.kill_on_max_descendants, kill);
+            });
+        }
 
-    mod parse_args {
-        use super::*;
-        use assert_matches::assert_matches;
+        #[test]
+        fn warn_fds_not_int() {
+            let parser = Parser::from_iter(["elapsed", "--warn-fds", "many", "foo"]);
+            assert!(Arguments::from_parser(parser).is_err());
+        }
 
         #[test]
-        fn command_only() {
-            let parser = Parser::from_iter(["elapsed", "foo"]);
+        fn redact() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--redact",
+                "hunter[0-9]",
+                "--redact-secrets",
+                "--redact",
+                "swordfish",
+                "foo",
+            ]);
             assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
                 assert_eq!(app.cmd, "foo");
-                assert!(app.args.is_empty());
-                assert!(!app.total);
+                assert_eq!(
+                    app.redact,
+                    ["hunter[0-9]".parse().unwrap(), "swordfish".parse().unwrap()]
+                );
+                assert!(app.redact_secrets);
+                assert!(!app.redact_relay);
             });
         }
 
         #[test]
-        fn command_with_arg() {
-            let parser = Parser::from_iter(["elapsed", "foo", "bar"]);
+        fn redact_relay() {
+            let parser =
+                Parser::from_iter(["elapsed", "--redact-secrets", "--redact-relay", "foo"]);
             assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
                 assert_eq!(app.cmd, "foo");
-                assert_eq!(app.args, ["bar"]);
+                assert!(app.redact_secrets);
+                assert!(app.redact_relay);
             });
         }
 
         #[test]
-        fn command_with_opt() {
-            let parser = Parser::from_iter(["elapsed", "foo", "--bar"]);
+        fn gather_stderr() {
+            let parser = Parser::from_iter(["elapsed", "--gather-stderr", "foo"]);
             assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
                 assert_eq!(app.cmd, "foo");
-                assert_eq!(app.args, ["--bar"]);
+                assert!(app.gather_stderr);
             });
         }
 
         #[test]
-        fn command_with_my_opt() {
-            let parser = Parser::from_iter(["elapsed", "foo", "--help"]);
+        fn color_stderr_default() {
+            let parser = Parser::from_iter(["elapsed", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.color_stderr, ColorWhen::Auto);
+            });
+        }
+
+        #[test]
+        fn color_stderr_no_value() {
+            let parser = Parser::from_iter(["elapsed", "--color-stderr", "foo"]);
             assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
                 assert_eq!(app.cmd, "foo");
-                assert_eq!(app.args, ["--help"]);
+                assert_eq!(app.color_stderr, ColorWhen::Always);
             });
         }
 
         #[test]
-        fn help() {
-            let parser = Parser::from_iter(["elapsed", "--help"]);
-            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Help);
+        fn color_stderr_never() {
+            let parser = Parser::from_iter(["elapsed", "--color-stderr=never", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.color_stderr, ColorWhen::Never);
+            });
         }
 
         #[test]
-        fn version() {
-            let parser = Parser::from_iter(["elapsed", "--version"]);
-            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Version);
+        fn line_buffer() {
+            let parser = Parser::from_iter(["elapsed", "-L", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert!(app.line_buffer);
+            });
         }
 
         #[test]
-        fn help_and_command() {
-            let parser = Parser::from_iter(["elapsed", "--help", "foo"]);
-            assert_eq!(Arguments::from_parser(parser).unwrap(), Arguments::Help);
+        fn line_timings() {
+            let parser = Parser::from_iter(["elapsed", "--line-timings", "times.jsonl", "foo"]);
+            assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
+                assert_eq!(app.cmd, "foo");
+                assert_eq!(app.line_timings, Some(PathBuf::from("times.jsonl")));
+                assert!(!app.append);
+            });
         }
 
         #[test]
-        fn total() {
-            let parser = Parser::from_iter(["elapsed", "--total", "foo"]);
+        fn append() {
+            let parser = Parser::from_iter([
+                "elapsed",
+                "--append",
+                "--line-timings",
+                "times.jsonl",
+                "foo",
+            ]);
             assert_matches!(Arguments::from_parser(parser).unwrap(), Arguments::Run(app) => {
                 assert_eq!(app.cmd, "foo");
-                assert!(app.args.is_empty());
-                assert!(app.total);
+                assert_eq!(app.line_timings, Some(PathBuf::from("times.jsonl")));
+                assert!(app.append);
             });
         }
 
@@ -662,74 +5469,141 @@ mod tests {
         }
     }
 
-    mod byte_lines {
+    mod announcer {
+        use super::*;
+
+        #[test]
+        fn due() {
+            let announcer = Announcer {
+                interval: Duration::from_secs(60),
+                next: Cell::new(Duration::from_secs(60)),
+            };
+            assert!(!announcer.due(Duration::from_secs(59)));
+            assert!(announcer.due(Duration::from_millis(60_010)));
+            assert!(!announcer.due(Duration::from_secs(61)));
+            assert!(announcer.due(Duration::from_secs(120)));
+            // Missed announcements are skipped:
+            assert!(announcer.due(Duration::from_secs(330)));
+            assert_eq!(announcer.next.get(), Duration::from_secs(360));
+            assert!(!announcer.due(Duration::from_secs(359)));
+        }
+    }
+
+    mod shared_status {
+        use super::*;
+
+        #[test]
+        fn check_drawn() {
+            let mut status = SharedStatus {
+                line: StatusLine::Active {
+                    format: Format::default(),
+                    clock: PausableClock::new(Instant::now()),
+                    err: io::stderr(),
+                    redraw: Redraw::Ansi,
+                },
+                shown: Some((Readings::default(), String::new())),
+                lost: None,
+            };
+            assert!(!status.check_drawn(Ok(()), Duration::from_secs(1)));
+            assert!(status.line.is_active());
+            assert!(status.shown.is_some());
+            let e = Error::Write(io::Error::other("terminal went away"));
+            assert!(status.check_drawn(Err(e), Duration::from_secs(2)));
+            assert!(!status.line.is_active());
+            assert_eq!(status.shown, None);
+            assert_eq!(
+                status.lost,
+                Some((Duration::from_secs(2), String::from("terminal went away")))
+            );
+        }
+    }
+
+    mod banner {
         use super::*;
-        use std::io::Cursor;
-        use tokio_test::io::Builder;
-
-        #[tokio::test]
-        async fn many_short_lines() {
-            let reader = Cursor::new(b"Hello!\nI like your code.\nGoodbye!\n");
-            let mut lines = ByteLines::new(reader);
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hello!\n");
+
+        #[test]
+        fn unicode() {
+            let banner = Banner::new(String::from("make -j8"), false, String::new());
+            let mut buf = Vec::new();
+            banner.start(&mut buf, "14:02:11Z").unwrap();
+            banner
+                .end(
+                    &mut buf,
+                    "exited with code 0",
+                    Duration::from_millis(83_456),
+                )
+                .unwrap();
             assert_eq!(
-                lines.next_line().await.unwrap().unwrap(),
-                b"I like your code.\n"
+                String::from_utf8(buf).unwrap(),
+                concat!(
+                    "\u{25B6} make -j8 \u{00B7} started 14:02:11Z\n",
+                    "\u{25C0} make -j8 \u{00B7} exited with code 0 after 00:01:23.456\n",
+                )
             );
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!\n");
-            assert_eq!(lines.next_line().await.unwrap(), None);
         }
 
-        #[tokio::test]
-        async fn many_short_lines_no_final_newline() {
-            let reader = Cursor::new(b"Hello!\nI like your code.\nGoodbye!");
-            let mut lines = ByteLines::new(reader);
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hello!\n");
+        #[test]
+        fn ascii() {
+            let banner = Banner::new(String::from("make -j8"), true, String::new());
+            let mut buf = Vec::new();
+            banner.start(&mut buf, "14:02:11Z").unwrap();
+            banner
+                .end(&mut buf, "terminated abnormally", Duration::from_secs(5))
+                .unwrap();
             assert_eq!(
-                lines.next_line().await.unwrap().unwrap(),
-                b"I like your code.\n"
+                String::from_utf8(buf).unwrap(),
+                concat!(
+                    "> make -j8 | started 14:02:11Z\n",
+                    "< make -j8 | terminated abnormally after 00:00:05.000\n",
+                )
             );
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!");
-            assert_eq!(lines.next_line().await.unwrap(), None);
-        }
-
-        #[tokio::test]
-        async fn split_line() {
-            let reader = Builder::new()
-                .read(b"Hello, ")
-                .read(b"World!\n")
-                .read(b"Bye now!\n")
-                .build();
-            let mut lines = ByteLines::new(reader);
+        }
+
+        #[test]
+        fn indented() {
+            let banner = Banner::new(String::from("make -j8"), true, String::from("    "));
+            let mut buf = Vec::new();
+            banner.start(&mut buf, "14:02:11Z").unwrap();
+            banner
+                .end(&mut buf, "exited with code 2", Duration::from_secs(5))
+                .unwrap();
             assert_eq!(
-                lines.next_line().await.unwrap().unwrap(),
-                b"Hello, World!\n"
+                String::from_utf8(buf).unwrap(),
+                concat!(
+                    "    > make -j8 | started 14:02:11Z\n",
+                    "    < make -j8 | exited with code 2 after 00:00:05.000\n",
+                )
             );
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Bye now!\n");
-            assert_eq!(lines.next_line().await.unwrap(), None);
         }
+    }
+
+    mod output_counts {
+        use super::*;
 
-        #[tokio::test]
-        async fn non_utf8() {
-            let reader = Cursor::new(b"Hell\xF6!\nI like your code.\nGoodbye!\n");
-            let mut lines = ByteLines::new(reader);
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hell\xF6!\n");
+        #[test]
+        fn record() {
+            let mut counts = OutputCounts::default();
+            counts.record(Stream::Stdout, b"Hello, world!\n", b'\n');
+            counts.record(Stream::Stderr, b"Password: ", b'\n');
+            counts.record(Stream::Stderr, b"\n", b'\n');
+            counts.record(Stream::Stdout, b"Goodbye.", b'\n');
+            assert_eq!(
+                counts,
+                OutputCounts {
+                    stdout: StreamCounts {
+                        lines: 1,
+                        bytes: 22
+                    },
+                    stderr: StreamCounts {
+                        lines: 1,
+                        bytes: 11
+                    },
+                }
+            );
             assert_eq!(
-                lines.next_line().await.unwrap().unwrap(),
-                b"I like your code.\n"
+                counts.to_string(),
+                "stdout: 1 line, 22 B; stderr: 1 line, 11 B"
             );
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!\n");
-            assert_eq!(lines.next_line().await.unwrap(), None);
-        }
-
-        #[tokio::test]
-        async fn strip_cr() {
-            let reader = Cursor::new(b"Hello!\r\nGoodbye!\n");
-            let mut lines = ByteLines::new(reader);
-            lines.strip_cr = true;
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Hello!\n");
-            assert_eq!(lines.next_line().await.unwrap().unwrap(), b"Goodbye!\n");
-            assert_eq!(lines.next_line().await.unwrap(), None);
         }
     }
 }
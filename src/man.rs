@@ -0,0 +1,209 @@
+//! Rendering of the `--help` text as a roff man page
+use std::fmt::Write;
+
+/// The column at which option descriptions start in the `--help` text
+const DESCRIPTION_COLUMN: usize = 20;
+
+/// Convert the `--help` text `help` into a man page in roff format.
+///
+/// The help text is expected to consist of a "Usage:" line, a one-line
+/// summary, any number of further paragraphs, and then an "Options:" line
+/// followed by the options, each of which is an indented header line (with
+/// the start of the description optionally beginning at
+/// [`DESCRIPTION_COLUMN`]) followed by description lines indented to
+/// [`DESCRIPTION_COLUMN`].  Description lines starting with "- " are list
+/// items and are kept on separate lines.
+pub(crate) fn render(help: &str, version: &str) -> String {
+    let mut lines = help.lines();
+    let mut out = format!(".TH ELAPSED 1 \"\" \"elapsed-cmd {version}\" \"User Commands\"\n");
+    let usage = lines
+        .next()
+        .and_then(|ln| ln.strip_prefix("Usage: elapsed "))
+        .unwrap_or_default();
+    let summary = lines.find(|ln| !ln.is_empty()).unwrap_or_default();
+    out.push_str(".SH NAME\n");
+    let _ = writeln!(out, "elapsed \\- {}", escape(summary));
+    out.push_str(".SH SYNOPSIS\n");
+    let _ = writeln!(out, ".B elapsed\n{}", inline(usage));
+    out.push_str(".SH DESCRIPTION\n");
+    let mut para_break = false;
+    for ln in lines.by_ref() {
+        if ln == "Options:" {
+            break;
+        } else if ln.is_empty() {
+            para_break = true;
+        } else {
+            if std::mem::take(&mut para_break) && !out.ends_with(".SH DESCRIPTION\n") {
+                out.push_str(".PP\n");
+            }
+            let _ = writeln!(out, "{}", inline(ln));
+        }
+    }
+    out.push_str(".SH OPTIONS\n");
+    para_break = false;
+    for ln in lines {
+        if ln.is_empty() {
+            para_break = true;
+        } else if let Some(desc) = description_line(ln) {
+            if std::mem::take(&mut para_break) {
+                out.push_str(".IP\n");
+            }
+            if desc.starts_with("- ") {
+                out.push_str(".br\n");
+            }
+            let _ = writeln!(out, "{}", inline(desc.trim_start()));
+        } else {
+            para_break = false;
+            let (header, desc) = split_header(ln);
+            let _ = writeln!(out, ".TP\n\\fB{}\\fR", inline(header));
+            if let Some(desc) = desc {
+                let _ = writeln!(out, "{}", inline(desc));
+            }
+        }
+    }
+    out
+}
+
+/// If `line` is indented to [`DESCRIPTION_COLUMN`], return the rest of it
+fn description_line(line: &str) -> Option<&str> {
+    line.get(..DESCRIPTION_COLUMN)
+        .filter(|indent| indent.bytes().all(|b| b == b' '))
+        .map(|_| &line[DESCRIPTION_COLUMN..])
+}
+
+/// Split an option header line into the option names and the start of the
+/// description, if any
+fn split_header(line: &str) -> (&str, Option<&str>) {
+    match line.get(..DESCRIPTION_COLUMN) {
+        Some(head) if line.len() > DESCRIPTION_COLUMN && head.ends_with(' ') => {
+            (head.trim(), Some(&line[DESCRIPTION_COLUMN..]))
+        }
+        _ => (line.trim(), None),
+    }
+}
+
+/// Escape `s` for use as a line of roff text and render `<PLACEHOLDER>`s in
+/// italics
+fn inline(s: &str) -> String {
+    escape(s).replace('<', "\\fI").replace('>', "\\fP")
+}
+
+/// Escape characters in `s` that are special to roff
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    if s.starts_with(['.', '\'']) {
+        out.push_str("\\&");
+    }
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\e"),
+            '-' => out.push_str("\\-"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("plain text", "plain text")]
+    #[case("--total", "\\-\\-total")]
+    #[case("%n or \\n", "%n or \\en")]
+    #[case(".starts with a dot", "\\&.starts with a dot")]
+    #[case("'quoted'", "\\&'quoted'")]
+    fn test_escape(#[case] s: &str, #[case] out: &str) {
+        assert_eq!(escape(s), out);
+    }
+
+    #[rstest]
+    #[case(
+        "  --ascii           Erase the status",
+        "--ascii",
+        Some("Erase the status")
+    )]
+    #[case(
+        "  -L, --line-buffer Try to make",
+        "-L, --line-buffer",
+        Some("Try to make")
+    )]
+    #[case("  --a11y[=<SECONDS>]", "--a11y[=<SECONDS>]", None)]
+    #[case(
+        "  -f <TEMPLATE>, --format <TEMPLATE>",
+        "-f <TEMPLATE>, --format <TEMPLATE>",
+        None
+    )]
+    fn test_split_header(#[case] line: &str, #[case] header: &str, #[case] desc: Option<&str>) {
+        assert_eq!(split_header(line), (header, desc));
+    }
+
+    #[test]
+    fn render_sample() {
+        let help = concat!(
+            "Usage: elapsed [<options>] <command>\n",
+            "\n",
+            "Show runtime\n",
+            "\n",
+            "More info.\n",
+            "\n",
+            "Options:\n",
+            "  -f <TEMPLATE>\n",
+            "                    Set the format\n",
+            "\n",
+            "                    Escapes:\n",
+            "                    - %H - hours, which are\n",
+            "                      long\n",
+            "                    - \\\\ - backslash\n",
+            "\n",
+            "  -t, --total       Leave total\n",
+            "  -h, --help        Display help\n",
+        );
+        assert_eq!(
+            render(help, "1.2.3"),
+            concat!(
+                ".TH ELAPSED 1 \"\" \"elapsed-cmd 1.2.3\" \"User Commands\"\n",
+                ".SH NAME\n",
+                "elapsed \\- Show runtime\n",
+                ".SH SYNOPSIS\n",
+                ".B elapsed\n",
+                "[\\fIoptions\\fP] \\fIcommand\\fP\n",
+                ".SH DESCRIPTION\n",
+                "More info.\n",
+                ".SH OPTIONS\n",
+                ".TP\n",
+                "\\fB\\-f \\fITEMPLATE\\fP\\fR\n",
+                "Set the format\n",
+                ".IP\n",
+                "Escapes:\n",
+                ".br\n",
+                "\\- %H \\- hours, which are\n",
+                "long\n",
+                ".br\n",
+                "\\- \\e\\e \\- backslash\n",
+                ".TP\n",
+                "\\fB\\-t, \\-\\-total\\fR\n",
+                "Leave total\n",
+                ".TP\n",
+                "\\fB\\-h, \\-\\-help\\fR\n",
+                "Display help\n",
+            )
+        );
+    }
+
+    #[test]
+    fn render_help() {
+        let page = render(crate::HELP, "0.0.0");
+        assert!(page.contains("\n.TP\n\\fB\\-\\-ascii\\fR\nErase the status line"));
+        assert!(page.contains("\n.TP\n\\fB\\-\\-man\\fR\n"));
+        assert_eq!(
+            page.matches("\n.TP\n").count(),
+            crate::HELP
+                .lines()
+                .filter(|ln| ln.starts_with("  -"))
+                .count()
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Checking that the command's output is relayed byte for byte, for
+//! `--verify-passthrough`
+use crate::Stream;
+use std::fmt;
+
+/// The lookup table for computing CRC-32 checksums (IEEE polynomial, as used
+/// by gzip & zlib) a byte at a time
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i: u32 = 0;
+    while i < 256 {
+        let mut crc = i;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i as usize] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// A running CRC-32 checksum of a stream of bytes, along with the stream's
+/// length
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Checksum {
+    crc: u32,
+    len: u64,
+}
+
+impl Checksum {
+    pub(crate) fn new() -> Checksum {
+        Checksum { crc: !0, len: 0 }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.crc = CRC32_TABLE[usize::from(self.crc.to_le_bytes()[0] ^ b)] ^ (self.crc >> 8);
+        }
+        self.len += u64::try_from(data.len()).unwrap_or(u64::MAX);
+    }
+
+    pub(crate) fn crc(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Default for Checksum {
+    fn default() -> Checksum {
+        Checksum::new()
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes, CRC-32 {:08x}", self.len, self.crc())
+    }
+}
+
+/// Checksums of the output read from one of the command's streams and of the
+/// output written to the corresponding stream of `elapsed`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Passthrough {
+    pub(crate) read: Checksum,
+    pub(crate) written: Checksum,
+}
+
+impl Passthrough {
+    pub(crate) fn matches(&self) -> bool {
+        self.read == self.written
+    }
+}
+
+/// Checksums of the output passed through on each of `elapsed`'s output
+/// streams.  Merged output is all written to stdout and so is counted as
+/// stdout.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct PassthroughCheck {
+    pub(crate) stdout: Passthrough,
+    pub(crate) stderr: Passthrough,
+}
+
+impl PassthroughCheck {
+    /// Note that `record` was read from the command on `stream`
+    pub(crate) fn read(&mut self, stream: Stream, record: &[u8]) {
+        self.get_mut(stream).read.update(record);
+    }
+
+    /// Note that `record` was written out on behalf of `stream`
+    pub(crate) fn written(&mut self, stream: Stream, record: &[u8]) {
+        self.get_mut(stream).written.update(record);
+    }
+
+    fn get_mut(&mut self, stream: Stream) -> &mut Passthrough {
+        match stream {
+            Stream::Stdout | Stream::Merged => &mut self.stdout,
+            Stream::Stderr => &mut self.stderr,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32() {
+        let mut sum = Checksum::new();
+        assert_eq!(sum.crc(), 0);
+        sum.update(b"1234");
+        sum.update(b"56789");
+        assert_eq!(sum.crc(), 0xCBF4_3926);
+        assert_eq!(sum.to_string(), "9 bytes, CRC-32 cbf43926");
+    }
+
+    #[test]
+    fn passthrough() {
+        let mut check = PassthroughCheck::default();
+        check.read(Stream::Merged, b"Hello!\n");
+        check.read(Stream::Stderr, b"Oops!\n");
+        check.read(Stream::Stdout, b"Goodbye!\n");
+        assert!(!check.stdout.matches());
+        assert!(!check.stderr.matches());
+        check.written(Stream::Stdout, b"Hello!\nGoodbye!\n");
+        assert!(check.stdout.matches());
+        assert!(!check.stderr.matches());
+        check.written(Stream::Stderr, b"Oops!");
+        assert!(!check.stderr.matches());
+        check.written(Stream::Stderr, b"\n");
+        assert!(check.stderr.matches());
+    }
+}
@@ -0,0 +1,71 @@
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::fmt;
+
+/// A regular expression for matching against lines of output from the child
+/// process.
+///
+/// This is a wrapper around [`regex::bytes::Regex`] that implements `Eq` by
+/// comparing the patterns' source strings.
+#[derive(Clone)]
+pub(crate) struct Pattern(Regex);
+
+impl Pattern {
+    /// Test whether the pattern matches anywhere in `line`.  Any trailing
+    /// line ending is ignored.
+    pub(crate) fn is_match(&self, line: &[u8]) -> bool {
+        self.0.is_match(trim_eol(line))
+    }
+
+    /// Replace all matches of the pattern in `line` with `rep`, which may
+    /// contain references to capture groups as supported by
+    /// [`Regex::replace_all()`]
+    pub(crate) fn replace_all<'a>(&self, line: &'a [u8], rep: &[u8]) -> Cow<'a, [u8]> {
+        self.0.replace_all(line, rep)
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Pattern").field(&self.0.as_str()).finish()
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Pattern) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for Pattern {}
+
+impl std::str::FromStr for Pattern {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Pattern, regex::Error> {
+        Regex::new(s).map(Pattern)
+    }
+}
+
+/// Strip a trailing LF or CR LF from `line`
+pub(crate) fn trim_eol(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("^Done$", b"Done\n", true)]
+    #[case("^Done$", b"Done\r\n", true)]
+    #[case("^Done$", b"Done", true)]
+    #[case("^Done$", b"Not Done\n", false)]
+    #[case("error", b"fatal error: oops\n", true)]
+    fn is_match(#[case] pattern: &str, #[case] line: &[u8], #[case] matched: bool) {
+        let pat = pattern.parse::<Pattern>().unwrap();
+        assert_eq!(pat.is_match(line), matched);
+    }
+}
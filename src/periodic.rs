@@ -0,0 +1,136 @@
+//! Running a command at regular intervals while the command runs, for
+//! `--periodic`
+use crate::units::parse_duration;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::Child;
+
+/// A command to run at a regular interval, as given by `--periodic`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Periodic {
+    pub(crate) interval: Duration,
+    pub(crate) command: String,
+}
+
+impl std::str::FromStr for Periodic {
+    type Err = ParsePeriodicError;
+
+    fn from_str(s: &str) -> Result<Periodic, ParsePeriodicError> {
+        let (interval, command) = s.split_once(':').ok_or(ParsePeriodicError)?;
+        let interval = parse_duration(interval)
+            .filter(|d| !d.is_zero())
+            .ok_or(ParsePeriodicError)?;
+        if command.trim().is_empty() {
+            return Err(ParsePeriodicError);
+        }
+        Ok(Periodic {
+            interval,
+            command: command.to_owned(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid periodic command; expected \"<INTERVAL>:<COMMAND>\" with a positive interval")]
+pub(crate) struct ParsePeriodicError;
+
+/// Tracks when a `--periodic` command is next due to be run and whether its
+/// last run is still going
+#[derive(Debug)]
+pub(crate) struct PeriodicHook {
+    periodic: Periodic,
+    /// The elapsed time at which the command is next due
+    next: Duration,
+    /// The last run of the command, if it may not have exited yet
+    running: Option<Child>,
+}
+
+impl PeriodicHook {
+    pub(crate) fn new(periodic: Periodic) -> PeriodicHook {
+        PeriodicHook {
+            next: periodic.interval,
+            periodic,
+            running: None,
+        }
+    }
+
+    pub(crate) fn command(&self) -> &str {
+        &self.periodic.command
+    }
+
+    /// Return whether the command is due to be run as of `elapsed` into the
+    /// run.  If it is, the next run is scheduled for the first multiple of
+    /// the interval after `elapsed`, so that runs that came due while the
+    /// event loop was busy aren't made up for all at once.
+    pub(crate) fn due(&mut self, elapsed: Duration) -> bool {
+        if elapsed < self.next {
+            return false;
+        }
+        while self.next <= elapsed {
+            self.next += self.periodic.interval;
+        }
+        true
+    }
+
+    /// Return whether the last run of the command is still going
+    pub(crate) fn is_running(&mut self) -> bool {
+        let running = self
+            .running
+            .as_mut()
+            .is_some_and(|child| matches!(child.try_wait(), Ok(None)));
+        if !running {
+            self.running = None;
+        }
+        running
+    }
+
+    /// Record that a new run of the command has been started
+    pub(crate) fn started(&mut self, child: Child) {
+        self.running = Some(child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "30s:curl -fsS https://example.com/ping?t=$ELAPSED_SECONDS".parse::<Periodic>(),
+            Ok(Periodic {
+                interval: Duration::from_secs(30),
+                command: String::from("curl -fsS https://example.com/ping?t=$ELAPSED_SECONDS"),
+            })
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("30s")]
+    #[case("30s:")]
+    #[case("0:date")]
+    #[case("soon:date")]
+    fn parse_invalid(#[case] s: &str) {
+        assert_eq!(s.parse::<Periodic>(), Err(ParsePeriodicError));
+    }
+
+    #[test]
+    fn due() {
+        let mut hook = PeriodicHook::new(Periodic {
+            interval: Duration::from_secs(5),
+            command: String::from("date"),
+        });
+        let secs = Duration::from_secs;
+        assert!(!hook.due(secs(1)));
+        assert!(!hook.due(secs(4)));
+        assert!(hook.due(secs(5)));
+        assert!(!hook.due(secs(6)));
+        assert!(!hook.due(secs(9)));
+        // The runs due at 10 and 15 seconds were missed; only one is made up.
+        assert!(hook.due(secs(17)));
+        assert!(!hook.due(secs(19)));
+        assert!(hook.due(secs(20)));
+    }
+}
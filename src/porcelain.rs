@@ -0,0 +1,252 @@
+//! Machine-readable status records for `--porcelain` & `--json`
+use crate::Stream;
+use crate::runid::run_id;
+use crate::sink::{Line, Sink};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Where to write porcelain records to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum PorcelainTarget {
+    Stderr,
+    /// An inherited file descriptor
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Fd(u32),
+}
+
+/// The format in which to write porcelain records
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum PorcelainStyle {
+    /// Space-separated words, for `--porcelain`
+    #[default]
+    Plain,
+    /// JSON objects, for `--json`
+    Json,
+}
+
+/// A writer of porcelain records, one per line.  In the plain style, these
+/// are:
+///
+/// - `tick <SECONDS>` — on every refresh, giving the whole number of seconds
+///   elapsed so far
+/// - `line out <BYTES>` / `line err <BYTES>` — for every line of output
+///   received from the command on stdout or stderr, giving its length
+/// - `exit <CODE> <SECONDS> <RUN_ID>` — once the command has finished, giving
+///   the return code that `elapsed` will exit with, the total elapsed time to
+///   a tenth of a second, and the ID of the run
+/// - `dropped <LINES>` — in place of lines of output that were dropped under
+///   `--sink-overflow=drop`, giving how many there were
+///
+/// In the JSON style, each record is instead an [`Event`].
+#[derive(Debug)]
+pub(crate) struct Porcelain<W> {
+    out: W,
+    style: PorcelainStyle,
+}
+
+impl<W: Write> Porcelain<W> {
+    pub(crate) fn new(out: W, style: PorcelainStyle) -> Porcelain<W> {
+        Porcelain { out, style }
+    }
+
+    fn tick(&mut self, elapsed: Duration) -> io::Result<()> {
+        match self.style {
+            PorcelainStyle::Plain => self.record(format_args!("tick {}", elapsed.as_secs())),
+            PorcelainStyle::Json => self.event(&Event::Tick {
+                elapsed: elapsed.as_secs_f64(),
+            }),
+        }
+    }
+
+    /// Record a line of output received on `stream`, giving the length of
+    /// `record` in the plain style or the contents of `text` in the JSON
+    /// style
+    fn line(&mut self, stream: Stream, record: &[u8], text: &[u8]) -> io::Result<()> {
+        match (self.style, stream) {
+            (PorcelainStyle::Plain, Stream::Stdout | Stream::Merged) => {
+                self.record(format_args!("line out {}", record.len()))
+            }
+            (PorcelainStyle::Plain, Stream::Stderr) => {
+                self.record(format_args!("line err {}", record.len()))
+            }
+            (PorcelainStyle::Json, Stream::Stdout | Stream::Merged) => {
+                self.event(&Event::Stdout { line: decode(text) })
+            }
+            (PorcelainStyle::Json, Stream::Stderr) => {
+                self.event(&Event::Stderr { line: decode(text) })
+            }
+        }
+    }
+
+    /// Record a warning about the run.  Only the JSON style has a record for
+    /// this.
+    fn warning(&mut self, msg: &str) -> io::Result<()> {
+        match self.style {
+            PorcelainStyle::Plain => Ok(()),
+            PorcelainStyle::Json => self.event(&Event::Warning {
+                message: Cow::Borrowed(msg),
+            }),
+        }
+    }
+
+    fn exit(&mut self, code: u8, elapsed: Duration) -> io::Result<()> {
+        match self.style {
+            PorcelainStyle::Plain => self.record(format_args!(
+                "exit {code} {}.{} {}",
+                elapsed.as_secs(),
+                elapsed.subsec_millis() / 100,
+                run_id()
+            )),
+            PorcelainStyle::Json => self.event(&Event::Exit {
+                code,
+                elapsed: elapsed.as_secs_f64(),
+                run_id: run_id(),
+            }),
+        }
+    }
+
+    /// Record that `dropped` lines of output were dropped because the records
+    /// couldn't be written fast enough
+    fn gap(&mut self, dropped: u64) -> io::Result<()> {
+        match self.style {
+            PorcelainStyle::Plain => self.record(format_args!("dropped {dropped}")),
+            PorcelainStyle::Json => self.event(&Event::Dropped { lines: dropped }),
+        }
+    }
+
+    /// Write a record in the plain style.  Each record is written with a
+    /// single call so that, when writing to stderr from a `--sink-queue`
+    /// thread, it isn't interleaved with the command's stderr.
+    fn record(&mut self, args: std::fmt::Arguments<'_>) -> io::Result<()> {
+        self.out.write_all(format!("{args}\n").as_bytes())?;
+        self.out.flush()
+    }
+
+    /// Write a record in the JSON style, likewise with a single call
+    fn event(&mut self, event: &Event<'_>) -> io::Result<()> {
+        let mut buf = serde_json::to_vec(event)?;
+        buf.push(b'\n');
+        self.out.write_all(&buf)?;
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Sink for Porcelain<W> {
+    fn line(&mut self, line: &Line<'_>) -> io::Result<()> {
+        Porcelain::line(self, line.stream, line.record, line.text)
+    }
+
+    fn warning(&mut self, msg: &str) -> io::Result<()> {
+        Porcelain::warning(self, msg)
+    }
+
+    fn tick(&mut self, elapsed: Duration) -> io::Result<()> {
+        Porcelain::tick(self, elapsed)
+    }
+
+    fn exit(&mut self, code: u8, elapsed: Duration) -> io::Result<()> {
+        Porcelain::exit(self, code, elapsed)
+    }
+
+    fn gap(&mut self, dropped: u64) -> io::Result<()> {
+        Porcelain::gap(self, dropped)
+    }
+}
+
+/// A record written by `--json`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum Event<'a> {
+    /// Written on every refresh, giving the number of seconds elapsed so far
+    Tick { elapsed: f64 },
+    /// Written for every line of output received from the command on stdout
+    Stdout { line: Cow<'a, str> },
+    /// Written for every line of output received from the command on stderr
+    Stderr { line: Cow<'a, str> },
+    /// Written once the command has finished, giving the return code that
+    /// `elapsed` will exit with, the total number of seconds elapsed, and the
+    /// ID of the run
+    Exit {
+        code: u8,
+        elapsed: f64,
+        run_id: &'static str,
+    },
+    /// Written whenever `elapsed` reports a warning about the run
+    Warning { message: Cow<'a, str> },
+    /// Written in place of lines of output that were dropped under
+    /// `--sink-overflow=drop`, giving how many there were
+    Dropped { lines: u64 },
+}
+
+/// Decode a line of output for a JSON record, dropping any trailing line
+/// ending
+fn decode(line: &[u8]) -> Cow<'_, str> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records() {
+        let mut porcelain = Porcelain::new(Vec::new(), PorcelainStyle::Plain);
+        porcelain.tick(Duration::from_millis(999)).unwrap();
+        porcelain
+            .line(Stream::Stdout, &[b'.'; 512], &[b'.'; 511])
+            .unwrap();
+        porcelain.line(Stream::Stderr, b"", b"").unwrap();
+        porcelain.tick(Duration::from_millis(73_050)).unwrap();
+        porcelain.warning("ignored").unwrap();
+        porcelain.gap(17).unwrap();
+        porcelain.exit(0, Duration::from_millis(73_250)).unwrap();
+        assert_eq!(
+            String::from_utf8(porcelain.out).unwrap(),
+            format!(
+                "tick 0\nline out 512\nline err 0\ntick 73\ndropped 17\nexit 0 73.2 {}\n",
+                run_id()
+            )
+        );
+    }
+
+    #[test]
+    fn json() {
+        let mut porcelain = Porcelain::new(Vec::new(), PorcelainStyle::Json);
+        porcelain.tick(Duration::from_millis(1500)).unwrap();
+        porcelain
+            .line(
+                Stream::Merged,
+                b"Hello, \"world\"\r\n",
+                b"Hello, \"world\"\r\n",
+            )
+            .unwrap();
+        porcelain
+            .line(Stream::Stderr, b"Bad byte: \xFF", b"Bad byte: \xFF")
+            .unwrap();
+        porcelain
+            .line(Stream::Stdout, b"NUL-terminated\0", b"NUL-terminated")
+            .unwrap();
+        porcelain.warning("disk is almost full").unwrap();
+        porcelain.gap(1).unwrap();
+        porcelain.exit(3, Duration::from_millis(73_250)).unwrap();
+        assert_eq!(
+            String::from_utf8(porcelain.out).unwrap(),
+            format!(
+                concat!(
+                    "{{\"event\":\"tick\",\"elapsed\":1.5}}\n",
+                    "{{\"event\":\"stdout\",\"line\":\"Hello, \\\"world\\\"\"}}\n",
+                    "{{\"event\":\"stderr\",\"line\":\"Bad byte: \u{FFFD}\"}}\n",
+                    "{{\"event\":\"stdout\",\"line\":\"NUL-terminated\"}}\n",
+                    "{{\"event\":\"warning\",\"message\":\"disk is almost full\"}}\n",
+                    "{{\"event\":\"dropped\",\"lines\":1}}\n",
+                    "{{\"event\":\"exit\",\"code\":3,\"elapsed\":73.25,\"run_id\":\"{}\"}}\n",
+                ),
+                run_id()
+            )
+        );
+    }
+}
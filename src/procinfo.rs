@@ -0,0 +1,612 @@
+use cfg_if::cfg_if;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How long a process must remain in uninterruptible sleep or as a zombie
+/// before [`StuckDetector`] reports it
+const STUCK_AFTER: Duration = Duration::from_secs(5);
+
+/// The maximum number of passes [`kill_tree()`] makes over a process tree
+/// stopping newly-forked processes before killing everything it's stopped
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const KILL_TREE_PASSES: usize = 10;
+
+/// The number of most common wait reasons displayed by [`WaitReasons`]
+const TOP_WAIT_REASONS: usize = 3;
+
+/// Resource usage statistics for the command's process tree
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct ProcStats {
+    /// Total CPU time (user plus system) used by the command and its
+    /// descendants, including descendants that have already exited
+    pub(crate) cpu_time: Duration,
+    /// The state of the command's process, as a single-character code (e.g.,
+    /// `R` for running or `S` for sleeping)
+    pub(crate) state: char,
+    /// The number of threads in the command's process
+    pub(crate) threads: u64,
+    /// The number of open file descriptors in the command's process, if they
+    /// could be counted
+    pub(crate) fds: Option<usize>,
+    /// The number of live descendants of the command's process
+    pub(crate) descendants: usize,
+    /// The total resident set size of the command and its live descendants,
+    /// in bytes
+    pub(crate) rss: u64,
+    /// The largest value of `rss` sampled so far during the run
+    pub(crate) rss_peak: u64,
+}
+
+impl ProcStats {
+    /// Carry over the RSS high-water mark from the previous sample, if any
+    pub(crate) fn with_peak_from(mut self, prev: Option<ProcStats>) -> ProcStats {
+        self.rss_peak = self.rss.max(prev.map_or(0, |st| st.rss_peak));
+        self
+    }
+}
+
+cfg_if! {
+    if #[cfg(target_os = "linux")] {
+        use nix::sys::signal::{Signal, kill};
+        use nix::unistd::{Pid, SysconfVar, sysconf};
+        use std::collections::HashSet;
+        use std::fs;
+
+        /// Sample resource usage statistics for the process with ID `pid` and
+        /// all of its descendants.  Returns `None` if the process no longer
+        /// exists or its statistics could not be read.
+        pub(crate) fn sample(pid: u32) -> Option<ProcStats> {
+            let ticks = sysconf(SysconfVar::CLK_TCK)
+                .ok()
+                .flatten()
+                .and_then(|t| u64::try_from(t).ok())
+                .filter(|&t| t > 0)?;
+            let page_size = sysconf(SysconfVar::PAGE_SIZE)
+                .ok()
+                .flatten()
+                .and_then(|p| u64::try_from(p).ok())?;
+            let procs = read_all_stats();
+            let tree = process_tree(&procs, pid);
+            let root = tree.first()?;
+            // A process's `cutime` & `cstime` only cover children that it has
+            // already reaped, so adding them up across the live tree doesn't
+            // count anything twice.
+            let total = tree
+                .iter()
+                .map(|st| st.utime + st.stime + st.cutime + st.cstime)
+                .sum::<u64>();
+            let rss = tree
+                .iter()
+                .map(|st| st.rss)
+                .sum::<u64>()
+                .saturating_mul(page_size);
+            Some(ProcStats {
+                cpu_time: ticks_to_duration(total, ticks),
+                state: root.state,
+                threads: root.num_threads,
+                fds: count_fds(pid),
+                descendants: tree.len() - 1,
+                rss,
+                rss_peak: rss,
+            })
+        }
+
+        /// Return the process with ID `pid` (if it's in `procs`) followed by
+        /// all of its descendants in `procs`
+        fn process_tree(procs: &[StatLine], pid: u32) -> Vec<&StatLine> {
+            let mut tree = procs.iter().filter(|st| st.pid == pid).collect::<Vec<_>>();
+            let mut i = 0;
+            while let Some(parent) = tree.get(i).map(|st| st.pid) {
+                tree.extend(procs.iter().filter(|st| st.ppid == parent && st.pid != parent));
+                i += 1;
+            }
+            tree
+        }
+
+        /// Kill the process with ID `pid` and all of its descendants.  As a
+        /// runaway process tree may keep forking while this is happening,
+        /// the processes are first frozen with `SIGSTOP`, repeating until no
+        /// new processes appear, and then all sent `SIGKILL`.
+        pub(crate) fn kill_tree(pid: u32) {
+            let mut stopped = HashSet::new();
+            for _ in 0..KILL_TREE_PASSES {
+                let procs = read_all_stats();
+                let mut new = false;
+                for st in process_tree(&procs, pid) {
+                    if stopped.insert(st.pid) {
+                        new = true;
+                        if let Ok(p) = i32::try_from(st.pid) {
+                            let _ = kill(Pid::from_raw(p), Signal::SIGSTOP);
+                        }
+                    }
+                }
+                if !new {
+                    break;
+                }
+            }
+            for p in stopped {
+                if let Ok(p) = i32::try_from(p) {
+                    let _ = kill(Pid::from_raw(p), Signal::SIGKILL);
+                }
+            }
+        }
+
+        /// Return the number of open file descriptors in the process with ID
+        /// `pid`, or `None` if they could not be counted (e.g., due to lack of
+        /// permissions)
+        fn count_fds(pid: u32) -> Option<usize> {
+            fs::read_dir(format!("/proc/{pid}/fd"))
+                .ok()
+                .map(|entries| entries.flatten().count())
+        }
+
+        /// Return the state of the process with ID `pid`, or `None` if it
+        /// could not be determined.  This is cheaper than [`sample()`], as it
+        /// only has to read information about a single process.
+        pub(crate) fn state(pid: u32) -> Option<char> {
+            let s = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            parse_stat(&s).map(|st| st.state)
+        }
+
+        /// Return the wait channel of the process with ID `pid` — the name
+        /// of the kernel function in which it's sleeping, or "0" if it's not
+        /// sleeping — or `None` if it could not be read
+        pub(crate) fn wait_channel(pid: u32) -> Option<String> {
+            fs::read_to_string(format!("/proc/{pid}/wchan")).ok()
+        }
+
+        /// Read & parse `/proc/<pid>/stat` for every process on the system,
+        /// skipping any that can't be read (e.g., because they've exited)
+        fn read_all_stats() -> Vec<StatLine> {
+            let Ok(entries) = fs::read_dir("/proc") else {
+                return Vec::new();
+            };
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| {
+                    entry
+                        .file_name()
+                        .to_str()
+                        .is_some_and(|name| name.bytes().all(|b| b.is_ascii_digit()))
+                })
+                .filter_map(|entry| fs::read_to_string(entry.path().join("stat")).ok())
+                .filter_map(|s| parse_stat(&s))
+                .collect()
+        }
+    } else {
+        /// Sample resource usage statistics for the process with ID `pid` and
+        /// all of its descendants.  This is not supported on the current
+        /// platform, and so `None` is always returned.
+        pub(crate) fn sample(_pid: u32) -> Option<ProcStats> {
+            None
+        }
+
+        /// Return the state of the process with ID `pid`.  This is not
+        /// supported on the current platform, and so `None` is always
+        /// returned.
+        pub(crate) fn state(_pid: u32) -> Option<char> {
+            None
+        }
+
+        /// Return the wait channel of the process with ID `pid`.  This is not
+        /// supported on the current platform, and so `None` is always
+        /// returned.
+        pub(crate) fn wait_channel(_pid: u32) -> Option<String> {
+            None
+        }
+
+        /// Kill the process with ID `pid` and all of its descendants.  This
+        /// is not supported on the current platform, and so nothing is done.
+        pub(crate) fn kill_tree(_pid: u32) {}
+    }
+}
+
+/// Resource usage of child processes that have exited and been waited for
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct ChildUsage {
+    /// CPU time spent in user mode
+    pub(crate) user_time: Duration,
+    /// CPU time spent in the kernel on the processes' behalf
+    pub(crate) system_time: Duration,
+    /// The largest maximum resident set size of any of the processes, in bytes
+    pub(crate) max_rss: u64,
+}
+
+impl ChildUsage {
+    /// Total CPU time (user plus system)
+    pub(crate) fn cpu_time(&self) -> Duration {
+        self.user_time + self.system_time
+    }
+
+    /// Return the usage accrued since `earlier` was sampled.  The CPU times
+    /// are reduced by those in `earlier`, but `max_rss` is kept as-is, as the
+    /// peak of processes that exited since then can't be told apart from the
+    /// peaks of those before.
+    pub(crate) fn since(&self, earlier: &ChildUsage) -> ChildUsage {
+        ChildUsage {
+            user_time: self.user_time.saturating_sub(earlier.user_time),
+            system_time: self.system_time.saturating_sub(earlier.system_time),
+            max_rss: self.max_rss,
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(unix)] {
+        use nix::sys::resource::{UsageWho, getrusage};
+        use nix::sys::time::{TimeVal, TimeValLike};
+
+        /// Return the resource usage of all of `elapsed`'s child processes
+        /// that have exited and been waited for, including their own waited-for
+        /// descendants.  Returns `None` if this could not be determined.
+        ///
+        /// Note that this includes helper processes (e.g., for
+        /// `--caffeinate`) as well as the command.
+        pub(crate) fn child_usage() -> Option<ChildUsage> {
+            let usage = getrusage(UsageWho::RUSAGE_CHILDREN).ok()?;
+            let max_rss = u64::try_from(usage.max_rss()).ok()?;
+            // `ru_maxrss` is in bytes on Apple platforms and in kibibytes
+            // everywhere else.
+            let max_rss = if cfg!(target_vendor = "apple") {
+                max_rss
+            } else {
+                max_rss.saturating_mul(1024)
+            };
+            let duration = |tv: TimeVal| {
+                u64::try_from(tv.num_microseconds())
+                    .ok()
+                    .map(Duration::from_micros)
+            };
+            Some(ChildUsage {
+                user_time: duration(usage.user_time())?,
+                system_time: duration(usage.system_time())?,
+                max_rss,
+            })
+        }
+    } else {
+        /// Return the resource usage of all of `elapsed`'s child processes
+        /// that have exited.  This is not supported on the current platform,
+        /// and so `None` is always returned.
+        pub(crate) fn child_usage() -> Option<ChildUsage> {
+            None
+        }
+    }
+}
+
+/// The fields of `/proc/<pid>/stat` that we care about
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct StatLine {
+    pid: u32,
+    state: char,
+    ppid: u32,
+    utime: u64,
+    stime: u64,
+    cutime: u64,
+    cstime: u64,
+    num_threads: u64,
+    /// Resident set size, in pages
+    rss: u64,
+}
+
+/// Parse the contents of a `/proc/<pid>/stat` file.  See `proc_pid_stat(5)`
+/// for the format.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(s: &str) -> Option<StatLine> {
+    // The command name is enclosed in parentheses and may itself contain
+    // spaces & parentheses, so find the *last* closing parenthesis.
+    let (head, tail) = s.rsplit_once(')')?;
+    let (pid, _) = head.split_once('(')?;
+    let pid = pid.trim().parse().ok()?;
+    // `fields[0]` is field 3 (the process state) in `proc_pid_stat(5)`'s
+    // numbering.
+    let fields = tail.split_whitespace().collect::<Vec<_>>();
+    let field = |n: usize| fields.get(n - 3).copied();
+    Some(StatLine {
+        pid,
+        state: field(3)?.chars().next()?,
+        ppid: field(4)?.parse().ok()?,
+        utime: field(14)?.parse().ok()?,
+        stime: field(15)?.parse().ok()?,
+        cutime: field(16)?.parse().ok()?,
+        cstime: field(17)?.parse().ok()?,
+        num_threads: field(20)?.parse().ok()?,
+        rss: field(24)?.parse().ok()?,
+    })
+}
+
+/// Tracks whether a process has been sitting in a state that suggests it's
+/// wedged — uninterruptible sleep (`D`), typically waiting on a hung NFS mount
+/// or a dead device, or a zombie (`Z`) — for a suspiciously long time
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) struct StuckDetector {
+    /// The suspicious state the process is currently in and when it was first
+    /// observed in it
+    since: Option<(char, Instant)>,
+    /// True if the current stretch in a suspicious state has already been
+    /// reported
+    reported: bool,
+}
+
+impl StuckDetector {
+    /// Record that the process was observed in state `state` at time `now`.
+    /// Returns the state if the process has now been in the same suspicious
+    /// state for at least [`STUCK_AFTER`] and this hasn't been reported yet.
+    pub(crate) fn observe(&mut self, state: Option<char>, now: Instant) -> Option<char> {
+        match (state, self.since) {
+            (Some(st @ ('D' | 'Z')), Some((prev, since))) if st == prev => {
+                if !self.reported && now.saturating_duration_since(since) >= STUCK_AFTER {
+                    self.reported = true;
+                    return Some(st);
+                }
+            }
+            (Some(st @ ('D' | 'Z')), _) => {
+                self.since = Some((st, now));
+                self.reported = false;
+            }
+            _ => {
+                self.since = None;
+                self.reported = false;
+            }
+        }
+        None
+    }
+}
+
+/// A tally of what the command's process was waiting on each time it was
+/// sampled, for `--wait-reasons`
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct WaitReasons {
+    /// The number of samples taken in each wait channel, with samples in
+    /// which the process wasn't waiting counted under "running"
+    counts: BTreeMap<String, u64>,
+    samples: u64,
+}
+
+impl WaitReasons {
+    pub(crate) fn new() -> WaitReasons {
+        WaitReasons::default()
+    }
+
+    /// Record a sample of the process's wait channel as returned by
+    /// [`wait_channel()`]
+    pub(crate) fn record(&mut self, wchan: &str) {
+        let wchan = wchan.trim();
+        let reason = if wchan.is_empty() || wchan == "0" {
+            "running"
+        } else {
+            wchan
+        };
+        *self.counts.entry(reason.to_owned()).or_default() += 1;
+        self.samples += 1;
+    }
+}
+
+impl fmt::Display for WaitReasons {
+    /// Display the most common wait reasons along with the percentage of
+    /// samples in which each was seen, e.g., "`pipe_read 80%, running 20%`"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.samples == 0 {
+            return write!(f, "none");
+        }
+        let mut reasons = self.counts.iter().collect::<Vec<_>>();
+        reasons.sort_by(|(_, a), (_, b)| b.cmp(a));
+        for (i, (reason, &n)) in reasons.into_iter().take(TOP_WAIT_REASONS).enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            let pct = (n * 200 + self.samples) / (self.samples * 2);
+            write!(f, "{reason} {pct}%")?;
+        }
+        Ok(())
+    }
+}
+
+/// Return a description of a process state code for use in messages
+pub(crate) fn describe_state(state: char) -> &'static str {
+    match state {
+        'R' => "running",
+        'S' => "sleeping",
+        'D' => "in uninterruptible sleep",
+        'Z' => "a zombie",
+        'T' => "stopped",
+        't' => "stopped by a debugger",
+        'X' => "dead",
+        'I' => "idle",
+        _ => "in an unknown state",
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn ticks_to_duration(ticks: u64, per_sec: u64) -> Duration {
+    let secs = ticks / per_sec;
+    let nanos = (ticks % per_sec) * 1_000_000_000 / per_sec;
+    Duration::new(secs, u32::try_from(nanos).unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stat_line() {
+        let s = concat!(
+            "12345 (python3) S 12340 12345 12340 34816 12345 4194304 1580 0 0 0 ",
+            "237 41 3 2 20 0 1 0 5611402 19111936 2303 18446744073709551615 1 1 ",
+            "0 0 0 0 0 16781312 2 0 0 0 17 5 0 0 0 0 0 0 0 0 0 0 0 0 0\n"
+        );
+        assert_eq!(
+            parse_stat(s),
+            Some(StatLine {
+                pid: 12345,
+                state: 'S',
+                ppid: 12340,
+                utime: 237,
+                stime: 41,
+                cutime: 3,
+                cstime: 2,
+                num_threads: 1,
+                rss: 2303,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_stat_line_weird_comm() {
+        let s = concat!(
+            "42 (a) b (c)) R 1 42 42 0 -1 4194560 100 0 0 0 ",
+            "5 6 7 8 20 0 1 0 100 1000 10 18446744073709551615 1 1 ",
+            "0 0 0 0 0 0 0 0 0 0 17 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n"
+        );
+        assert_eq!(
+            parse_stat(s),
+            Some(StatLine {
+                pid: 42,
+                state: 'R',
+                ppid: 1,
+                utime: 5,
+                stime: 6,
+                cutime: 7,
+                cstime: 8,
+                num_threads: 1,
+                rss: 10,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_stat_line_truncated() {
+        assert_eq!(parse_stat("42 (foo) R 1 42 42 0"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sample_self() {
+        let stats = sample(std::process::id()).unwrap();
+        assert!(stats.threads >= 1);
+        assert!(stats.fds.is_some_and(|n| n >= 3));
+        assert!(stats.rss > 0);
+        assert_eq!(stats.rss_peak, stats.rss);
+    }
+
+    #[test]
+    fn rss_peak() {
+        let stats = |rss| ProcStats {
+            rss,
+            rss_peak: rss,
+            ..ProcStats::default()
+        };
+        let first = stats(100).with_peak_from(None);
+        assert_eq!(first.rss_peak, 100);
+        let second = stats(300).with_peak_from(Some(first));
+        assert_eq!(second.rss_peak, 300);
+        let third = stats(200).with_peak_from(Some(second));
+        assert_eq!((third.rss, third.rss_peak), (200, 300));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tree() {
+        let stat = |pid, ppid| StatLine {
+            pid,
+            state: 'S',
+            ppid,
+            utime: 0,
+            stime: 0,
+            cutime: 0,
+            cstime: 0,
+            num_threads: 1,
+            rss: 0,
+        };
+        let procs = [
+            stat(1, 0),
+            stat(10, 1),
+            stat(11, 10),
+            stat(12, 1),
+            stat(13, 11),
+            stat(14, 11),
+        ];
+        let pids = |pid| {
+            process_tree(&procs, pid)
+                .into_iter()
+                .map(|st| st.pid)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(pids(10), [10, 11, 13, 14]);
+        assert_eq!(pids(12), [12]);
+        assert_eq!(pids(99), Vec::<u32>::new());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn child_usage_available() {
+        assert!(child_usage().is_some());
+    }
+
+    #[test]
+    fn child_usage_since() {
+        let earlier = ChildUsage {
+            user_time: Duration::from_millis(1500),
+            system_time: Duration::from_millis(200),
+            max_rss: 4096,
+        };
+        let later = ChildUsage {
+            user_time: Duration::from_millis(4000),
+            system_time: Duration::from_millis(700),
+            max_rss: 8192,
+        };
+        assert_eq!(
+            later.since(&earlier),
+            ChildUsage {
+                user_time: Duration::from_millis(2500),
+                system_time: Duration::from_millis(500),
+                max_rss: 8192,
+            }
+        );
+    }
+
+    #[test]
+    fn ticks() {
+        assert_eq!(ticks_to_duration(0, 100), Duration::ZERO);
+        assert_eq!(ticks_to_duration(250, 100), Duration::from_millis(2500));
+        assert_eq!(ticks_to_duration(7, 3), Duration::new(2, 333_333_333));
+    }
+
+    #[test]
+    fn stuck_detector() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut detector = StuckDetector::default();
+        assert_eq!(detector.observe(Some('S'), at(0)), None);
+        assert_eq!(detector.observe(Some('D'), at(1)), None);
+        assert_eq!(detector.observe(Some('D'), at(3)), None);
+        assert_eq!(detector.observe(Some('D'), at(6)), Some('D'));
+        assert_eq!(detector.observe(Some('D'), at(7)), None);
+        assert_eq!(detector.observe(Some('R'), at(8)), None);
+        assert_eq!(detector.observe(Some('D'), at(9)), None);
+        assert_eq!(detector.observe(Some('Z'), at(12)), None);
+        assert_eq!(detector.observe(None, at(15)), None);
+        assert_eq!(detector.observe(Some('Z'), at(16)), None);
+        assert_eq!(detector.observe(Some('Z'), at(21)), Some('Z'));
+    }
+
+    #[test]
+    fn wait_reasons() {
+        let mut reasons = WaitReasons::new();
+        assert_eq!(reasons.to_string(), "none");
+        for _ in 0..5 {
+            reasons.record("pipe_read\n");
+        }
+        for _ in 0..2 {
+            reasons.record("do_wait");
+        }
+        reasons.record("0");
+        reasons.record("hrtimer_nanosleep");
+        reasons.record("0");
+        reasons.record("");
+        assert_eq!(
+            reasons.to_string(),
+            "pipe_read 45%, running 27%, do_wait 18%"
+        );
+    }
+}
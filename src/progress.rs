@@ -0,0 +1,175 @@
+//! Recognizing the progress reports that some commands emit as OSC 9;4
+//! escape sequences (as introduced by `ConEmu` and supported by Windows
+//! Terminal and others)
+use std::ops::Range;
+
+/// The start of an OSC 9;4 escape sequence
+const INTRO: &[u8] = b"\x1B]9;4;";
+
+/// What a progress report says about how far along the command is
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Report {
+    /// The command is the given percentage of the way done
+    Percent(u8),
+    /// The command's progress is indeterminate, or it has stopped reporting
+    /// progress
+    Unknown,
+    /// The command changed the state of its progress (e.g., to "error" or
+    /// "paused") without giving a new percentage
+    Unchanged,
+}
+
+/// An OSC 9;4 progress report found in a record of output
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Sequence {
+    /// Where the escape sequence (including its terminator) lies in the
+    /// record
+    pub(crate) range: Range<usize>,
+    pub(crate) report: Report,
+}
+
+/// Find all of the complete, well-formed OSC 9;4 escape sequences in
+/// `record`.  Sequences may be terminated by either BEL or ST.
+pub(crate) fn scan(record: &[u8]) -> Vec<Sequence> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = find(&record[pos..], INTRO) {
+        let start = pos + offset;
+        let params_start = start + INTRO.len();
+        let Some(params_len) = record[params_start..]
+            .iter()
+            .position(|&b| b == b'\x07' || b == b'\x1B')
+        else {
+            break;
+        };
+        let params_end = params_start + params_len;
+        let end = match record[params_end..] {
+            [b'\x07', ..] => Some(params_end + 1),
+            [b'\x1B', b'\\', ..] => Some(params_end + 2),
+            _ => None,
+        };
+        match (end, parse_params(&record[params_start..params_end])) {
+            (Some(end), Some(report)) => {
+                found.push(Sequence {
+                    range: start..end,
+                    report,
+                });
+                pos = end;
+            }
+            _ => pos = start + 1,
+        }
+    }
+    found
+}
+
+/// Return `record` with the given sequences removed
+pub(crate) fn strip(record: &[u8], sequences: &[Sequence]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(record.len());
+    let mut pos = 0;
+    for seq in sequences {
+        out.extend_from_slice(&record[pos..seq.range.start]);
+        pos = seq.range.end;
+    }
+    out.extend_from_slice(&record[pos..]);
+    out
+}
+
+/// Return whether `record` consists of nothing but OSC 9;4 escape sequences
+/// (including if it's empty)
+pub(crate) fn is_progress_only(record: &[u8]) -> bool {
+    let sequences = scan(record);
+    sequences.iter().map(|seq| seq.range.len()).sum::<usize>() == record.len()
+}
+
+/// Parse the `<STATE>[;<PROGRESS>]` parameters of an OSC 9;4 sequence
+fn parse_params(params: &[u8]) -> Option<Report> {
+    let params = std::str::from_utf8(params).ok()?;
+    let (state, value) = match params.split_once(';') {
+        Some((state, value)) => (state, Some(value)),
+        None => (params, None),
+    };
+    let value = match value.filter(|v| !v.is_empty()) {
+        Some(v) => Some(u8::try_from(v.parse::<u32>().ok()?.min(100)).ok()?),
+        None => None,
+    };
+    match (state, value) {
+        ("0" | "3", _) => Some(Report::Unknown),
+        ("1" | "2" | "4", Some(pct)) => Some(Report::Percent(pct)),
+        ("1", None) => Some(Report::Percent(0)),
+        ("2" | "4", None) => Some(Report::Unchanged),
+        _ => None,
+    }
+}
+
+/// Return the index of the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(b"\x1B]9;4;1;42\x07", Report::Percent(42))]
+    #[case(b"\x1B]9;4;1;42\x1B\\", Report::Percent(42))]
+    #[case(b"\x1B]9;4;1;250\x07", Report::Percent(100))]
+    #[case(b"\x1B]9;4;1\x07", Report::Percent(0))]
+    #[case(b"\x1B]9;4;2;17\x07", Report::Percent(17))]
+    #[case(b"\x1B]9;4;2\x07", Report::Unchanged)]
+    #[case(b"\x1B]9;4;4;\x07", Report::Unchanged)]
+    #[case(b"\x1B]9;4;3;0\x07", Report::Unknown)]
+    #[case(b"\x1B]9;4;0\x07", Report::Unknown)]
+    fn scan_one(#[case] seq: &[u8], #[case] report: Report) {
+        assert_eq!(
+            scan(seq),
+            [Sequence {
+                range: 0..seq.len(),
+                report
+            }]
+        );
+    }
+
+    #[rstest]
+    #[case(b"Downloading...\n")]
+    #[case(b"\x1B]9;Build finished\x07")]
+    #[case(b"\x1B]9;4;5;10\x07")]
+    #[case(b"\x1B]9;4;1;ten\x07")]
+    #[case(b"\x1B]9;4;1;10")]
+    #[case(b"\x1B]9;4;1;10\x1B[m")]
+    fn scan_none(#[case] record: &[u8]) {
+        assert_eq!(scan(record), []);
+    }
+
+    #[test]
+    fn scan_and_strip() {
+        let record = b"\x1B]9;4;1;10\x07Compiling foo\x1B]9;4;1;20\x1B\\ (2/10)\n";
+        let sequences = scan(record);
+        assert_eq!(
+            sequences,
+            [
+                Sequence {
+                    range: 0..11,
+                    report: Report::Percent(10)
+                },
+                Sequence {
+                    range: 24..36,
+                    report: Report::Percent(20)
+                },
+            ]
+        );
+        assert_eq!(strip(record, &sequences), b"Compiling foo (2/10)\n");
+        assert!(!is_progress_only(record));
+    }
+
+    #[rstest]
+    #[case(b"", true)]
+    #[case(b"\x1B]9;4;1;10\x07", true)]
+    #[case(b"\x1B]9;4;1;10\x07\x1B]9;4;0\x1B\\", true)]
+    #[case(b"\x1B]9;4;1;10\x07 ", false)]
+    #[case(b"Password: ", false)]
+    fn progress_only(#[case] record: &[u8], #[case] only: bool) {
+        assert_eq!(is_progress_only(record), only);
+    }
+}
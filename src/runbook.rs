@@ -0,0 +1,766 @@
+//! Implementation of `--runbook`, which runs a sequence of named steps defined
+//! in a TOML file, each under its own `elapsed`, and reports how long each one
+//! took
+use crate::TIMED_OUT_EXIT_CODE;
+use crate::summary::format_duration;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+
+/// A list of steps to run, as read from a runbook file
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Runbook {
+    #[serde(rename = "step", default)]
+    steps: Vec<Step>,
+}
+
+impl Runbook {
+    pub(crate) fn load(path: &Path) -> Result<Runbook, RunbookError> {
+        let src = std::fs::read_to_string(path).map_err(RunbookError::Read)?;
+        src.parse()
+    }
+
+    /// Split the steps into groups to run one after another.  Each run of
+    /// consecutive steps with `parallel = true` forms a group whose steps are
+    /// run at the same time; every other step forms a group by itself.
+    fn groups(&self) -> Vec<&[Step]> {
+        let mut groups = Vec::new();
+        let mut rest = &self.steps[..];
+        while let Some(first) = rest.first() {
+            let size = if first.parallel {
+                rest.iter().take_while(|st| st.parallel).count()
+            } else {
+                1
+            };
+            let (group, r) = rest.split_at(size);
+            groups.push(group);
+            rest = r;
+        }
+        groups
+    }
+}
+
+impl std::str::FromStr for Runbook {
+    type Err = RunbookError;
+
+    fn from_str(s: &str) -> Result<Runbook, RunbookError> {
+        let runbook = toml::from_str::<Runbook>(s)?;
+        if runbook.steps.is_empty() {
+            return Err(RunbookError::NoSteps);
+        }
+        // The names of the steps in the groups before the current one
+        let mut earlier = HashSet::new();
+        for group in runbook.groups() {
+            for (i, step) in group.iter().enumerate() {
+                if step.name.is_empty() {
+                    return Err(RunbookError::EmptyName);
+                }
+                if earlier.contains(step.name.as_str())
+                    || group[..i].iter().any(|st| st.name == step.name)
+                {
+                    return Err(RunbookError::DuplicateName(step.name.clone()));
+                }
+                if step.run.argv().is_empty() {
+                    return Err(RunbookError::EmptyCommand(step.name.clone()));
+                }
+                if let Some(dep) = step
+                    .depends_on
+                    .iter()
+                    .find(|dep| !earlier.contains(dep.as_str()))
+                {
+                    return Err(RunbookError::BadDependency(step.name.clone(), dep.clone()));
+                }
+            }
+            earlier.extend(group.iter().map(|st| st.name.as_str()));
+        }
+        Ok(runbook)
+    }
+}
+
+/// A single named command in a runbook
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    name: String,
+    run: StepCommand,
+    /// Whether to run the step at the same time as the adjacent steps that
+    /// also have `parallel` set
+    #[serde(default)]
+    parallel: bool,
+    /// The maximum number of seconds to let the step run for before
+    /// terminating it
+    timeout: Option<u64>,
+    /// Whether a failure of the step should be ignored, neither keeping later
+    /// steps from running nor affecting `elapsed`'s return code
+    #[serde(default)]
+    continue_on_error: bool,
+    /// The names of earlier steps that must all have succeeded for this step
+    /// to run
+    #[serde(default)]
+    depends_on: Vec<String>,
+    #[serde(default)]
+    when: When,
+}
+
+impl Step {
+    /// Determine whether the step should be run given whether a step has
+    /// failed so far and the outcomes of the steps run so far, returning the
+    /// reason why not if it should be skipped
+    fn skip_reason(
+        &self,
+        failed_so_far: bool,
+        succeeded: &HashMap<&str, bool>,
+    ) -> Option<SkipReason> {
+        match (self.when, failed_so_far) {
+            (When::Success, true) => return Some(SkipReason::EarlierFailure),
+            (When::Failure, false) => return Some(SkipReason::NoEarlierFailure),
+            _ => (),
+        }
+        self.depends_on
+            .iter()
+            .find(|dep| !succeeded.get(dep.as_str()).copied().unwrap_or_default())
+            .map(|dep| SkipReason::Dependency(dep.clone()))
+    }
+}
+
+/// When to run a step, based on whether any earlier step has failed
+/// (ignoring failures of steps with `continue_on_error` set)
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum When {
+    /// Only run the step if no earlier step has failed
+    #[default]
+    Success,
+    /// Only run the step if an earlier step has failed
+    Failure,
+    /// Run the step regardless of whether an earlier step has failed
+    Always,
+}
+
+/// The command for a step, either as a string to pass to the shell or as a
+/// list of arguments to run directly
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(untagged)]
+enum StepCommand {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl StepCommand {
+    fn argv(&self) -> Vec<&str> {
+        match self {
+            StepCommand::Shell(s) => {
+                cfg_if::cfg_if! {
+                    if #[cfg(windows)] {
+                        vec!["cmd", "/C", s]
+                    } else {
+                        vec!["sh", "-c", s]
+                    }
+                }
+            }
+            StepCommand::Argv(args) => args.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// How a step ended
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Outcome {
+    /// The step's `elapsed` exited with the given return code, or was killed
+    /// by a signal if `None`
+    Exited(Option<i32>),
+    /// The step ran past its timeout and was terminated
+    TimedOut,
+    /// The step was not run
+    Skipped(SkipReason),
+}
+
+impl Outcome {
+    fn from_status(status: ExitStatus) -> Outcome {
+        Outcome::Exited(status.code())
+    }
+
+    fn success(&self) -> bool {
+        *self == Outcome::Exited(Some(0))
+    }
+
+    /// The return code for `elapsed` to exit with if this is the outcome of
+    /// the first failed step
+    fn exit_code(&self) -> u8 {
+        match self {
+            Outcome::Exited(Some(rc)) => u8::try_from(*rc).unwrap_or(1),
+            Outcome::TimedOut => TIMED_OUT_EXIT_CODE,
+            Outcome::Exited(None) | Outcome::Skipped(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Exited(Some(rc)) => write!(f, "exited with code {rc}"),
+            Outcome::Exited(None) => write!(f, "terminated abnormally"),
+            Outcome::TimedOut => write!(f, "timed out"),
+            Outcome::Skipped(reason) => write!(f, "skipped, as {reason}"),
+        }
+    }
+}
+
+/// Why a step was skipped
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum SkipReason {
+    /// The step has `when = "success"`, and an earlier step failed
+    EarlierFailure,
+    /// The step has `when = "failure"`, and no earlier step failed
+    NoEarlierFailure,
+    /// The step depends on the given step, which did not succeed
+    Dependency(String),
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::EarlierFailure => write!(f, "an earlier step failed"),
+            SkipReason::NoEarlierFailure => write!(f, "no earlier step failed"),
+            SkipReason::Dependency(name) => write!(f, "{name:?} did not succeed"),
+        }
+    }
+}
+
+/// The overall verdict on a step, as shown in the final report
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Verdict {
+    Ok,
+    Failed,
+    /// The step failed, but it has `continue_on_error` set
+    Ignored,
+    Skipped,
+}
+
+impl Verdict {
+    fn new(outcome: &Outcome, continue_on_error: bool) -> Verdict {
+        match outcome {
+            Outcome::Skipped(_) => Verdict::Skipped,
+            o if o.success() => Verdict::Ok,
+            _ if continue_on_error => Verdict::Ignored,
+            _ => Verdict::Failed,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Verdict::Ok => "ok",
+            Verdict::Failed => "FAILED",
+            Verdict::Ignored => "ignored",
+            Verdict::Skipped => "skipped",
+        }
+    }
+}
+
+/// The final table of step outcomes & timings
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Report {
+    steps: Vec<ReportRow>,
+    total: Duration,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ReportRow {
+    name: String,
+    verdict: Verdict,
+    outcome: Outcome,
+    /// How long the step ran for, or `None` if it was skipped
+    duration: Option<Duration>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self
+            .steps
+            .iter()
+            .map(|row| row.name.len())
+            .chain(["Step".len(), "Total".len()])
+            .max()
+            .unwrap_or_default();
+        let verdict_width = self
+            .steps
+            .iter()
+            .map(|row| row.verdict.as_str().len())
+            .chain(["Result".len()])
+            .max()
+            .unwrap_or_default();
+        // The "Result" column already says when a step was skipped, so just
+        // give the reason in the "Status" column.
+        let outcomes = self
+            .steps
+            .iter()
+            .map(|row| match &row.outcome {
+                Outcome::Skipped(reason) => reason.to_string(),
+                outcome => outcome.to_string(),
+            })
+            .collect::<Vec<_>>();
+        let outcome_width = outcomes
+            .iter()
+            .map(String::len)
+            .chain(["Status".len()])
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            f,
+            "{:name_width$}  {:verdict_width$}  {:outcome_width$}  Duration",
+            "Step", "Result", "Status"
+        )?;
+        for (row, outcome) in self.steps.iter().zip(outcomes) {
+            let name = &row.name;
+            let verdict = row.verdict.as_str();
+            match row.duration {
+                Some(d) => writeln!(
+                    f,
+                    "{name:name_width$}  {verdict:verdict_width$}  {outcome:outcome_width$}  {}",
+                    format_duration(d)
+                )?,
+                None => writeln!(f, "{name:name_width$}  {verdict:verdict_width$}  {outcome}")?,
+            }
+        }
+        writeln!(
+            f,
+            "{:name_width$}  {:verdict_width$}  {:outcome_width$}  {}",
+            "Total",
+            "",
+            "",
+            format_duration(self.total)
+        )
+    }
+}
+
+/// Run the steps of `runbook` and then print a table of the steps' outcomes &
+/// durations to stderr.  Returns the return code of the first failed step
+/// (not counting steps with `continue_on_error` set), or 0 if there were no
+/// such failures.
+#[tokio::main(flavor = "current_thread")]
+pub(crate) async fn run(runbook: Runbook) -> Result<u8, RunbookError> {
+    let exe = std::env::current_exe().map_err(RunbookError::CurrentExe)?;
+    let start = Instant::now();
+    let mut steps = Vec::with_capacity(runbook.steps.len());
+    // Maps the names of the steps run or skipped so far to whether they
+    // succeeded
+    let mut succeeded = HashMap::new();
+    let mut failure: Option<Outcome> = None;
+    for group in runbook.groups() {
+        let piped = group.len() > 1;
+        let mut handles = Vec::with_capacity(group.len());
+        for step in group {
+            handles.push(match step.skip_reason(failure.is_some(), &succeeded) {
+                Some(reason) => Err(reason),
+                None => Ok(start_step(&exe, step, piped)?),
+            });
+        }
+        for (step, handle) in group.iter().zip(handles) {
+            let (outcome, duration) = match handle {
+                Ok(handle) => {
+                    let (outcome, duration) = handle.await.map_err(RunbookError::Join)??;
+                    (outcome, Some(duration))
+                }
+                Err(reason) => (Outcome::Skipped(reason), None),
+            };
+            let mut err = io::stderr().lock();
+            match duration {
+                Some(d) => writeln!(
+                    err,
+                    "[{}] {outcome} after {}",
+                    step.name,
+                    format_duration(d)
+                ),
+                None => writeln!(err, "[{}] {outcome}", step.name),
+            }
+            .map_err(RunbookError::Write)?;
+            let verdict = Verdict::new(&outcome, step.continue_on_error);
+            if verdict == Verdict::Failed && failure.is_none() {
+                failure = Some(outcome.clone());
+            }
+            succeeded.insert(step.name.as_str(), outcome.success());
+            steps.push(ReportRow {
+                name: step.name.clone(),
+                verdict,
+                outcome,
+                duration,
+            });
+        }
+    }
+    let report = Report {
+        steps,
+        total: start.elapsed(),
+    };
+    let mut err = io::stderr().lock();
+    write!(err, "{report}").map_err(RunbookError::Write)?;
+    err.flush().map_err(RunbookError::Write)?;
+    Ok(failure.map_or(0, |o| o.exit_code()))
+}
+
+/// A handle for awaiting the outcome & duration of a running step
+type StepHandle = JoinHandle<Result<(Outcome, Duration), RunbookError>>;
+
+/// Start running `step` under `exe` (i.e., `elapsed`) and return a handle for
+/// awaiting its outcome & duration.  If `piped` is true, the step's output is
+/// read through pipes and forwarded with the step's name prefixed to each
+/// line; as the step's `elapsed` then isn't writing to a terminal, it won't
+/// show a status line that would fight with those of other steps.
+fn start_step(exe: &Path, step: &Step, piped: bool) -> Result<StepHandle, RunbookError> {
+    let start = Instant::now();
+    let mut cmd = Command::new(exe);
+    cmd.arg("--format")
+        .arg(step_format(&step.name))
+        .arg("--")
+        .args(step.run.argv());
+    if piped {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| RunbookError::Spawn(step.name.clone(), e))?;
+    let prefix = format!("[{}] ", step.name);
+    let forwarders = [
+        child
+            .stdout
+            .take()
+            .map(|out| tokio::spawn(forward(out, prefix.clone(), io::stdout()))),
+        child
+            .stderr
+            .take()
+            .map(|err| tokio::spawn(forward(err, prefix, io::stderr()))),
+    ];
+    let timeout = step.timeout.map(Duration::from_secs);
+    Ok(tokio::spawn(async move {
+        let outcome = wait_for_step(&mut child, timeout).await?;
+        for handle in forwarders.into_iter().flatten() {
+            handle.await.map_err(RunbookError::Join)?;
+        }
+        Ok((outcome, start.elapsed()))
+    }))
+}
+
+/// Return the `--format` for the status line of the step named `name`, with
+/// the characters that `--format` treats specially escaped so that the name is
+/// shown as-is
+fn step_format(name: &str) -> String {
+    let name = name.replace('\\', "\\\\").replace('%', "%%");
+    format!("[{name}] Elapsed: %H:%M:%S")
+}
+
+/// Wait for a step's process to exit, terminating it if it runs for longer
+/// than `timeout`
+async fn wait_for_step(
+    child: &mut Child,
+    timeout: Option<Duration>,
+) -> Result<Outcome, RunbookError> {
+    let Some(timeout) = timeout else {
+        return child
+            .wait()
+            .await
+            .map(Outcome::from_status)
+            .map_err(RunbookError::Wait);
+    };
+    if let Ok(r) = tokio::time::timeout(timeout, child.wait()).await {
+        return r.map(Outcome::from_status).map_err(RunbookError::Wait);
+    }
+    // On Unix, send the step's `elapsed` a SIGTERM so that it can pass it on
+    // to its command.
+    #[cfg(unix)]
+    if let Some(pid) = child.id().and_then(|pid| i32::try_from(pid).ok()) {
+        use nix::sys::signal::{Signal, kill};
+        let _ = kill(nix::unistd::Pid::from_raw(pid), Signal::SIGTERM);
+    }
+    #[cfg(not(unix))]
+    let _ = child.start_kill();
+    child.wait().await.map_err(RunbookError::Wait)?;
+    Ok(Outcome::TimedOut)
+}
+
+/// Copy lines from `reader` to `out`, prefixing each one with `prefix`
+async fn forward<R, W>(reader: R, prefix: String, mut out: W)
+where
+    R: AsyncRead + Unpin,
+    W: Write,
+{
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                if !line.ends_with(b"\n") {
+                    line.push(b'\n');
+                }
+                // Errors writing the output are ignored, as there's no one
+                // left to report them to if stdout or stderr is broken.
+                let _ = out
+                    .write_all(prefix.as_bytes())
+                    .and_then(|()| out.write_all(&line))
+                    .and_then(|()| out.flush());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum RunbookError {
+    #[error("failed to read runbook file: {0}")]
+    Read(io::Error),
+    #[error("invalid runbook: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("runbook does not define any steps")]
+    NoSteps,
+    #[error("runbook step names cannot be empty")]
+    EmptyName,
+    #[error("runbook defines multiple steps named {0:?}")]
+    DuplicateName(String),
+    #[error("runbook step {0:?} has an empty command")]
+    EmptyCommand(String),
+    #[error("runbook step {0:?} depends on {1:?}, which is not run before it")]
+    BadDependency(String, String),
+    #[error("failed to determine path to elapsed executable: {0}")]
+    CurrentExe(io::Error),
+    #[error("failed to start runbook step {0:?}: {1}")]
+    Spawn(String, io::Error),
+    #[error("error waiting for runbook step to finish: {0}")]
+    Wait(io::Error),
+    #[error("runbook step task failed: {0}")]
+    Join(tokio::task::JoinError),
+    #[error("failed to write to stderr: {0}")]
+    Write(io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{Format, Snapshot};
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+
+    #[test]
+    fn parse() {
+        let runbook = concat!(
+            "[[step]]\n",
+            "name = \"build\"\n",
+            "run = \"cargo build\"\n",
+            "timeout = 600\n",
+            "\n",
+            "[[step]]\n",
+            "name = \"lint\"\n",
+            "run = [\"cargo\", \"clippy\"]\n",
+            "parallel = true\n",
+            "\n",
+            "[[step]]\n",
+            "name = \"notify\"\n",
+            "run = \"notify-send 'Build failed'\"\n",
+            "when = \"failure\"\n",
+            "depends_on = [\"build\"]\n",
+            "continue_on_error = true\n",
+        )
+        .parse::<Runbook>()
+        .unwrap();
+        assert_eq!(
+            runbook.steps,
+            [
+                Step {
+                    name: String::from("build"),
+                    run: StepCommand::Shell(String::from("cargo build")),
+                    parallel: false,
+                    timeout: Some(600),
+                    continue_on_error: false,
+                    depends_on: Vec::new(),
+                    when: When::Success,
+                },
+                Step {
+                    name: String::from("lint"),
+                    run: StepCommand::Argv(vec![String::from("cargo"), String::from("clippy")]),
+                    parallel: true,
+                    timeout: None,
+                    continue_on_error: false,
+                    depends_on: Vec::new(),
+                    when: When::Success,
+                },
+                Step {
+                    name: String::from("notify"),
+                    run: StepCommand::Shell(String::from("notify-send 'Build failed'")),
+                    parallel: false,
+                    timeout: None,
+                    continue_on_error: true,
+                    depends_on: vec![String::from("build")],
+                    when: When::Failure,
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("build", "[build] Elapsed: 00:00:00")]
+    #[case("100% done", "[100% done] Elapsed: 00:00:00")]
+    #[case("C:\\build", "[C:\\build] Elapsed: 00:00:00")]
+    #[case("a\\nb %H", "[a\\nb %H] Elapsed: 00:00:00")]
+    fn step_format_shows_name(#[case] name: &str, #[case] shown: &str) {
+        let runbook = format!("[[step]]\nname = {name:?}\nrun = \"true\"\n")
+            .parse::<Runbook>()
+            .unwrap();
+        let fmt = step_format(&runbook.steps[0].name)
+            .parse::<Format>()
+            .unwrap();
+        assert_eq!(fmt.display(&Snapshot::default()), shown);
+    }
+
+    #[test]
+    fn parse_no_steps() {
+        assert_matches!("".parse::<Runbook>(), Err(RunbookError::NoSteps));
+    }
+
+    #[test]
+    fn parse_duplicate_name() {
+        let r = concat!(
+            "[[step]]\nname = \"a\"\nrun = \"true\"\n",
+            "[[step]]\nname = \"a\"\nrun = \"false\"\n",
+        )
+        .parse::<Runbook>();
+        assert_matches!(r, Err(RunbookError::DuplicateName(name)) => {
+            assert_eq!(name, "a");
+        });
+    }
+
+    #[test]
+    fn parse_empty_command() {
+        let r = "[[step]]\nname = \"a\"\nrun = []\n".parse::<Runbook>();
+        assert_matches!(r, Err(RunbookError::EmptyCommand(name)) => {
+            assert_eq!(name, "a");
+        });
+    }
+
+    #[rstest]
+    #[case("c")]
+    #[case("b")]
+    #[case("a")]
+    fn parse_bad_dependency(#[case] dep: &str) {
+        let r = format!(
+            concat!(
+                "[[step]]\nname = \"a\"\nrun = \"true\"\nparallel = true\n",
+                "[[step]]\nname = \"b\"\nrun = \"true\"\nparallel = true\n",
+                "depends_on = [\"{}\"]\n",
+            ),
+            dep
+        )
+        .parse::<Runbook>();
+        assert_matches!(r, Err(RunbookError::BadDependency(step, d)) => {
+            assert_eq!(step, "b");
+            assert_eq!(d, dep);
+        });
+    }
+
+    #[test]
+    fn parse_unknown_field() {
+        let r = "[[step]]\nname = \"a\"\nrun = \"true\"\nretries = 3\n".parse::<Runbook>();
+        assert_matches!(r, Err(RunbookError::Parse(_)));
+    }
+
+    #[test]
+    fn groups() {
+        let runbook = concat!(
+            "[[step]]\nname = \"a\"\nrun = \"true\"\n",
+            "[[step]]\nname = \"b\"\nrun = \"true\"\nparallel = true\n",
+            "[[step]]\nname = \"c\"\nrun = \"true\"\nparallel = true\n",
+            "[[step]]\nname = \"d\"\nrun = \"true\"\n",
+            "[[step]]\nname = \"e\"\nrun = \"true\"\nparallel = true\n",
+        )
+        .parse::<Runbook>()
+        .unwrap();
+        let names = runbook
+            .groups()
+            .into_iter()
+            .map(|g| g.iter().map(|st| st.name.as_str()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(names, [vec!["a"], vec!["b", "c"], vec!["d"], vec!["e"]]);
+    }
+
+    #[test]
+    fn skip_reason() {
+        let runbook = concat!(
+            "[[step]]\nname = \"build\"\nrun = \"true\"\n",
+            "[[step]]\nname = \"lint\"\nrun = \"true\"\nwhen = \"always\"\n",
+            "[[step]]\nname = \"cleanup\"\nrun = \"true\"\nwhen = \"failure\"\n",
+            "[[step]]\nname = \"deploy\"\nrun = \"true\"\nwhen = \"always\"\n",
+            "depends_on = [\"build\", \"lint\"]\n",
+        )
+        .parse::<Runbook>()
+        .unwrap();
+        let [build, lint, cleanup, deploy] = &runbook.steps[..] else {
+            panic!("wrong number of steps");
+        };
+        let succeeded = HashMap::from([("build", true), ("lint", false)]);
+        assert_eq!(build.skip_reason(false, &succeeded), None);
+        assert_eq!(
+            build.skip_reason(true, &succeeded),
+            Some(SkipReason::EarlierFailure)
+        );
+        assert_eq!(lint.skip_reason(true, &succeeded), None);
+        assert_eq!(
+            cleanup.skip_reason(false, &succeeded),
+            Some(SkipReason::NoEarlierFailure)
+        );
+        assert_eq!(cleanup.skip_reason(true, &succeeded), None);
+        assert_eq!(
+            deploy.skip_reason(false, &succeeded),
+            Some(SkipReason::Dependency(String::from("lint")))
+        );
+    }
+
+    #[test]
+    fn report() {
+        let report = Report {
+            steps: vec![
+                ReportRow {
+                    name: String::from("build"),
+                    verdict: Verdict::Ok,
+                    outcome: Outcome::Exited(Some(0)),
+                    duration: Some(Duration::from_millis(12_345)),
+                },
+                ReportRow {
+                    name: String::from("lint"),
+                    verdict: Verdict::Ignored,
+                    outcome: Outcome::Exited(Some(1)),
+                    duration: Some(Duration::from_millis(3_000)),
+                },
+                ReportRow {
+                    name: String::from("integration-tests"),
+                    verdict: Verdict::Failed,
+                    outcome: Outcome::TimedOut,
+                    duration: Some(Duration::from_secs(600)),
+                },
+                ReportRow {
+                    name: String::from("deploy"),
+                    verdict: Verdict::Skipped,
+                    outcome: Outcome::Skipped(SkipReason::EarlierFailure),
+                    duration: None,
+                },
+            ],
+            total: Duration::from_millis(615_400),
+        };
+        assert_eq!(
+            report.to_string(),
+            concat!(
+                "Step               Result   Status                  Duration\n",
+                "build              ok       exited with code 0      00:00:12.345\n",
+                "lint               ignored  exited with code 1      00:00:03.000\n",
+                "integration-tests  FAILED   timed out               00:10:00.000\n",
+                "deploy             skipped  an earlier step failed\n",
+                "Total                                               00:10:15.400\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,46 @@
+//! The unique ID of the current run of `elapsed`, for correlating the various
+//! records that it writes about the same run
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+static RUN_ID: LazyLock<String> = LazyLock::new(generate);
+
+/// Return the ID of the current run, a string of sixteen lowercase
+/// hexadecimal digits
+pub(crate) fn run_id() -> &'static str {
+    &RUN_ID
+}
+
+fn generate() -> String {
+    // `RandomState` is seeded from the operating system's source of
+    // randomness, which is plenty to keep IDs from colliding.  The process ID
+    // & time are mixed in for good measure.
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    if let Ok(d) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(d.as_nanos());
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable() {
+        let id = run_id();
+        assert_eq!(id.len(), 16);
+        assert!(
+            id.chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        );
+        assert_eq!(run_id(), id);
+    }
+
+    #[test]
+    fn unique() {
+        assert_ne!(generate(), generate());
+    }
+}
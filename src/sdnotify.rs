@@ -0,0 +1,99 @@
+use cfg_if::cfg_if;
+use std::io;
+use std::time::Duration;
+
+cfg_if! {
+    if #[cfg(unix)] {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        /// A connection to the socket named by the `NOTIFY_SOCKET` environment
+        /// variable
+        #[derive(Debug)]
+        pub(crate) struct SystemdNotifier {
+            socket: UnixDatagram,
+            addr: SocketAddr,
+        }
+
+        impl SystemdNotifier {
+            /// Return a `SystemdNotifier` for the socket given by the
+            /// `NOTIFY_SOCKET` environment variable, or `None` if the variable
+            /// is not set
+            pub(crate) fn from_env() -> io::Result<Option<SystemdNotifier>> {
+                match std::env::var_os("NOTIFY_SOCKET") {
+                    Some(s) if !s.is_empty() => SystemdNotifier::for_address(&s).map(Some),
+                    _ => Ok(None),
+                }
+            }
+
+            fn for_address(address: &OsStr) -> io::Result<SystemdNotifier> {
+                let addr = if let Some(name) = address.as_bytes().strip_prefix(b"@") {
+                    abstract_address(name)?
+                } else if address.as_bytes().starts_with(b"/") {
+                    SocketAddr::from_pathname(address)?
+                } else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "unsupported NOTIFY_SOCKET address",
+                    ));
+                };
+                let socket = UnixDatagram::unbound()?;
+                Ok(SystemdNotifier { socket, addr })
+            }
+
+            /// Ask systemd to extend the current start, runtime, or stop
+            /// timeout of the service by `by` from now
+            pub(crate) fn extend_timeout(&self, by: Duration) -> io::Result<()> {
+                let msg = format!("EXTEND_TIMEOUT_USEC={}", by.as_micros());
+                self.socket.send_to_addr(msg.as_bytes(), &self.addr)?;
+                Ok(())
+            }
+
+            /// Tell systemd that the service has finished starting up
+            pub(crate) fn ready(&self) -> io::Result<()> {
+                self.socket.send_to_addr(b"READY=1", &self.addr)?;
+                Ok(())
+            }
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        fn abstract_address(name: &[u8]) -> io::Result<SocketAddr> {
+            cfg_if! {
+                if #[cfg(target_os = "linux")] {
+                    use std::os::linux::net::SocketAddrExt;
+                } else {
+                    use std::os::android::net::SocketAddrExt;
+                }
+            }
+            SocketAddr::from_abstract_name(name)
+        }
+
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        fn abstract_address(_name: &[u8]) -> io::Result<SocketAddr> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract socket addresses are not supported on this platform",
+            ))
+        }
+    } else {
+        /// Stand-in for the systemd notification socket on platforms without
+        /// Unix sockets; it can never be constructed.
+        #[derive(Debug)]
+        pub(crate) enum SystemdNotifier {}
+
+        impl SystemdNotifier {
+            pub(crate) fn from_env() -> io::Result<Option<SystemdNotifier>> {
+                Ok(None)
+            }
+
+            pub(crate) fn extend_timeout(&self, _by: Duration) -> io::Result<()> {
+                match *self {}
+            }
+
+            pub(crate) fn ready(&self) -> io::Result<()> {
+                match *self {}
+            }
+        }
+    }
+}
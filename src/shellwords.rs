@@ -0,0 +1,104 @@
+use thiserror::Error;
+
+/// Split a string into words following the quoting rules of the POSIX shell:
+/// words are separated by whitespace, text inside single quotes is taken
+/// literally, backslashes inside double quotes only escape `$`, `` ` ``, `"`,
+/// `\`, and newline, and a backslash outside of quotes escapes the next
+/// character.  Expansions of any kind are not performed.
+pub(crate) fn split(s: &str) -> Result<Vec<String>, SplitError> {
+    let mut words = Vec::new();
+    // `None` when between words
+    let mut current: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(w) = current.take() {
+                    words.push(w);
+                }
+            }
+            '\'' => {
+                let w = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.push(c),
+                        None => return Err(SplitError::UnterminatedSingle),
+                    }
+                }
+            }
+            '"' => {
+                let w = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => (),
+                            Some(c @ ('$' | '`' | '"' | '\\')) => w.push(c),
+                            Some(c) => {
+                                w.push('\\');
+                                w.push(c);
+                            }
+                            None => return Err(SplitError::UnterminatedDouble),
+                        },
+                        Some(c) => w.push(c),
+                        None => return Err(SplitError::UnterminatedDouble),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => (),
+                Some(c) => current.get_or_insert_with(String::new).push(c),
+                None => return Err(SplitError::TrailingBackslash),
+            },
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(w) = current {
+        words.push(w);
+    }
+    Ok(words)
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+pub(crate) enum SplitError {
+    #[error("unterminated single quote")]
+    UnterminatedSingle,
+    #[error("unterminated double quote")]
+    UnterminatedDouble,
+    #[error("backslash at end of string")]
+    TrailingBackslash,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", &[])]
+    #[case("   ", &[])]
+    #[case("--total", &["--total"])]
+    #[case("  --total\t-r 500\n", &["--total", "-r", "500"])]
+    #[case("--format 'Time: %s'", &["--format", "Time: %s"])]
+    #[case("--format \"Time: %s\"", &["--format", "Time: %s"])]
+    #[case("--format=Time:\\ %s", &["--format=Time: %s"])]
+    #[case("'it'\\''s'", &["it's"])]
+    #[case("\"a\\\"b\\\\c\\d\"", &["a\"b\\c\\d"])]
+    #[case("'a\\b'", &["a\\b"])]
+    #[case("''", &[""])]
+    #[case("a\"\"b", &["ab"])]
+    #[case("foo\\\nbar", &["foobar"])]
+    fn split_ok(#[case] s: &str, #[case] words: &[&str]) {
+        assert_eq!(split(s).unwrap(), words);
+    }
+
+    #[rstest]
+    #[case("'foo", SplitError::UnterminatedSingle)]
+    #[case("--format \"foo", SplitError::UnterminatedDouble)]
+    #[case("\"foo\\", SplitError::UnterminatedDouble)]
+    #[case("foo\\", SplitError::TrailingBackslash)]
+    fn split_err(#[case] s: &str, #[case] err: SplitError) {
+        assert_eq!(split(s), Err(err));
+    }
+}
@@ -0,0 +1,692 @@
+//! Destinations that receive every line of the command's output and the
+//! events of the run, such as the `--line-timings` file & `--porcelain`
+//! records
+use crate::Stream;
+use std::io::{self, Write};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use thiserror::Error;
+
+/// A line of the command's output, as given to each [`Sink`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Line<'a> {
+    /// The stream the line was read from
+    pub(crate) stream: Stream,
+    /// The line as read from the command, including its terminator
+    pub(crate) record: &'a [u8],
+    /// The line with any custom (non-newline) delimiter removed
+    pub(crate) text: &'a [u8],
+    /// How long after the command started the line was received
+    pub(crate) elapsed: Duration,
+    /// How long it had been since the previous output (or since the command
+    /// started, for the first line)
+    pub(crate) gap: Duration,
+}
+
+/// A destination for the command's output and the events of the run.  Only
+/// `line()` is required; sinks that don't care about the other events can
+/// leave them as no-ops.
+pub(crate) trait Sink {
+    /// Called for each line of the command's output
+    fn line(&mut self, line: &Line<'_>) -> io::Result<()>;
+
+    /// Called on every refresh with the time elapsed so far
+    fn tick(&mut self, _elapsed: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the command has finished with the code that `elapsed` will
+    /// exit with and the total elapsed time
+    fn exit(&mut self, _code: u8, _elapsed: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called with the text of each warning that `elapsed` reports about the
+    /// run
+    fn warning(&mut self, _msg: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called when the given number of lines (at least one) had to be
+    /// dropped because the sink was falling behind, before the next line or
+    /// event that made it through
+    fn gap(&mut self, _dropped: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called at the end of the run in order to write out anything still
+    /// buffered
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A [`Sink`] that writes a copy of each line to `W` as-is, for `--tee-fd`
+#[derive(Debug)]
+pub(crate) struct Tee<W>(pub(crate) W);
+
+impl<W: Write> Sink for Tee<W> {
+    fn line(&mut self, line: &Line<'_>) -> io::Result<()> {
+        self.0.write_all(line.record)
+    }
+
+    fn gap(&mut self, dropped: u64) -> io::Result<()> {
+        writeln!(self.0, "{}", gap_marker(dropped))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// What a queued sink does when its queue is full, as given by
+/// `--sink-overflow`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub(crate) enum Overflow {
+    /// Wait for room in the queue, holding up the reading of the command's
+    /// output
+    #[default]
+    Block,
+    /// Drop the line (or tick) and write a marker to the sink once there's
+    /// room again
+    Drop,
+}
+
+impl std::str::FromStr for Overflow {
+    type Err = ParseOverflowError;
+
+    fn from_str(s: &str) -> Result<Overflow, ParseOverflowError> {
+        match s {
+            "block" => Ok(Overflow::Block),
+            "drop" => Ok(Overflow::Drop),
+            _ => Err(ParseOverflowError),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid sink overflow policy; expected \"block\" or \"drop\"")]
+pub(crate) struct ParseOverflowError;
+
+/// How to feed lines & events to each sink via a queue of its own, drained by
+/// a thread of its own
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Queueing {
+    /// The maximum number of lines & events that may wait in each queue
+    pub(crate) capacity: usize,
+    pub(crate) overflow: Overflow,
+}
+
+/// Return the marker written in place of `dropped` lines by sinks whose
+/// output is free-form text
+pub(crate) fn gap_marker(dropped: u64) -> String {
+    format!(
+        "[elapsed: {dropped} line{} dropped]",
+        if dropped == 1 { "" } else { "s" }
+    )
+}
+
+/// A set of [`Sink`]s that each receive every line & event in the order in
+/// which they were added.  Each sink is paired with the name of the option it
+/// was configured by and a function for converting its I/O errors into `E`.
+///
+/// By default, each line & event is written to each sink as it happens.  With
+/// [`Queueing`], each sink instead runs on a thread of its own, fed through a
+/// bounded queue, so that a slow sink only holds up `elapsed` once its queue
+/// is full — or never, if lines are dropped instead.
+pub(crate) struct Sinks<E> {
+    sinks: Vec<Entry<E>>,
+    queueing: Option<Queueing>,
+}
+
+/// A [`Sink`] in a [`Sinks`]
+struct Entry<E> {
+    /// The option the sink was configured by, e.g., "`--tee-fd`"
+    name: &'static str,
+    feed: Feed,
+    on_error: fn(io::Error) -> E,
+}
+
+/// How lines & events get to a sink
+enum Feed {
+    Direct(Box<dyn Sink + Send>),
+    Queued(Queue),
+}
+
+impl<E> Sinks<E> {
+    pub(crate) fn new(queueing: Option<Queueing>) -> Sinks<E> {
+        Sinks {
+            sinks: Vec::new(),
+            queueing,
+        }
+    }
+
+    /// Add `sink`, configured by the option `name`.  If queueing is enabled,
+    /// this starts the sink's thread, failing if the thread can't be started.
+    pub(crate) fn push<S: Sink + Send + 'static>(
+        &mut self,
+        name: &'static str,
+        sink: S,
+        on_error: fn(io::Error) -> E,
+    ) -> io::Result<()> {
+        let feed = match self.queueing {
+            Some(Queueing { capacity, .. }) => {
+                Feed::Queued(Queue::start(name, Box::new(sink), capacity)?)
+            }
+            None => Feed::Direct(Box::new(sink)),
+        };
+        self.sinks.push(Entry {
+            name,
+            feed,
+            on_error,
+        });
+        Ok(())
+    }
+
+    pub(crate) fn line(&mut self, line: &Line<'_>) -> Result<(), E> {
+        self.each(true, |sink| sink.line(line), || Message::Line(line.into()))
+    }
+
+    pub(crate) fn tick(&mut self, elapsed: Duration) -> Result<(), E> {
+        self.each(false, |sink| sink.tick(elapsed), || Message::Tick(elapsed))
+    }
+
+    pub(crate) fn exit(&mut self, code: u8, elapsed: Duration) -> Result<(), E> {
+        self.all(
+            |sink| sink.exit(code, elapsed),
+            || Message::Exit(code, elapsed),
+        )
+    }
+
+    pub(crate) fn warning(&mut self, msg: &str) -> Result<(), E> {
+        self.all(
+            |sink| sink.warning(msg),
+            || Message::Warning(msg.to_owned()),
+        )
+    }
+
+    /// Write out anything still buffered by the sinks, waiting for any
+    /// queues to be drained & their threads to finish
+    pub(crate) fn finish(&mut self) -> Result<(), E> {
+        for entry in &mut self.sinks {
+            match &mut entry.feed {
+                Feed::Direct(sink) => sink.finish(),
+                Feed::Queued(queue) => queue.finish(),
+            }
+            .map_err(entry.on_error)?;
+        }
+        Ok(())
+    }
+
+    /// If lines are dropped when a sink's queue is full, return the name of
+    /// each sink along with how many lines were dropped for it
+    pub(crate) fn drops(&self) -> Option<Vec<(&'static str, u64)>> {
+        self.queueing
+            .filter(|q| q.overflow == Overflow::Drop)
+            .map(|_| {
+                self.sinks
+                    .iter()
+                    .map(|entry| match &entry.feed {
+                        Feed::Direct(_) => (entry.name, 0),
+                        Feed::Queued(queue) => (entry.name, queue.dropped),
+                    })
+                    .collect()
+            })
+    }
+
+    /// Give an event that must not be dropped to each sink, either directly
+    /// via `direct` or by queueing the message created by `message`, waiting
+    /// for room in the queue if necessary
+    fn all<F, M>(&mut self, mut direct: F, message: M) -> Result<(), E>
+    where
+        F: FnMut(&mut dyn Sink) -> io::Result<()>,
+        M: Fn() -> Message,
+    {
+        for entry in &mut self.sinks {
+            match &mut entry.feed {
+                Feed::Direct(sink) => direct(&mut **sink),
+                Feed::Queued(queue) => queue.send(message()),
+            }
+            .map_err(entry.on_error)?;
+        }
+        Ok(())
+    }
+
+    /// Give a line (if `is_line` is true) or tick to each sink, either
+    /// directly via `direct` or by queueing the message created by `message`
+    fn each<F, M>(&mut self, is_line: bool, mut direct: F, message: M) -> Result<(), E>
+    where
+        F: FnMut(&mut dyn Sink) -> io::Result<()>,
+        M: Fn() -> Message,
+    {
+        let overflow = self.queueing.map(|q| q.overflow);
+        for entry in &mut self.sinks {
+            match &mut entry.feed {
+                Feed::Direct(sink) => direct(&mut **sink),
+                Feed::Queued(queue) if overflow == Some(Overflow::Drop) => {
+                    queue.offer(message(), is_line)
+                }
+                Feed::Queued(queue) => queue.send(message()),
+            }
+            .map_err(entry.on_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> Default for Sinks<E> {
+    fn default() -> Sinks<E> {
+        Sinks::new(None)
+    }
+}
+
+/// A line or event sent to a sink's thread
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Message {
+    Line(OwnedLine),
+    Tick(Duration),
+    Exit(u8, Duration),
+    Warning(String),
+    /// The given number of lines were dropped since the last message
+    Gap(u64),
+}
+
+/// An owned copy of a [`Line`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct OwnedLine {
+    stream: Stream,
+    record: Vec<u8>,
+    /// The length of the line's `text`, which is always a prefix of `record`
+    text_len: usize,
+    elapsed: Duration,
+    gap: Duration,
+}
+
+impl OwnedLine {
+    fn as_line(&self) -> Line<'_> {
+        Line {
+            stream: self.stream,
+            record: &self.record,
+            text: &self.record[..self.text_len],
+            elapsed: self.elapsed,
+            gap: self.gap,
+        }
+    }
+}
+
+impl From<&Line<'_>> for OwnedLine {
+    fn from(line: &Line<'_>) -> OwnedLine {
+        OwnedLine {
+            stream: line.stream,
+            record: line.record.to_vec(),
+            text_len: line.text.len(),
+            elapsed: line.elapsed,
+            gap: line.gap,
+        }
+    }
+}
+
+/// The sending end of a sink's queue, along with the thread that drains it
+struct Queue {
+    /// `None` once the queue has been closed by `finish()`
+    tx: Option<SyncSender<Message>>,
+    /// `None` once the thread has been joined
+    worker: Option<JoinHandle<io::Result<()>>>,
+    /// The number of lines dropped since the last one that was queued
+    pending_gap: u64,
+    /// The total number of lines dropped
+    dropped: u64,
+}
+
+impl Queue {
+    fn start(name: &str, sink: Box<dyn Sink + Send>, capacity: usize) -> io::Result<Queue> {
+        let (tx, rx) = sync_channel(capacity);
+        let worker = std::thread::Builder::new()
+            .name(format!("sink {name}"))
+            .spawn(move || drain(sink, &rx))?;
+        Ok(Queue {
+            tx: Some(tx),
+            worker: Some(worker),
+            pending_gap: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Queue `msg`, waiting for room in the queue if necessary
+    fn send(&mut self, msg: Message) -> io::Result<()> {
+        self.send_gap()?;
+        self.push(msg)
+    }
+
+    /// Queue a marker for any lines dropped since the last queued message,
+    /// waiting for room in the queue if necessary
+    fn send_gap(&mut self) -> io::Result<()> {
+        if self.pending_gap > 0 {
+            let gap = Message::Gap(self.pending_gap);
+            self.pending_gap = 0;
+            self.push(gap)?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, msg: Message) -> io::Result<()> {
+        if let Some(tx) = &self.tx {
+            if tx.send(msg).is_err() {
+                return Err(self.failure());
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue `msg` if there's room in the queue; otherwise, drop it, counting
+    /// it as a dropped line if `is_line` is true
+    fn offer(&mut self, msg: Message, is_line: bool) -> io::Result<()> {
+        let Some(tx) = &self.tx else {
+            return Ok(());
+        };
+        let mut r = Ok(());
+        if self.pending_gap > 0 {
+            r = tx.try_send(Message::Gap(self.pending_gap));
+            if r.is_ok() {
+                self.pending_gap = 0;
+            }
+        }
+        if r.is_ok() {
+            r = tx.try_send(msg);
+        }
+        match r {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                if is_line {
+                    self.pending_gap += 1;
+                    self.dropped += 1;
+                }
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => Err(self.failure()),
+        }
+    }
+
+    /// Close the queue and wait for the thread to drain it
+    fn finish(&mut self) -> io::Result<()> {
+        self.send_gap()?;
+        self.tx = None;
+        match self.worker.take() {
+            Some(worker) => join(worker),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the error that stopped the sink's thread
+    fn failure(&mut self) -> io::Error {
+        self.tx = None;
+        match self.worker.take().map(join) {
+            Some(Err(e)) => e,
+            _ => io::Error::other("sink thread stopped unexpectedly"),
+        }
+    }
+}
+
+/// Wait for a sink's thread to finish and return its result
+fn join(worker: JoinHandle<io::Result<()>>) -> io::Result<()> {
+    match worker.join() {
+        Ok(r) => r,
+        Err(e) => std::panic::resume_unwind(e),
+    }
+}
+
+/// Feed the messages from `rx` to `sink` until the queue is closed, then
+/// finish the sink
+fn drain(mut sink: Box<dyn Sink + Send>, rx: &Receiver<Message>) -> io::Result<()> {
+    for msg in rx {
+        match msg {
+            Message::Line(line) => sink.line(&line.as_line())?,
+            Message::Tick(elapsed) => sink.tick(elapsed)?,
+            Message::Exit(code, elapsed) => sink.exit(code, elapsed)?,
+            Message::Warning(msg) => sink.warning(&msg)?,
+            Message::Gap(dropped) => sink.gap(dropped)?,
+        }
+    }
+    sink.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Sender};
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// A sink that logs every call made to it, optionally stalling on the
+    /// first line until told to continue
+    struct Recorder {
+        log: Log,
+        name: &'static str,
+        /// Senders to notify when the first line arrives & receivers to wait
+        /// on before continuing
+        stall: Option<(Sender<()>, Receiver<()>)>,
+    }
+
+    impl Recorder {
+        fn new(log: &Log, name: &'static str) -> Recorder {
+            Recorder {
+                log: Arc::clone(log),
+                name,
+                stall: None,
+            }
+        }
+
+        fn record(&self, event: String) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {event}", self.name));
+        }
+    }
+
+    impl Sink for Recorder {
+        fn line(&mut self, line: &Line<'_>) -> io::Result<()> {
+            if let Some((started, release)) = self.stall.take() {
+                started.send(()).unwrap();
+                let _ = release.recv();
+            }
+            self.record(format!("line {}", String::from_utf8_lossy(line.text)));
+            Ok(())
+        }
+
+        fn exit(&mut self, code: u8, _elapsed: Duration) -> io::Result<()> {
+            self.record(format!("exit {code}"));
+            Ok(())
+        }
+
+        fn warning(&mut self, msg: &str) -> io::Result<()> {
+            self.record(format!("warning {msg}"));
+            Ok(())
+        }
+
+        fn gap(&mut self, dropped: u64) -> io::Result<()> {
+            self.record(format!("gap {dropped}"));
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.record(String::from("finish"));
+            Ok(())
+        }
+    }
+
+    /// A sink that fails on every line
+    struct Broken;
+
+    impl Sink for Broken {
+        fn line(&mut self, _line: &Line<'_>) -> io::Result<()> {
+            Err(io::Error::other("broken"))
+        }
+    }
+
+    fn line(text: &[u8]) -> Line<'_> {
+        Line {
+            stream: Stream::Stdout,
+            record: text,
+            text,
+            elapsed: Duration::from_secs(1),
+            gap: Duration::from_secs(1),
+        }
+    }
+
+    fn events(log: &Log, name: &str) -> Vec<String> {
+        log.lock()
+            .unwrap()
+            .iter()
+            .filter_map(|ev| ev.strip_prefix(name)?.strip_prefix(' '))
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn direct() {
+        let log = Log::default();
+        let mut sinks = Sinks::<String>::new(None);
+        sinks
+            .push("a", Recorder::new(&log, "a"), |e| e.to_string())
+            .unwrap();
+        sinks
+            .push("b", Recorder::new(&log, "b"), |e| e.to_string())
+            .unwrap();
+        sinks.line(&line(b"foo")).unwrap();
+        sinks.tick(Duration::from_secs(2)).unwrap();
+        sinks.warning("uh-oh").unwrap();
+        sinks.exit(3, Duration::from_secs(2)).unwrap();
+        sinks.finish().unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "a line foo",
+                "b line foo",
+                "a warning uh-oh",
+                "b warning uh-oh",
+                "a exit 3",
+                "b exit 3",
+                "a finish",
+                "b finish"
+            ]
+        );
+        assert_eq!(sinks.drops(), None);
+    }
+
+    #[test]
+    fn queued_block() {
+        let log = Log::default();
+        let mut sinks = Sinks::<String>::new(Some(Queueing {
+            capacity: 1,
+            overflow: Overflow::Block,
+        }));
+        sinks
+            .push("a", Recorder::new(&log, "a"), |e| e.to_string())
+            .unwrap();
+        sinks
+            .push("b", Recorder::new(&log, "b"), |e| e.to_string())
+            .unwrap();
+        for text in ["foo", "bar", "baz"] {
+            sinks.line(&line(text.as_bytes())).unwrap();
+        }
+        sinks.warning("uh-oh").unwrap();
+        sinks.exit(0, Duration::from_secs(2)).unwrap();
+        sinks.finish().unwrap();
+        for name in ["a", "b"] {
+            assert_eq!(
+                events(&log, name),
+                [
+                    "line foo",
+                    "line bar",
+                    "line baz",
+                    "warning uh-oh",
+                    "exit 0",
+                    "finish"
+                ]
+            );
+        }
+        assert_eq!(sinks.drops(), None);
+    }
+
+    #[test]
+    fn queued_drop() {
+        let log = Log::default();
+        let mut sinks = Sinks::<String>::new(Some(Queueing {
+            capacity: 1,
+            overflow: Overflow::Drop,
+        }));
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel();
+        let mut slow = Recorder::new(&log, "slow");
+        slow.stall = Some((started_tx, release_rx));
+        sinks.push("--slow", slow, |e| e.to_string()).unwrap();
+        sinks.line(&line(b"one")).unwrap();
+        // Wait until the sink's thread is stuck on the first line, leaving
+        // room in the queue for exactly one more.
+        started_rx.recv().unwrap();
+        sinks.line(&line(b"two")).unwrap();
+        sinks.line(&line(b"three")).unwrap();
+        sinks.tick(Duration::from_secs(1)).unwrap();
+        sinks.line(&line(b"four")).unwrap();
+        drop(release_tx);
+        sinks.exit(1, Duration::from_secs(2)).unwrap();
+        sinks.finish().unwrap();
+        assert_eq!(
+            events(&log, "slow"),
+            ["line one", "line two", "gap 2", "exit 1", "finish"]
+        );
+        assert_eq!(sinks.drops(), Some(vec![("--slow", 2)]));
+    }
+
+    #[test]
+    fn direct_error() {
+        let log = Log::default();
+        let mut sinks = Sinks::new(None);
+        sinks
+            .push("--broken", Broken, |e| format!("--broken: {e}"))
+            .unwrap();
+        sinks
+            .push("a", Recorder::new(&log, "a"), |e| e.to_string())
+            .unwrap();
+        assert_eq!(
+            sinks.line(&line(b"foo")),
+            Err(String::from("--broken: broken"))
+        );
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn queued_error() {
+        let mut sinks = Sinks::new(Some(Queueing {
+            capacity: 8,
+            overflow: Overflow::Block,
+        }));
+        sinks
+            .push("--broken", Broken, |e| format!("--broken: {e}"))
+            .unwrap();
+        assert_eq!(sinks.line(&line(b"foo")), Ok(()));
+        assert_eq!(sinks.finish(), Err(String::from("--broken: broken")));
+    }
+
+    #[test]
+    fn tee() {
+        let mut tee = Tee(Vec::new());
+        tee.line(&Line {
+            stream: Stream::Stderr,
+            record: b"foo\0",
+            text: b"foo",
+            elapsed: Duration::ZERO,
+            gap: Duration::ZERO,
+        })
+        .unwrap();
+        tee.gap(1).unwrap();
+        tee.gap(42).unwrap();
+        assert_eq!(
+            tee.0,
+            b"foo\0[elapsed: 1 line dropped]\n[elapsed: 42 lines dropped]\n"
+        );
+    }
+}
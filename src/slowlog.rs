@@ -0,0 +1,93 @@
+//! The log of lines preceded by long stretches of silence written by
+//! `--slowlog`
+use crate::logfile::LogFile;
+use crate::pattern::trim_eol;
+use crate::sink::{Line, Sink, gap_marker};
+use crate::summary::format_duration;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// A writer of the lines of output from the child process that arrived at
+/// least `threshold` after the previous output (or after the command started,
+/// for the first line), each prefixed with when it arrived and how long the
+/// gap before it was
+#[derive(Debug)]
+pub(crate) struct SlowLog<W = LogFile> {
+    out: W,
+    threshold: Duration,
+}
+
+impl SlowLog {
+    /// Open `path` for writing the log, appending to it instead of truncating
+    /// it if `append` is true
+    pub(crate) fn create(path: &Path, threshold: Duration, append: bool) -> io::Result<SlowLog> {
+        let out = LogFile::create(path, append)?;
+        Ok(SlowLog::new(out, threshold))
+    }
+}
+
+impl<W: Write> SlowLog<W> {
+    fn new(out: W, threshold: Duration) -> SlowLog<W> {
+        SlowLog { out, threshold }
+    }
+
+    /// Record `line` if the `gap` of silence preceding it is long enough.
+    /// `elapsed` is the time since the command was started at which the line
+    /// was received.
+    fn record(&mut self, line: &[u8], elapsed: Duration, gap: Duration) -> io::Result<()> {
+        if gap >= self.threshold {
+            write!(
+                self.out,
+                "{} (+{}) ",
+                format_duration(elapsed),
+                format_duration(gap)
+            )?;
+            self.out.write_all(trim_eol(line))?;
+            self.out.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for SlowLog<W> {
+    fn line(&mut self, line: &Line<'_>) -> io::Result<()> {
+        self.record(line.text, line.elapsed, line.gap)
+    }
+
+    fn gap(&mut self, dropped: u64) -> io::Result<()> {
+        writeln!(self.out, "{}", gap_marker(dropped))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold() {
+        let mut slowlog = SlowLog::new(Vec::new(), Duration::from_secs(5));
+        let ms = Duration::from_millis;
+        slowlog.record(b"Starting\n", ms(6_000), ms(6_000)).unwrap();
+        slowlog.record(b"Step 1\n", ms(7_000), ms(1_000)).unwrap();
+        slowlog
+            .record(b"Step 2\r\n", ms(12_000), ms(5_000))
+            .unwrap();
+        slowlog.record(b"Step 3", ms(16_999), ms(4_999)).unwrap();
+        slowlog
+            .record(b"Done\n", ms(3_723_450), ms(3_706_451))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(slowlog.out).unwrap(),
+            concat!(
+                "00:00:06.000 (+00:00:06.000) Starting\n",
+                "00:00:12.000 (+00:00:05.000) Step 2\n",
+                "01:02:03.450 (+01:01:46.451) Done\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Forwarding of `elapsed`'s stdin to the command for `--pipe-stdin`
+use std::fs::File;
+use std::io;
+use std::thread;
+use tokio::process::ChildStdin;
+
+/// Start a thread that copies everything from `elapsed`'s stdin to `dest`,
+/// closing `dest` once stdin reaches EOF so that the command sees EOF as well.
+///
+/// A dedicated thread doing blocking I/O is used instead of
+/// [`tokio::io::stdin()`], as a pending read of the latter would keep the
+/// runtime from shutting down after the command exits.
+pub(crate) fn pump(dest: ChildStdin) -> io::Result<()> {
+    #[cfg(unix)]
+    let fd = dest.into_owned_fd()?;
+    #[cfg(windows)]
+    let fd = dest.into_owned_handle()?;
+    let mut dest = File::from(fd);
+    thread::Builder::new()
+        .name(String::from("elapsed-stdin"))
+        .spawn(move || {
+            // An error here most likely means that the command closed its
+            // stdin early, in which case it doesn't want the rest anyway.
+            let _ = io::copy(&mut io::stdin().lock(), &mut dest);
+        })?;
+    Ok(())
+}
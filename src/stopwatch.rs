@@ -0,0 +1,294 @@
+//! Named sub-timers that are started & stopped by lines of the command's
+//! output, for `--stopwatch-regex`
+use crate::pattern::Pattern;
+use crate::summary::format_duration;
+use std::fmt;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// A sub-timer that runs from whenever a line of output matches `start` until
+/// the next line that matches `stop`, accumulating time across each such
+/// span
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Stopwatch {
+    name: String,
+    start: Pattern,
+    stop: Pattern,
+    /// When the current span started, if the stopwatch is running
+    running_since: Option<Instant>,
+    /// The total time of all completed spans
+    total: Duration,
+    /// The number of spans started so far
+    spans: usize,
+}
+
+impl Stopwatch {
+    /// Update the stopwatch for a line of output received at time `now`.  A
+    /// running stopwatch is only checked against its stop pattern, and a
+    /// stopped one only against its start pattern.
+    pub(crate) fn observe(&mut self, line: &[u8], now: Instant) {
+        match self.running_since {
+            Some(since) if self.stop.is_match(line) => {
+                self.total += now.saturating_duration_since(since);
+                self.running_since = None;
+            }
+            None if self.start.is_match(line) => {
+                self.running_since = Some(now);
+                self.spans += 1;
+            }
+            _ => (),
+        }
+    }
+
+    /// Stop the stopwatch at time `now` if it's running
+    pub(crate) fn finish(&mut self, now: Instant) {
+        if let Some(since) = self.running_since.take() {
+            self.total += now.saturating_duration_since(since);
+        }
+    }
+}
+
+impl fmt::Display for Stopwatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({} span{})",
+            self.name,
+            format_duration(self.total),
+            self.spans,
+            if self.spans == 1 { "" } else { "s" }
+        )
+    }
+}
+
+impl std::str::FromStr for Stopwatch {
+    type Err = ParseStopwatchError;
+
+    /// Parse a stopwatch from a string of the form
+    /// `<NAME>:<START_RE>:<STOP_RE>`.  The name & start regex end at the first
+    /// and second colons, respectively, so only the stop regex can contain
+    /// colons.
+    fn from_str(s: &str) -> Result<Stopwatch, ParseStopwatchError> {
+        let (name, rest) = s.split_once(':').ok_or(ParseStopwatchError::Syntax)?;
+        let (start, stop) = rest.split_once(':').ok_or(ParseStopwatchError::Syntax)?;
+        if name.is_empty() {
+            return Err(ParseStopwatchError::EmptyName);
+        }
+        Ok(Stopwatch {
+            name: name.to_owned(),
+            start: start.parse()?,
+            stop: stop.parse()?,
+            running_since: None,
+            total: Duration::ZERO,
+            spans: 0,
+        })
+    }
+}
+
+/// A built-in set of stopwatches for timing the phases of a common build or
+/// test tool, for `--preset`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Preset {
+    Cargo,
+    Make,
+    Pytest,
+    Npm,
+}
+
+impl Preset {
+    /// The stopwatches for the preset, in `--stopwatch-regex` syntax
+    fn specs(self) -> &'static [&'static str] {
+        match self {
+            Preset::Cargo => &[
+                r"build:^\s+(Compiling|Checking|Documenting) :^\s+Finished ",
+                r"test:^\s+(Running|Doc-tests) :^test result: ",
+            ],
+            Preset::Make => &[
+                r"subdirs:^g?make(\[\d+\])?\x3A Entering directory :^g?make(\[\d+\])?: Leaving directory ",
+            ],
+            Preset::Pytest => &[
+                r"collect:^=+ test session starts =+$:collected \d+ items?",
+                r"test:collected \d+ items?:^=+ .* in \d+(\.\d+)?s",
+            ],
+            Preset::Npm => &[r"script:^> \S+@\S+ \S+$:^npm (ERR!|error) "],
+        }
+    }
+
+    pub(crate) fn stopwatches(self) -> Vec<Stopwatch> {
+        self.specs()
+            .iter()
+            .map(|s| s.parse().expect("preset stopwatches should be valid"))
+            .collect()
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = ParsePresetError;
+
+    fn from_str(s: &str) -> Result<Preset, ParsePresetError> {
+        match s {
+            "cargo" => Ok(Preset::Cargo),
+            "make" => Ok(Preset::Make),
+            "pytest" => Ok(Preset::Pytest),
+            "npm" => Ok(Preset::Npm),
+            _ => Err(ParsePresetError),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Error, PartialEq)]
+#[error("invalid preset; expected \"cargo\", \"make\", \"pytest\", or \"npm\"")]
+pub(crate) struct ParsePresetError;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub(crate) enum ParseStopwatchError {
+    #[error("expected <NAME>:<START_RE>:<STOP_RE>")]
+    Syntax,
+    #[error("stopwatch name cannot be empty")]
+    EmptyName,
+    #[error(transparent)]
+    Regex(#[from] regex::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+    use rstest::rstest;
+
+    #[test]
+    fn parse() {
+        let sw = "dl:^Downloading:^(Compiling|Done):"
+            .parse::<Stopwatch>()
+            .unwrap();
+        assert_eq!(sw.name, "dl");
+        assert_eq!(sw.start, "^Downloading".parse::<Pattern>().unwrap());
+        assert_eq!(sw.stop, "^(Compiling|Done):".parse::<Pattern>().unwrap());
+    }
+
+    #[rstest]
+    #[case("dl")]
+    #[case("dl:^Downloading")]
+    fn parse_syntax_err(#[case] s: &str) {
+        assert_eq!(s.parse::<Stopwatch>(), Err(ParseStopwatchError::Syntax));
+    }
+
+    #[test]
+    fn parse_empty_name() {
+        assert_eq!(
+            ":start:stop".parse::<Stopwatch>(),
+            Err(ParseStopwatchError::EmptyName)
+        );
+    }
+
+    #[test]
+    fn parse_bad_regex() {
+        assert_matches!(
+            "dl:(:stop".parse::<Stopwatch>(),
+            Err(ParseStopwatchError::Regex(_))
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut sw = "dl:^Downloading:^Compiling".parse::<Stopwatch>().unwrap();
+        sw.observe(b"Compiling foo\n", at(0));
+        sw.observe(b"Downloading bar\n", at(100));
+        sw.observe(b"Downloading baz\n", at(150));
+        sw.observe(b"Compiling bar\n", at(400));
+        sw.observe(b"Downloading quux\n", at(1000));
+        sw.finish(at(1250));
+        assert_eq!(sw.to_string(), "dl: 00:00:00.550 (2 spans)");
+    }
+
+    #[rstest]
+    #[case(Preset::Cargo)]
+    #[case(Preset::Make)]
+    #[case(Preset::Pytest)]
+    #[case(Preset::Npm)]
+    fn preset_stopwatches(#[case] preset: Preset) {
+        assert_eq!(preset.stopwatches().len(), preset.specs().len());
+    }
+
+    #[test]
+    fn cargo_preset() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut sws = Preset::Cargo.stopwatches();
+        for (ms, line) in [
+            (0, "    Updating crates.io index\n"),
+            (100, "   Compiling libc v0.2.177\n"),
+            (200, "   Compiling foo v0.1.0 (/src/foo)\n"),
+            (
+                1100,
+                "    Finished `test` profile [unoptimized + debuginfo] target(s) in 1.00s\n",
+            ),
+            (
+                1150,
+                "     Running unittests src/lib.rs (target/debug/deps/foo-0123)\n",
+            ),
+            (1200, "test result: ok. 3 passed; 0 failed; 0 ignored\n"),
+            (1300, "   Doc-tests foo\n"),
+            (1600, "test result: ok. 1 passed; 0 failed; 0 ignored\n"),
+        ] {
+            for sw in &mut sws {
+                sw.observe(line.as_bytes(), at(ms));
+            }
+        }
+        let reports = sws.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            reports,
+            [
+                "build: 00:00:01.000 (1 span)",
+                "test: 00:00:00.350 (2 spans)"
+            ]
+        );
+    }
+
+    #[test]
+    fn pytest_preset() {
+        let t0 = Instant::now();
+        let at = |ms| t0 + Duration::from_millis(ms);
+        let mut sws = Preset::Pytest.stopwatches();
+        for (ms, line) in [
+            (
+                0,
+                "============================= test session starts ==============================\n",
+            ),
+            (
+                10,
+                "platform linux -- Python 3.12.3, pytest-8.3.3, pluggy-1.5.0\n",
+            ),
+            (250, "collected 42 items\n"),
+            (
+                3000,
+                "tests/test_foo.py ....................................  [100%]\n",
+            ),
+            (
+                3250,
+                "============================== 42 passed in 3.00s ==============================\n",
+            ),
+        ] {
+            for sw in &mut sws {
+                sw.observe(line.as_bytes(), at(ms));
+            }
+        }
+        let reports = sws.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            reports,
+            [
+                "collect: 00:00:00.250 (1 span)",
+                "test: 00:00:03.000 (1 span)"
+            ]
+        );
+    }
+
+    #[test]
+    fn never_started() {
+        let mut sw = "dl:^Downloading:^Compiling".parse::<Stopwatch>().unwrap();
+        sw.finish(Instant::now());
+        assert_eq!(sw.to_string(), "dl: 00:00:00.000 (0 spans)");
+    }
+}
@@ -0,0 +1,392 @@
+//! The table of statistics about the run printed by `--summary`
+use crate::OutputCounts;
+use crate::clock::ClockJump;
+use crate::energy::format_joules;
+use crate::procinfo::{ChildUsage, WaitReasons};
+use crate::units::{format_bytes, format_count};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Statistics about a finished run of the command
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Summary {
+    /// The command line that was run
+    pub(crate) command: String,
+    /// The ID of the run, as given to the command in `ELAPSED_RUN_ID`
+    pub(crate) run_id: String,
+    /// The wall-clock date & time at which the command was started
+    pub(crate) started: String,
+    pub(crate) duration: Duration,
+    /// How long the command spent suspended (stopped by a job control signal)
+    pub(crate) suspended: Duration,
+    /// How the command exited, e.g., "exited with code 0"
+    pub(crate) exit_status: String,
+    /// The resource usage of the command, if available
+    pub(crate) usage: Option<ChildUsage>,
+    /// The energy consumed by the CPU packages during the run, in
+    /// microjoules, if measured
+    pub(crate) energy: Option<u64>,
+    /// The most common things the command's process was waiting on, if
+    /// sampled
+    pub(crate) wait_reasons: Option<WaitReasons>,
+    pub(crate) counts: OutputCounts,
+    /// True if the command's stdout & stderr were merged (with `--merge`), in
+    /// which case all output is counted as stdout
+    pub(crate) merged: bool,
+    /// True if `elapsed` stopped relaying the command's stdout partway through
+    /// because the reader of its own stdout went away
+    pub(crate) stdout_broken: bool,
+    /// How long after the command was started it first produced any output,
+    /// or `None` if it never did
+    pub(crate) first_output: Option<Duration>,
+    /// The longest stretch of time during which the command produced no
+    /// output
+    pub(crate) longest_silence: Duration,
+    /// Statistics on the gaps between consecutive lines of output, or `None`
+    /// if there were fewer than two lines
+    pub(crate) line_gaps: Option<GapStats>,
+    /// Jumps in the system clock that occurred during the run
+    pub(crate) clock_jumps: Vec<ClockJump>,
+    /// If `elapsed` stopped displaying the status line because drawing it
+    /// failed, how long after the start that happened and the error message
+    pub(crate) status_lost: Option<(Duration, String)>,
+    /// The number of refreshes of the status line that were delayed because
+    /// `elapsed` was busy handling output
+    pub(crate) missed_ticks: u64,
+    /// If lines are dropped when a sink falls behind (`--sink-overflow=drop`),
+    /// the name of each sink along with how many lines were dropped for it
+    pub(crate) sink_drops: Option<Vec<(&'static str, u64)>>,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rows = vec![
+            ("Command", self.command.clone()),
+            ("Run ID", self.run_id.clone()),
+            ("Started", self.started.clone()),
+            ("Duration", format_duration(self.duration)),
+            ("Exit status", self.exit_status.clone()),
+        ];
+        if !self.suspended.is_zero() {
+            rows.push(("Suspended", format_duration(self.suspended)));
+        }
+        if let Some(usage) = self.usage {
+            rows.push((
+                "CPU time",
+                format!(
+                    "{} (user {}, system {})",
+                    format_duration(usage.cpu_time()),
+                    format_duration(usage.user_time),
+                    format_duration(usage.system_time)
+                ),
+            ));
+            rows.push(("Max RSS", format_bytes(usage.max_rss)));
+        }
+        if let Some(energy) = self.energy {
+            rows.push(("Energy", format_joules(energy)));
+        }
+        if let Some(reasons) = &self.wait_reasons {
+            rows.push(("Wait reasons", reasons.to_string()));
+        }
+        let mut notes = Vec::new();
+        if self.merged {
+            notes.push("stdout & stderr merged");
+        }
+        if self.stdout_broken {
+            notes.push("relaying stopped on broken pipe");
+        }
+        let mut stdout = self.counts.stdout.to_string();
+        if !notes.is_empty() {
+            stdout = format!("{stdout} ({})", notes.join("; "));
+        }
+        if self.merged {
+            rows.push(("Output", stdout));
+        } else {
+            rows.push(("Stdout", stdout));
+            rows.push(("Stderr", self.counts.stderr.to_string()));
+        }
+        rows.push((
+            "First output",
+            self.first_output
+                .map_or_else(|| String::from("none"), format_duration),
+        ));
+        rows.push(("Longest silence", format_duration(self.longest_silence)));
+        rows.push((
+            "Line gaps",
+            self.line_gaps
+                .map_or_else(|| String::from("none"), |gaps| gaps.to_string()),
+        ));
+        if !self.clock_jumps.is_empty() {
+            let jumps = self
+                .clock_jumps
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            rows.push(("Clock jumps", jumps.join(", ")));
+        }
+        if self.missed_ticks > 0 {
+            rows.push(("Missed ticks", format_count(self.missed_ticks)));
+        }
+        if let Some(drops) = self.sink_drops.as_ref().filter(|d| !d.is_empty()) {
+            let drops = drops
+                .iter()
+                .map(|&(name, n)| format!("{name} {}", format_count(n)))
+                .collect::<Vec<_>>();
+            rows.push(("Dropped lines", drops.join(", ")));
+        }
+        if let Some((at, e)) = &self.status_lost {
+            rows.push((
+                "Status line",
+                format!("lost at {}: {e}", format_duration(*at)),
+            ));
+        }
+        let width = rows.iter().map(|(k, _)| k.len()).max().unwrap_or_default();
+        for (key, value) in rows {
+            writeln!(f, "{key:width$}  {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A record of the times between consecutive lines of output
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct LineGaps {
+    /// When the most recent line was received
+    last: Option<Instant>,
+    gaps: Vec<Duration>,
+}
+
+impl LineGaps {
+    pub(crate) fn new() -> LineGaps {
+        LineGaps::default()
+    }
+
+    /// Note that a line of output was received at time `now`
+    pub(crate) fn observe(&mut self, now: Instant) {
+        if let Some(last) = self.last.replace(now) {
+            self.gaps.push(now.saturating_duration_since(last));
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Option<GapStats> {
+        let mut gaps = self.gaps.clone();
+        gaps.sort_unstable();
+        // Percentiles are computed using the nearest-rank method.
+        let nth = |pct: usize| gaps[(gaps.len() * pct).div_ceil(100).saturating_sub(1)];
+        Some(GapStats {
+            min: *gaps.first()?,
+            median: nth(50),
+            p95: nth(95),
+            max: *gaps.last()?,
+        })
+    }
+}
+
+/// Summary statistics on the gaps between consecutive lines of output
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct GapStats {
+    pub(crate) min: Duration,
+    pub(crate) median: Duration,
+    pub(crate) p95: Duration,
+    pub(crate) max: Duration,
+}
+
+impl fmt::Display for GapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min {}, median {}, p95 {}, max {}",
+            format_duration(self.min),
+            format_duration(self.median),
+            format_duration(self.p95),
+            format_duration(self.max)
+        )
+    }
+}
+
+/// Format a duration as "HH:MM:SS.mmm"
+pub(crate) fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        d.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamCounts;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Duration::ZERO, "00:00:00.000")]
+    #[case(Duration::from_millis(1234), "00:00:01.234")]
+    #[case(Duration::from_millis(3_723_450), "01:02:03.450")]
+    #[case(Duration::from_secs(100 * 3600), "100:00:00.000")]
+    fn duration(#[case] d: Duration, #[case] s: &str) {
+        assert_eq!(format_duration(d), s);
+    }
+
+    #[test]
+    fn display() {
+        let summary = Summary {
+            command: String::from("make -j4"),
+            run_id: String::from("5f0c3a9e7d1b2468"),
+            started: String::from("2026-10-16T14:00:00+02:00"),
+            duration: Duration::from_millis(83_456),
+            suspended: Duration::from_millis(12_500),
+            exit_status: String::from("exited with code 0"),
+            usage: Some(ChildUsage {
+                user_time: Duration::from_millis(40_000),
+                system_time: Duration::from_millis(5_120),
+                max_rss: 3 << 20,
+            }),
+            energy: Some(1_234_567_890),
+            wait_reasons: None,
+            counts: OutputCounts {
+                stdout: StreamCounts {
+                    lines: 120,
+                    bytes: 4608,
+                },
+                stderr: StreamCounts {
+                    lines: 1,
+                    bytes: 25,
+                },
+            },
+            merged: false,
+            stdout_broken: false,
+            first_output: Some(Duration::from_millis(250)),
+            longest_silence: Duration::from_millis(12_300),
+            line_gaps: Some(GapStats {
+                min: Duration::from_millis(1),
+                median: Duration::from_millis(120),
+                p95: Duration::from_millis(1500),
+                max: Duration::from_millis(12_300),
+            }),
+            clock_jumps: vec![ClockJump {
+                at: Duration::from_secs(42),
+                forwards: true,
+                by: Duration::from_secs(3600),
+            }],
+            status_lost: Some((
+                Duration::from_millis(61_500),
+                String::from("Input/output error (os error 5)"),
+            )),
+            missed_ticks: 12,
+            sink_drops: Some(vec![("--slowlog", 0), ("--json", 1234)]),
+        };
+        assert_eq!(
+            summary.to_string(),
+            concat!(
+                "Command          make -j4\n",
+                "Run ID           5f0c3a9e7d1b2468\n",
+                "Started          2026-10-16T14:00:00+02:00\n",
+                "Duration         00:01:23.456\n",
+                "Exit status      exited with code 0\n",
+                "Suspended        00:00:12.500\n",
+                "CPU time         00:00:45.120 (user 00:00:40.000, system 00:00:05.120)\n",
+                "Max RSS          3.0 MiB\n",
+                "Energy           1234.567 J\n",
+                "Stdout           120 lines, 4.5 KiB\n",
+                "Stderr           1 line, 25 B\n",
+                "First output     00:00:00.250\n",
+                "Longest silence  00:00:12.300\n",
+                "Line gaps        min 00:00:00.001, median 00:00:00.120, p95 00:00:01.500, max 00:00:12.300\n",
+                "Clock jumps      +01:00:00 at 00:00:42\n",
+                "Missed ticks     12\n",
+                "Dropped lines    --slowlog 0, --json 1234\n",
+                "Status line      lost at 00:01:01.500: Input/output error (os error 5)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn display_merged_no_usage() {
+        let summary = Summary {
+            command: String::from("true"),
+            run_id: String::from("0123456789abcdef"),
+            started: String::from("2026-10-16T12:00:00Z"),
+            duration: Duration::from_millis(5),
+            suspended: Duration::ZERO,
+            exit_status: String::from("terminated abnormally"),
+            usage: None,
+            energy: None,
+            wait_reasons: None,
+            counts: OutputCounts {
+                stdout: StreamCounts {
+                    lines: 2,
+                    bytes: 40,
+                },
+                stderr: StreamCounts::default(),
+            },
+            merged: true,
+            stdout_broken: true,
+            first_output: None,
+            longest_silence: Duration::from_millis(5),
+            line_gaps: None,
+            clock_jumps: Vec::new(),
+            status_lost: None,
+            missed_ticks: 0,
+            sink_drops: None,
+        };
+        assert_eq!(
+            summary.to_string(),
+            concat!(
+                "Command          true\n",
+                "Run ID           0123456789abcdef\n",
+                "Started          2026-10-16T12:00:00Z\n",
+                "Duration         00:00:00.005\n",
+                "Exit status      terminated abnormally\n",
+                "Output           2 lines, 40 B (stdout & stderr merged; relaying stopped on broken pipe)\n",
+                "First output     none\n",
+                "Longest silence  00:00:00.005\n",
+                "Line gaps        none\n",
+            )
+        );
+    }
+
+    #[test]
+    fn line_gaps() {
+        let t0 = Instant::now();
+        let mut gaps = LineGaps::new();
+        assert_eq!(gaps.stats(), None);
+        gaps.observe(t0);
+        assert_eq!(gaps.stats(), None);
+        let mut t = t0;
+        for ms in [30, 10, 20, 500, 40, 50, 60, 70, 80, 90] {
+            t += Duration::from_millis(ms);
+            gaps.observe(t);
+        }
+        assert_eq!(
+            gaps.stats(),
+            Some(GapStats {
+                min: Duration::from_millis(10),
+                median: Duration::from_millis(50),
+                p95: Duration::from_millis(500),
+                max: Duration::from_millis(500),
+            })
+        );
+    }
+
+    #[test]
+    fn line_gaps_single() {
+        let t0 = Instant::now();
+        let mut gaps = LineGaps::new();
+        gaps.observe(t0);
+        gaps.observe(t0 + Duration::from_millis(250));
+        let d = Duration::from_millis(250);
+        assert_eq!(
+            gaps.stats(),
+            Some(GapStats {
+                min: d,
+                median: d,
+                p95: d,
+                max: d,
+            })
+        );
+    }
+}
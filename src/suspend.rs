@@ -0,0 +1,142 @@
+//! Noticing when the command is suspended (e.g., by Ctrl-Z in `--tty` mode or
+//! by `SIGSTOP`) & resumed, and keeping the time it spends suspended off the
+//! clock for `--exclude-suspended`
+use std::time::{Duration, Instant};
+
+/// A clock measuring the time elapsed since a starting instant, less any time
+/// during which it was paused
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct PausableClock {
+    start: Instant,
+    /// When the clock was paused, if it currently is
+    paused_since: Option<Instant>,
+    /// The total length of all completed pauses
+    paused: Duration,
+}
+
+impl PausableClock {
+    pub(crate) fn new(start: Instant) -> PausableClock {
+        PausableClock {
+            start,
+            paused_since: None,
+            paused: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused_since.is_some()
+    }
+
+    /// Return the time on the clock as of now
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.elapsed_at(Instant::now())
+    }
+
+    /// Return the time on the clock as of `now`
+    pub(crate) fn elapsed_at(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.start)
+            .saturating_sub(self.paused_at(now))
+    }
+
+    /// Return how long the clock has spent paused as of `now`, including the
+    /// current pause, if any
+    pub(crate) fn paused_at(&self, now: Instant) -> Duration {
+        self.paused
+            + self
+                .paused_since
+                .map_or(Duration::ZERO, |since| now.saturating_duration_since(since))
+    }
+
+    /// Stop the clock at time `now` if it's running
+    pub(crate) fn pause(&mut self, now: Instant) {
+        if self.paused_since.is_none() {
+            self.paused_since = Some(now);
+        }
+    }
+
+    /// Restart the clock at time `now` if it's paused
+    pub(crate) fn resume(&mut self, now: Instant) {
+        if let Some(since) = self.paused_since.take() {
+            self.paused += now.saturating_duration_since(since);
+        }
+    }
+}
+
+/// A change in whether the command is suspended
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Change {
+    /// The command was stopped by a job control signal
+    Stopped,
+    /// The command was continued by `SIGCONT`
+    Continued,
+}
+
+/// A listener for `SIGCHLD` that reports when the command's process stops or
+/// continues.  Only the command's own process is watched, not any of its
+/// descendants.  On platforms without `waitid(2)`, or if the listener
+/// couldn't be set up, it never fires.
+#[derive(Debug)]
+pub(crate) struct SuspendWatcher {
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl SuspendWatcher {
+    pub(crate) fn new() -> SuspendWatcher {
+        SuspendWatcher {
+            #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::child()).ok(),
+        }
+    }
+
+    /// Wait for the process with ID `pid` to stop or continue.  Exits of the
+    /// process are not consumed, so they can still be collected by
+    /// [`tokio::process::Child::wait()`].
+    #[cfg_attr(
+        not(any(target_os = "linux", target_os = "android", target_os = "freebsd")),
+        allow(clippy::unused_async, unused_variables)
+    )]
+    pub(crate) async fn recv(&mut self, pid: Option<u32>) -> Change {
+        #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+        if let (Some(signal), Some(pid)) =
+            (&mut self.signal, pid.and_then(|p| i32::try_from(p).ok()))
+        {
+            use nix::sys::wait::{Id, WaitPidFlag, WaitStatus, waitid};
+            use nix::unistd::Pid;
+            let flags = WaitPidFlag::WSTOPPED | WaitPidFlag::WCONTINUED | WaitPidFlag::WNOHANG;
+            while signal.recv().await.is_some() {
+                match waitid(Id::Pid(Pid::from_raw(pid)), flags) {
+                    Ok(WaitStatus::Stopped(..)) => return Change::Stopped,
+                    Ok(WaitStatus::Continued(_)) => return Change::Continued,
+                    _ => (),
+                }
+            }
+        }
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_resume() {
+        let start = Instant::now();
+        let secs = Duration::from_secs;
+        let mut clock = PausableClock::new(start);
+        assert_eq!(clock.elapsed_at(start + secs(5)), secs(5));
+        clock.pause(start + secs(5));
+        assert!(clock.is_paused());
+        assert_eq!(clock.elapsed_at(start + secs(8)), secs(5));
+        assert_eq!(clock.paused_at(start + secs(8)), secs(3));
+        // Pausing an already-paused clock changes nothing.
+        clock.pause(start + secs(9));
+        clock.resume(start + secs(10));
+        assert!(!clock.is_paused());
+        assert_eq!(clock.elapsed_at(start + secs(12)), secs(7));
+        clock.resume(start + secs(13));
+        assert_eq!(clock.elapsed_at(start + secs(14)), secs(9));
+        assert_eq!(clock.paused_at(start + secs(14)), secs(5));
+    }
+}
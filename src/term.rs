@@ -0,0 +1,139 @@
+use std::io::{self, Write};
+
+static BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Write OSC 133 ("semantic prompt") marks to `out`.  Each of `marks` is the
+/// part of a sequence after `133;`, e.g., `"A"` or `"D;0"`.
+pub(crate) fn write_osc133<W: Write>(mut out: W, marks: &[&str]) -> io::Result<()> {
+    for m in marks {
+        write!(out, "\x1B]133;{m}\x07")?;
+    }
+    out.flush()
+}
+
+/// Set the terminal "user variable" `name` to `value` using the OSC 1337
+/// `SetUserVar` sequence supported by iTerm2 and `WezTerm`
+pub(crate) fn set_user_var<W: Write>(mut out: W, name: &str, value: &str) -> io::Result<()> {
+    write!(
+        out,
+        "\x1B]1337;SetUserVar={name}={}\x07",
+        base64(value.as_bytes())
+    )?;
+    out.flush()
+}
+
+/// Returns true if `$TERM` is set to "dumb", indicating a terminal (such as an
+/// Emacs shell buffer) that doesn't support cursor-movement escape sequences
+pub(crate) fn is_dumb() -> bool {
+    std::env::var_os("TERM").is_some_and(|t| t == "dumb")
+}
+
+/// Returns true if the environment indicates that we're running in kitty,
+/// which uses OSC 99 for notifications rather than OSC 9.  `$TERM` is checked
+/// because, unlike kitty's other environment variables, it's passed along
+/// over SSH.
+pub(crate) fn is_kitty() -> bool {
+    std::env::var_os("TERM").is_some_and(|t| t == "xterm-kitty")
+        || std::env::var_os("KITTY_WINDOW_ID").is_some()
+}
+
+/// Send a desktop notification with the given title & body via the terminal,
+/// using kitty's OSC 99 protocol if `kitty` is true or else the OSC 9
+/// sequence supported by foot, `WezTerm`, iTerm2, and others (which only takes
+/// a body).  Since this goes through the terminal, it works even when running
+/// on a remote host over SSH.
+pub(crate) fn send_notification<W: Write>(
+    mut out: W,
+    kitty: bool,
+    title: &str,
+    body: &str,
+) -> io::Result<()> {
+    let title = sanitize(title);
+    let body = sanitize(body);
+    if kitty {
+        write!(out, "\x1B]99;i=elapsed:d=0;{title}\x1B\\")?;
+        write!(out, "\x1B]99;i=elapsed:d=1:p=body;{body}\x1B\\")?;
+    } else {
+        write!(out, "\x1B]9;{title}: {body}\x07")?;
+    }
+    out.flush()
+}
+
+/// Replace control characters (which could terminate an escape sequence
+/// early) with spaces
+fn sanitize(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+/// Encode `data` in standard, padded base64
+fn base64(data: &[u8]) -> String {
+    let mut s = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b0 = chunk.first().copied().unwrap_or(0);
+        let b1 = chunk.get(1).copied().unwrap_or(0);
+        let b2 = chunk.get(2).copied().unwrap_or(0);
+        let n = (u32::from(b0) << 16) | (u32::from(b1) << 8) | u32::from(b2);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = usize::try_from((n >> (18 - 6 * i)) & 0x3F).unwrap_or(0);
+                s.push(char::from(BASE64_ALPHABET[index]));
+            } else {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("", "")]
+    #[case("f", "Zg==")]
+    #[case("fo", "Zm8=")]
+    #[case("foo", "Zm9v")]
+    #[case("foob", "Zm9vYg==")]
+    #[case("fooba", "Zm9vYmE=")]
+    #[case("foobar", "Zm9vYmFy")]
+    #[case("Elapsed: 00:01:02", "RWxhcHNlZDogMDA6MDE6MDI=")]
+    fn test_base64(#[case] data: &str, #[case] encoded: &str) {
+        assert_eq!(base64(data.as_bytes()), encoded);
+    }
+
+    #[test]
+    fn user_var() {
+        let mut out = Vec::new();
+        set_user_var(&mut out, "elapsed", "00:00:05").unwrap();
+        assert_eq!(out, b"\x1B]1337;SetUserVar=elapsed=MDA6MDA6MDU=\x07");
+    }
+
+    #[test]
+    fn notification_osc9() {
+        let mut out = Vec::new();
+        send_notification(&mut out, false, "elapsed", "make exited with code 0").unwrap();
+        assert_eq!(out, b"\x1B]9;elapsed: make exited with code 0\x07");
+    }
+
+    #[test]
+    fn notification_kitty() {
+        let mut out = Vec::new();
+        send_notification(&mut out, true, "elapsed", "Line one\nLine\x07two").unwrap();
+        assert_eq!(
+            out,
+            b"\x1B]99;i=elapsed:d=0;elapsed\x1B\\\x1B]99;i=elapsed:d=1:p=body;Line one Line two\x1B\\"
+        );
+    }
+
+    #[test]
+    fn osc133() {
+        let mut out = Vec::new();
+        write_osc133(&mut out, &["A", "B", "C"]).unwrap();
+        assert_eq!(out, b"\x1B]133;A\x07\x1B]133;B\x07\x1B]133;C\x07");
+    }
+}
@@ -0,0 +1,227 @@
+//! Answering the queries that a command run via a pseudo-terminal sends to its
+//! terminal, for which it would otherwise wait forever, as there's no real
+//! terminal on the other end of the pty to reply
+use crate::PtySize;
+
+/// The reply to a primary device attributes query: a VT220 with ANSI color
+const PRIMARY_ATTRIBUTES: &[u8] = b"\x1B[?62;22c";
+
+/// The reply to a secondary device attributes query: a VT220, firmware
+/// version 10, with no ROM cartridge
+const SECONDARY_ATTRIBUTES: &[u8] = b"\x1B[>1;10;0c";
+
+/// The reply to a device status report query: "terminal OK"
+const STATUS_OK: &[u8] = b"\x1B[0n";
+
+/// A query recognized in the command's output
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Query {
+    /// `CSI 6 n`: Report the cursor position
+    CursorPosition,
+    /// `CSI 5 n`: Report the terminal's status
+    Status,
+    /// `CSI c` or `CSI 0 c`: Report the primary device attributes
+    PrimaryAttributes,
+    /// `CSI > c` or `CSI > 0 c`: Report the secondary device attributes
+    SecondaryAttributes,
+    /// `CSI 18 t`: Report the size of the text area in characters
+    TextAreaSize,
+}
+
+/// Removes queries from the output read from a pseudo-terminal and composes
+/// replies to them.
+///
+/// In order to answer cursor position queries, the column that the cursor is
+/// in is tracked by counting the characters in the output since the last
+/// carriage return or newline.  The cursor is always reported as being on the
+/// bottom row, as that's where output that scrolls the screen ends up.
+///
+/// Queries that are split across multiple reads are not recognized and are
+/// passed through unchanged.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct QueryResponder {
+    /// The size of the pseudo-terminal, if known
+    size: Option<PtySize>,
+    /// The zero-based column that the cursor is in after the output seen so
+    /// far
+    column: usize,
+}
+
+/// The result of [`QueryResponder::answer()`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Answered {
+    /// The output with all answered queries removed, or `None` if there were
+    /// none
+    pub(crate) output: Option<Vec<u8>>,
+    /// The replies to write back to the pseudo-terminal
+    pub(crate) replies: Vec<u8>,
+}
+
+impl QueryResponder {
+    pub(crate) fn new(size: Option<PtySize>) -> QueryResponder {
+        QueryResponder { size, column: 0 }
+    }
+
+    /// Find & answer the queries in `data`, a chunk of output read from the
+    /// pseudo-terminal
+    pub(crate) fn answer(&mut self, data: &[u8]) -> Answered {
+        let mut answered = Answered::default();
+        // The start of the data not yet copied to `answered.output`
+        let mut kept = 0;
+        let mut i = 0;
+        while i < data.len() {
+            if data[i] == b'\x1B' {
+                if let Some((len, query)) = parse_csi(&data[i..]) {
+                    if let Some(reply) = query.and_then(|q| self.reply(q)) {
+                        let output = answered.output.get_or_insert_with(Vec::new);
+                        output.extend_from_slice(&data[kept..i]);
+                        answered.replies.extend_from_slice(&reply);
+                        kept = i + len;
+                    }
+                    i += len;
+                    continue;
+                }
+            }
+            match data[i] {
+                b'\n' | b'\r' => self.column = 0,
+                b'\x08' => self.column = self.column.saturating_sub(1),
+                b'\t' => self.column = (self.column / 8 + 1) * 8,
+                // Count each UTF-8 character by its first byte
+                b if b >= 0x20 && b != 0x7F && !(0x80..0xC0).contains(&b) => self.column += 1,
+                _ => (),
+            }
+            i += 1;
+        }
+        if let Some(output) = &mut answered.output {
+            output.extend_from_slice(&data[kept..]);
+        }
+        answered
+    }
+
+    /// Compose the reply to `query`, or return `None` if it can't be answered
+    fn reply(&self, query: Query) -> Option<Vec<u8>> {
+        match query {
+            Query::CursorPosition => {
+                let (row, column) = match self.size {
+                    Some(PtySize { cols, rows }) => {
+                        (rows, self.column.min(usize::from(cols) - 1) + 1)
+                    }
+                    None => (1, self.column + 1),
+                };
+                Some(format!("\x1B[{row};{column}R").into_bytes())
+            }
+            Query::Status => Some(STATUS_OK.to_vec()),
+            Query::PrimaryAttributes => Some(PRIMARY_ATTRIBUTES.to_vec()),
+            Query::SecondaryAttributes => Some(SECONDARY_ATTRIBUTES.to_vec()),
+            Query::TextAreaSize => self
+                .size
+                .map(|PtySize { cols, rows }| format!("\x1B[8;{rows};{cols}t").into_bytes()),
+        }
+    }
+}
+
+/// If `data` starts with a complete CSI escape sequence, return its length
+/// and the query it represents, if any
+fn parse_csi(data: &[u8]) -> Option<(usize, Option<Query>)> {
+    let body = data.strip_prefix(b"\x1B[")?;
+    let params_len = body
+        .iter()
+        .position(|b| !(0x30..=0x3F).contains(b))
+        .unwrap_or(body.len());
+    let inter_len = body[params_len..]
+        .iter()
+        .position(|b| !(0x20..=0x2F).contains(b))
+        .unwrap_or(body.len() - params_len);
+    let final_byte = *body
+        .get(params_len + inter_len)
+        .filter(|b| (0x40..=0x7E).contains(*b))?;
+    let len = 2 + params_len + inter_len + 1;
+    let params = &body[..params_len];
+    let query = match (params, inter_len, final_byte) {
+        (b"6", 0, b'n') => Some(Query::CursorPosition),
+        (b"5", 0, b'n') => Some(Query::Status),
+        (b"" | b"0", 0, b'c') => Some(Query::PrimaryAttributes),
+        (b">" | b">0", 0, b'c') => Some(Query::SecondaryAttributes),
+        (b"18", 0, b't') => Some(Query::TextAreaSize),
+        _ => None,
+    };
+    Some((len, query))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    const SIZE: PtySize = PtySize { cols: 80, rows: 24 };
+
+    #[rstest]
+    #[case(b"\x1B[6n", b"\x1B[24;1R")]
+    #[case(b"\x1B[5n", b"\x1B[0n")]
+    #[case(b"\x1B[c", b"\x1B[?62;22c")]
+    #[case(b"\x1B[0c", b"\x1B[?62;22c")]
+    #[case(b"\x1B[>c", b"\x1B[>1;10;0c")]
+    #[case(b"\x1B[>0c", b"\x1B[>1;10;0c")]
+    #[case(b"\x1B[18t", b"\x1B[8;24;80t")]
+    fn answer_one(#[case] query: &[u8], #[case] reply: &[u8]) {
+        let mut responder = QueryResponder::new(Some(SIZE));
+        assert_eq!(
+            responder.answer(query),
+            Answered {
+                output: Some(Vec::new()),
+                replies: reply.to_vec(),
+            }
+        );
+    }
+
+    #[rstest]
+    #[case(b"Hello, world!\n")]
+    #[case(b"\x1B[1;31mError\x1B[m\n")]
+    #[case(b"\x1B[?6n")]
+    #[case(b"\x1B[6")]
+    #[case(b"\x1B]9;4;1;50\x07")]
+    fn answer_none(#[case] data: &[u8]) {
+        let mut responder = QueryResponder::new(Some(SIZE));
+        assert_eq!(responder.answer(data), Answered::default());
+    }
+
+    #[test]
+    fn cursor_position() {
+        let mut responder = QueryResponder::new(Some(SIZE));
+        assert_eq!(
+            responder.answer("Line 1\nNäme:\t\x1B[1mX\x1B[m\x1B[6n rest".as_bytes()),
+            Answered {
+                output: Some("Line 1\nNäme:\t\x1B[1mX\x1B[m rest".as_bytes().to_vec()),
+                replies: b"\x1B[24;10R".to_vec(),
+            }
+        );
+        assert_eq!(
+            responder.answer(b"\r\x1B[6n"),
+            Answered {
+                output: Some(b"\r".to_vec()),
+                replies: b"\x1B[24;1R".to_vec(),
+            }
+        );
+        let long = vec![b'x'; 100];
+        assert_eq!(responder.answer(&long), Answered::default());
+        assert_eq!(
+            responder.answer(b"\x1B[6n"),
+            Answered {
+                output: Some(Vec::new()),
+                replies: b"\x1B[24;80R".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn unknown_size() {
+        let mut responder = QueryResponder::new(None);
+        assert_eq!(
+            responder.answer(b"> \x1B[18t\x1B[6n"),
+            Answered {
+                output: Some(b"> \x1B[18t".to_vec()),
+                replies: b"\x1B[1;3R".to_vec(),
+            }
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Escalation chains of signals for stopping the command, for
+//! `--term-sequence`
+use crate::units::parse_duration;
+use nix::sys::signal::Signal;
+use std::time::Duration;
+use thiserror::Error;
+
+/// A sequence of signals to send to the command in order to stop it, each
+/// paired with how long to wait for the command to exit before moving on to
+/// the next
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TermSequence(Vec<TermStep>);
+
+/// A single step of a [`TermSequence`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct TermStep {
+    pub(crate) signal: Signal,
+    /// How long to wait after sending `signal` before moving on to the next
+    /// step.  This is only `None` for the last step, after which there is
+    /// nothing left to do but wait.
+    pub(crate) wait: Option<Duration>,
+}
+
+impl TermSequence {
+    /// Return the step at (zero-based) index `i`, if there is one
+    pub(crate) fn get(&self, i: usize) -> Option<TermStep> {
+        self.0.get(i).copied()
+    }
+}
+
+impl Default for TermSequence {
+    /// Send `SIGTERM`, and then send `SIGKILL` if the command is still running
+    /// two seconds later
+    fn default() -> TermSequence {
+        TermSequence(vec![
+            TermStep {
+                signal: Signal::SIGTERM,
+                wait: Some(Duration::from_secs(2)),
+            },
+            TermStep {
+                signal: Signal::SIGKILL,
+                wait: None,
+            },
+        ])
+    }
+}
+
+impl std::str::FromStr for TermSequence {
+    type Err = ParseTermSequenceError;
+
+    /// Parse a comma-separated list of `SIGNAL[:WAIT]` items, where `SIGNAL`
+    /// is a signal name (case insensitive, with or without a "SIG" prefix) or
+    /// number and `WAIT` is a number of seconds optionally followed by a unit
+    /// of `ms`, `s`, or `m`.  Only the last item may omit `WAIT`.
+    fn from_str(s: &str) -> Result<TermSequence, ParseTermSequenceError> {
+        let mut steps = Vec::new();
+        let mut items = s.split(',').map(str::trim).peekable();
+        while let Some(item) = items.next() {
+            let (signal, wait) = match item.split_once(':') {
+                Some((signal, wait)) => (signal.trim(), Some(wait.trim())),
+                None => (item, None),
+            };
+            let signal = parse_signal(signal)
+                .ok_or_else(|| ParseTermSequenceError::Signal(signal.into()))?;
+            let wait = match wait {
+                Some(w) => {
+                    Some(parse_duration(w).ok_or_else(|| ParseTermSequenceError::Wait(w.into()))?)
+                }
+                None if items.peek().is_some() => {
+                    return Err(ParseTermSequenceError::MissingWait(item.into()));
+                }
+                None => None,
+            };
+            steps.push(TermStep { signal, wait });
+        }
+        Ok(TermSequence(steps))
+    }
+}
+
+/// Parse a signal name like "TERM", "sigint", or "9"
+fn parse_signal(s: &str) -> Option<Signal> {
+    if let Ok(n) = s.parse::<i32>() {
+        return Signal::try_from(n).ok();
+    }
+    let s = s.to_ascii_uppercase();
+    let name = s.strip_prefix("SIG").unwrap_or(&s);
+    format!("SIG{name}").parse::<Signal>().ok()
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(crate) enum ParseTermSequenceError {
+    #[error("invalid signal in termination sequence: {0:?}")]
+    Signal(String),
+    #[error("invalid wait time in termination sequence: {0:?}")]
+    Wait(String),
+    #[error("only the last signal in a termination sequence may omit a wait time: {0:?}")]
+    MissingWait(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn parse() {
+        let seq = "INT:5s,TERM:10s,KILL".parse::<TermSequence>().unwrap();
+        assert_eq!(
+            seq,
+            TermSequence(vec![
+                TermStep {
+                    signal: Signal::SIGINT,
+                    wait: Some(Duration::from_secs(5)),
+                },
+                TermStep {
+                    signal: Signal::SIGTERM,
+                    wait: Some(Duration::from_secs(10)),
+                },
+                TermStep {
+                    signal: Signal::SIGKILL,
+                    wait: None,
+                },
+            ])
+        );
+        assert_eq!(seq.get(3), None);
+    }
+
+    #[test]
+    fn parse_final_wait() {
+        let seq = "sighup : 1.5 , 15:500ms".parse::<TermSequence>().unwrap();
+        assert_eq!(
+            seq,
+            TermSequence(vec![
+                TermStep {
+                    signal: Signal::SIGHUP,
+                    wait: Some(Duration::from_millis(1500)),
+                },
+                TermStep {
+                    signal: Signal::SIGTERM,
+                    wait: Some(Duration::from_millis(500)),
+                },
+            ])
+        );
+    }
+
+    #[rstest]
+    #[case("", ParseTermSequenceError::Signal(String::new()))]
+    #[case("TERM,KILL", ParseTermSequenceError::MissingWait("TERM".into()))]
+    #[case("TERM:5s,,KILL", ParseTermSequenceError::Signal(String::new()))]
+    #[case("BOGUS:5s,KILL", ParseTermSequenceError::Signal("BOGUS".into()))]
+    #[case("TERM:soon,KILL", ParseTermSequenceError::Wait("soon".into()))]
+    #[case("TERM:-1s,KILL", ParseTermSequenceError::Wait("-1s".into()))]
+    fn parse_err(#[case] s: &str, #[case] err: ParseTermSequenceError) {
+        assert_eq!(s.parse::<TermSequence>(), Err(err));
+    }
+}
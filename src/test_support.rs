@@ -0,0 +1,253 @@
+//! A harness for testing programs that draw on a terminal.
+//!
+//! A [`TestScreen`] runs a command in a pseudo-terminal and feeds its output to
+//! a terminal emulator, so that tests can wait for the emulated screen to show
+//! what they expect, type keys at the command, and check how it exited:
+//!
+//! ```no_run
+//! use elapsed_cmd::test_support::{StartsWith, TestScreen};
+//! use std::time::Duration;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let mut screen = TestScreen::spawn(
+//!     pty_process::Command::new("python3").arg("-c").arg("print(input('Name: '))"),
+//! )
+//! .unwrap();
+//! screen
+//!     .wait_for_contents(StartsWith("Name:"), Duration::from_secs(1))
+//!     .await?;
+//! screen.send_keys(b"Alice\n").await?;
+//! let status = screen.wait_for_exit(Duration::from_secs(1)).await?;
+//! assert!(status.success());
+//! assert_eq!(screen.contents(), "Name: Alice\nAlice");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! This module is only available on Unix and only when the `test-support`
+//! feature is enabled.
+use nix::unistd::Pid;
+use std::fmt;
+use std::io;
+use std::process::ExitStatus;
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::{Instant, timeout, timeout_at},
+};
+
+/// The number of rows on the screen used by [`TestScreen::spawn()`]
+pub const DEFAULT_ROWS: u16 = 24;
+
+/// The number of columns on the screen used by [`TestScreen::spawn()`]
+pub const DEFAULT_COLS: u16 = 80;
+
+/// A test of a [`TestScreen`]'s contents, as passed to
+/// [`TestScreen::wait_for_contents()`]
+pub trait StrMatcher: fmt::Debug {
+    /// Return true if the screen contents `s` are what's being waited for
+    fn matches(&self, s: &str) -> bool;
+}
+
+/// A `&str` matches screen contents that are exactly equal to it
+impl StrMatcher for &str {
+    fn matches(&self, s: &str) -> bool {
+        *self == s
+    }
+}
+
+/// A matcher for screen contents that start with the given string
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StartsWith(pub &'static str);
+
+impl StrMatcher for StartsWith {
+    fn matches(&self, s: &str) -> bool {
+        s.starts_with(self.0)
+    }
+}
+
+/// A command running in a pseudo-terminal, along with an emulation of the
+/// terminal screen that its output is drawn on
+pub struct TestScreen {
+    parser: vt100::Parser,
+    p: tokio::process::Child,
+    pty: pty_process::Pty,
+    /// Everything read from the pty so far, for checking escape sequences
+    /// that vt100 doesn't keep track of
+    raw: Vec<u8>,
+}
+
+impl TestScreen {
+    /// Run `cmd` in a new pseudo-terminal with a screen of [`DEFAULT_ROWS`]
+    /// rows and [`DEFAULT_COLS`] columns
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pseudo-terminal cannot be set up or the
+    /// command cannot be spawned.
+    pub fn spawn(cmd: pty_process::Command) -> Result<TestScreen, pty_process::Error> {
+        TestScreen::spawn_with_size(cmd, DEFAULT_ROWS, DEFAULT_COLS)
+    }
+
+    /// Run `cmd` in a new pseudo-terminal with a screen of the given size
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pseudo-terminal cannot be set up or the
+    /// command cannot be spawned.
+    pub fn spawn_with_size(
+        cmd: pty_process::Command,
+        rows: u16,
+        cols: u16,
+    ) -> Result<TestScreen, pty_process::Error> {
+        let (pty, pts) = pty_process::open()?;
+        pty.resize(pty_process::Size::new(rows, cols))?;
+        let p = cmd.spawn(pts)?;
+        let parser = vt100::Parser::new(rows, cols, 0);
+        Ok(TestScreen {
+            pty,
+            p,
+            parser,
+            raw: Vec::new(),
+        })
+    }
+
+    /// Return the text currently shown on the screen, with trailing blank
+    /// lines and trailing whitespace on each line removed
+    pub fn contents(&self) -> String {
+        self.parser.screen().contents()
+    }
+
+    /// Return everything read from the pty so far, for checking escape
+    /// sequences that vt100 doesn't keep track of
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Return the process ID of the command
+    ///
+    /// # Panics
+    ///
+    /// Panics if the command has already been reaped by
+    /// [`wait_for_exit()`](TestScreen::wait_for_exit).
+    pub fn pid(&self) -> Pid {
+        let pid = self
+            .p
+            .id()
+            .expect("process should not have been reaped yet");
+        Pid::from_raw(i32::try_from(pid).expect("PID should fit in an i32"))
+    }
+
+    /// Type `keys` into the terminal
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to the pseudo-terminal fails.
+    pub async fn send_keys(&mut self, keys: &[u8]) -> io::Result<()> {
+        self.pty.write_all(keys).await
+    }
+
+    async fn read(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = vec![0u8; 2048];
+        match self.pty.read(&mut buf).await {
+            Ok(0) => Ok(None),
+            #[cfg(target_os = "linux")]
+            Err(e) if e.raw_os_error() == Some(5) => {
+                // On Linux, attempting to read from a pty master after the
+                // slave closes (due, e.g., to the child process exiting)
+                // results in EIO (which Rust currently represents with the
+                // undocumented ErrorKind::Uncategorized).
+                Ok(None)
+            }
+            Ok(n) => {
+                buf.truncate(n);
+                self.raw.extend_from_slice(&buf);
+                Ok(Some(buf))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Process the command's output until the screen contents match
+    /// `expected`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from the pseudo-terminal fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the screen contents do not match `expected` within `timeout`
+    /// or by the time the command closes the terminal.  The panic message
+    /// includes the final screen contents.
+    #[allow(clippy::match_wild_err_arm)]
+    pub async fn wait_for_contents<M: StrMatcher>(
+        &mut self,
+        expected: M,
+        timeout: Duration,
+    ) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            match timeout_at(deadline, self.read()).await {
+                Ok(Ok(Some(buf))) => {
+                    self.parser.process(&buf);
+                    if expected.matches(&self.contents()) {
+                        return Ok(());
+                    }
+                }
+                Ok(Ok(None)) => {
+                    panic!(
+                        "Reached EOF while waiting for screen contents {expected:?}; final content = {:?}",
+                        self.contents()
+                    );
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => {
+                    panic!(
+                        "Timed out while waiting for screen contents {expected:?}; final content = {:?}",
+                        self.contents()
+                    );
+                }
+            }
+        }
+    }
+
+    /// Process the rest of the command's output and then wait for it to exit,
+    /// returning its exit status
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading from the pseudo-terminal or waiting for the
+    /// command fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the command does not exit within `d`.  The panic message
+    /// includes the final screen contents.
+    pub async fn wait_for_exit(&mut self, d: Duration) -> io::Result<ExitStatus> {
+        if let Ok(r) = timeout(d, self.inner_wait_for_exit()).await {
+            r
+        } else {
+            panic!(
+                "Timed out while waiting for exit; final content = {:?}",
+                self.contents()
+            );
+        }
+    }
+
+    async fn inner_wait_for_exit(&mut self) -> io::Result<ExitStatus> {
+        while let Some(buf) = self.read().await? {
+            self.parser.process(&buf);
+        }
+        self.p.wait().await
+    }
+}
+
+impl fmt::Debug for TestScreen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestScreen")
+            .field("pid", &self.p.id())
+            .field("contents", &self.contents())
+            .finish_non_exhaustive()
+    }
+}
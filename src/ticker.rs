@@ -0,0 +1,117 @@
+//! A ticker that runs on a dedicated thread so that the status line's clock
+//! keeps advancing even while the event loop is stuck (e.g., on a blocking
+//! write of the command's output)
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{Receiver, channel, error::TrySendError};
+
+/// How long the event loop is given to pick up a tick before it's considered
+/// to be stalled
+const STALL_GRACE: Duration = Duration::from_millis(50);
+
+/// Delivers ticks to the event loop at a fixed period from a dedicated thread.
+/// If the event loop doesn't pick up a tick promptly, it's taken to be
+/// stalled, and the thread calls the `on_stall` callback itself.
+#[derive(Debug)]
+pub(crate) struct Ticker {
+    ticks: Receiver<()>,
+    /// The number of times the event loop has failed to pick up a tick
+    /// promptly
+    missed: Arc<AtomicU64>,
+    /// Dropping this stops the thread
+    _stop: mpsc::Sender<()>,
+}
+
+impl Ticker {
+    /// Start a thread that ticks every `period`, starting immediately
+    pub(crate) fn spawn<F>(period: Duration, mut on_stall: F) -> io::Result<Ticker>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let (tick_tx, ticks) = channel(1);
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        let missed = Arc::new(AtomicU64::new(0));
+        let missed_tx = Arc::clone(&missed);
+        thread::Builder::new()
+            .name(String::from("elapsed-ticker"))
+            .spawn(move || {
+                // Returns false if the thread should stop
+                let sleep = |d| stop_rx.recv_timeout(d) == Err(RecvTimeoutError::Timeout);
+                let grace = STALL_GRACE.min(period / 2);
+                let mut next = Instant::now();
+                loop {
+                    if tick_tx.try_send(()) == Err(TrySendError::Closed(())) {
+                        return;
+                    }
+                    if !sleep(grace) {
+                        return;
+                    }
+                    if tick_tx.capacity() == 0 {
+                        missed_tx.fetch_add(1, Ordering::Relaxed);
+                        on_stall();
+                    }
+                    // Ticks missed due to the system being suspended or the
+                    // thread not being scheduled are skipped rather than
+                    // made up for.
+                    next = (next + period).max(Instant::now());
+                    if !sleep(next.saturating_duration_since(Instant::now())) {
+                        return;
+                    }
+                }
+            })?;
+        Ok(Ticker {
+            ticks,
+            missed,
+            _stop: stop,
+        })
+    }
+
+    /// Return the number of ticks so far that the event loop failed to pick
+    /// up promptly because it was stalled
+    pub(crate) fn missed(&self) -> u64 {
+        self.missed.load(Ordering::Relaxed)
+    }
+
+    /// Wait for the next tick
+    pub(crate) async fn tick(&mut self) {
+        if self.ticks.recv().await.is_none() {
+            // The thread died; never tick again rather than spinning.
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[tokio::test]
+    async fn ticks() {
+        let mut ticker = Ticker::spawn(Duration::from_millis(10), || ()).unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            ticker.tick().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(ticker.missed(), 0);
+    }
+
+    #[test]
+    fn stalled() {
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&stalls);
+        let ticker = Ticker::spawn(Duration::from_millis(10), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(stalls.load(Ordering::SeqCst) >= 5);
+        assert!(ticker.missed() >= 5);
+        drop(ticker);
+    }
+}
@@ -0,0 +1,70 @@
+use crate::Stream;
+use crate::logfile::LogFile;
+use crate::runid::run_id;
+use crate::sink::{Line, Sink};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// A writer of JSON Lines records describing when each line of output from
+/// the child process was received
+#[derive(Debug)]
+pub(crate) struct LineTimings {
+    out: LogFile,
+}
+
+impl LineTimings {
+    /// Open `path` for writing records, appending to it instead of truncating
+    /// it if `append` is true
+    pub(crate) fn create(path: &Path, append: bool) -> io::Result<LineTimings> {
+        let out = LogFile::create(path, append)?;
+        Ok(LineTimings { out })
+    }
+
+    fn record(&mut self, stream: Stream, line: &[u8], elapsed: Duration) -> io::Result<()> {
+        let rec = LineRecord {
+            run_id: run_id(),
+            stream,
+            bytes: line.len(),
+            elapsed: elapsed.as_secs_f64(),
+        };
+        serde_json::to_writer(&mut self.out, &rec)?;
+        self.out.write_all(b"\n")
+    }
+}
+
+impl Sink for LineTimings {
+    fn line(&mut self, line: &Line<'_>) -> io::Result<()> {
+        self.record(line.stream, line.record, line.elapsed)
+    }
+
+    fn gap(&mut self, dropped: u64) -> io::Result<()> {
+        let rec = GapRecord {
+            run_id: run_id(),
+            dropped,
+        };
+        serde_json::to_writer(&mut self.out, &rec)?;
+        self.out.write_all(b"\n")
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+struct LineRecord {
+    run_id: &'static str,
+    stream: Stream,
+    bytes: usize,
+    elapsed: f64,
+}
+
+/// A record written in place of lines that were dropped because the file
+/// couldn't be written to fast enough
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+struct GapRecord {
+    run_id: &'static str,
+    dropped: u64,
+}
@@ -0,0 +1,86 @@
+use std::ffi::OsStr;
+use std::io;
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// A connection to the tmux pane that `elapsed` is running in, used for
+/// showing the elapsed time in the pane's title and announcing when the
+/// command finishes
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Tmux {
+    /// The ID of the pane (e.g., `%3`), if known
+    pane: Option<String>,
+    /// The title of the pane before `elapsed` started changing it
+    original_title: String,
+}
+
+impl Tmux {
+    /// Connect to the tmux pane that `elapsed` is running in.  Returns
+    /// `Ok(None)` if not running inside tmux.
+    pub(crate) async fn connect() -> io::Result<Option<Tmux>> {
+        if std::env::var_os("TMUX").is_none_or(|s| s.is_empty()) {
+            return Ok(None);
+        }
+        let pane = std::env::var("TMUX_PANE").ok().filter(|s| !s.is_empty());
+        let mut tmux = Tmux {
+            pane,
+            original_title: String::new(),
+        };
+        let out = tmux
+            .command("display-message")
+            .arg("-p")
+            .arg("#{pane_title}")
+            .stderr(Stdio::null())
+            .output()
+            .await?;
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "`tmux display-message` failed: {}",
+                out.status
+            )));
+        }
+        let mut title = String::from_utf8_lossy(&out.stdout).into_owned();
+        if title.ends_with('\n') {
+            title.pop();
+        }
+        tmux.original_title = title;
+        Ok(Some(tmux))
+    }
+
+    /// Set the pane's title to `title` without waiting for tmux to finish
+    pub(crate) fn set_title(&self, title: &str) -> io::Result<()> {
+        // Tokio reaps the process in the background once it exits.
+        self.select_pane_title(title).spawn()?;
+        Ok(())
+    }
+
+    /// Restore the pane's original title and display `message` in the tmux
+    /// status line
+    pub(crate) async fn finish(&self, message: &str) -> io::Result<()> {
+        self.select_pane_title(&self.original_title)
+            .status()
+            .await?;
+        self.command("display-message")
+            .arg(message)
+            .stdout(Stdio::null())
+            .status()
+            .await?;
+        Ok(())
+    }
+
+    fn select_pane_title(&self, title: &str) -> Command {
+        let mut cmd = self.command("select-pane");
+        cmd.arg("-T").arg(title).stdout(Stdio::null());
+        cmd
+    }
+
+    /// Construct a `tmux` command for running `subcommand` against the pane
+    fn command<S: AsRef<OsStr>>(&self, subcommand: S) -> Command {
+        let mut cmd = Command::new("tmux");
+        cmd.arg(subcommand).stdin(Stdio::null());
+        if let Some(pane) = &self.pane {
+            cmd.arg("-t").arg(pane);
+        }
+        cmd
+    }
+}
@@ -0,0 +1,294 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Binary prefixes for byte quantities of at least 1 KiB, in increasing order
+static BYTE_UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// The punctuation to use for numbers in all output, set once at startup from
+/// `--thousands-sep` and `--decimal-sep`
+static NUMBER_STYLE: OnceLock<NumberStyle> = OnceLock::new();
+
+/// How to punctuate numbers for display
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct NumberStyle {
+    /// The separator to insert between groups of three digits in the integer
+    /// part of a number; if empty, digits are not grouped
+    pub(crate) thousands_sep: String,
+    /// The separator to place between the integer & fractional parts of a
+    /// number
+    pub(crate) decimal_sep: String,
+}
+
+impl NumberStyle {
+    /// Make this the style used by [`format_count()`], [`format_decimal()`],
+    /// and [`format_bytes()`] for the rest of the program.  Only the first
+    /// call has any effect.
+    pub(crate) fn install(self) {
+        let _ = NUMBER_STYLE.set(self);
+    }
+
+    fn integer(&self, n: u64) -> String {
+        let digits = n.to_string();
+        if self.thousands_sep.is_empty() {
+            return digits;
+        }
+        let mut s = String::with_capacity(digits.len() * 2);
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i) % 3 == 0 {
+                s.push_str(&self.thousands_sep);
+            }
+            s.push(c);
+        }
+        s
+    }
+
+    fn decimal(&self, whole: u64, frac: &str) -> String {
+        format!("{}{}{frac}", self.integer(whole), self.decimal_sep)
+    }
+
+    fn bytes(&self, n: u64) -> String {
+        if n < 1024 {
+            return format!("{} B", self.integer(n));
+        }
+        let n = u128::from(n);
+        let mut unit = 0;
+        let mut scale: u128 = 1024;
+        while unit + 1 < BYTE_UNITS.len() && n >= scale * 1024 {
+            scale *= 1024;
+            unit += 1;
+        }
+        // `n / scale` is always less than 1024 * 1024 here.
+        let tenths = u64::try_from(n * 10 / scale).unwrap_or(u64::MAX);
+        format!(
+            "{} {}",
+            self.decimal(tenths / 10, &(tenths % 10).to_string()),
+            BYTE_UNITS[unit]
+        )
+    }
+}
+
+impl Default for NumberStyle {
+    fn default() -> NumberStyle {
+        NumberStyle {
+            thousands_sep: String::new(),
+            decimal_sep: String::from("."),
+        }
+    }
+}
+
+fn number_style() -> &'static NumberStyle {
+    NUMBER_STYLE.get_or_init(NumberStyle::default)
+}
+
+/// Format an integer quantity (e.g., a number of seconds or lines) for
+/// display, grouping its digits if so configured
+pub(crate) fn format_count(n: u64) -> String {
+    number_style().integer(n)
+}
+
+/// Format a number given as an integer part and a string of fractional digits
+/// for display, using the configured separators
+pub(crate) fn format_decimal(whole: u64, frac: &str) -> String {
+    number_style().decimal(whole, frac)
+}
+
+/// Format a number of bytes in human-readable form using binary (IEC)
+/// prefixes with one decimal place, e.g., "512 B" or "1.5 MiB".  Values are
+/// truncated rather than rounded.
+pub(crate) fn format_bytes(n: u64) -> String {
+    number_style().bytes(n)
+}
+
+/// Format a signed rate of change in bytes per second, e.g., "+1.5 MiB/s" or
+/// "-12 B/s"
+pub(crate) fn format_byte_rate(bytes_per_sec: i64) -> String {
+    let sign = if bytes_per_sec < 0 { '-' } else { '+' };
+    format!("{sign}{}/s", format_bytes(bytes_per_sec.unsigned_abs()))
+}
+
+/// Parse a human-readable quantity of bytes, such as "512", "1.5G", or
+/// "300MiB".  The number may have a fractional part and may be followed by
+/// one of the units `B`, `K`, `M`, `G`, `T`, `P`, or `E` (case insensitive),
+/// optionally followed by `iB` or `B`; all units are powers of 1024.
+/// Fractional bytes are truncated.
+pub(crate) fn parse_bytes(s: &str) -> Result<u64, ParseBytesError> {
+    let s = s.trim();
+    let numlen = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(numlen);
+    let unit = unit.trim_start();
+    let (whole, frac) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && frac.is_empty() {
+        return Err(ParseBytesError::InvalidNumber);
+    }
+    let multiplier: u128 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        "p" | "pb" | "pib" => 1 << 50,
+        "e" | "eb" | "eib" => 1 << 60,
+        _ => return Err(ParseBytesError::InvalidUnit(unit.to_owned())),
+    };
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        whole
+            .parse::<u128>()
+            .map_err(|_| ParseBytesError::InvalidNumber)?
+    };
+    let mut value = whole
+        .checked_mul(multiplier)
+        .ok_or(ParseBytesError::Overflow)?;
+    if !frac.is_empty() {
+        if !frac.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseBytesError::InvalidNumber);
+        }
+        // Only the first few digits can matter, as the largest multiplier is
+        // less than 10^19.
+        let frac = frac.get(..20).unwrap_or(frac);
+        let denom = 10u128.pow(u32::try_from(frac.len()).unwrap_or(20));
+        let numer = frac
+            .parse::<u128>()
+            .map_err(|_| ParseBytesError::InvalidNumber)?;
+        value += numer * multiplier / denom;
+    }
+    u64::try_from(value).map_err(|_| ParseBytesError::Overflow)
+}
+
+/// Parse a duration like "5", "2.5s", "500ms", or "1m", where a number without
+/// a unit is a number of seconds
+pub(crate) fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, unit_secs) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60.0)
+    } else {
+        (s, 1.0)
+    };
+    let number = number.trim_end().parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(number * unit_secs).ok()
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(crate) enum ParseBytesError {
+    #[error("invalid number in byte quantity")]
+    InvalidNumber,
+    #[error("invalid unit in byte quantity: {0:?}")]
+    InvalidUnit(String),
+    #[error("byte quantity is too large")]
+    Overflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("5", Duration::from_secs(5))]
+    #[case("5s", Duration::from_secs(5))]
+    #[case("0.25s", Duration::from_millis(250))]
+    #[case("250ms", Duration::from_millis(250))]
+    #[case("2m", Duration::from_secs(120))]
+    fn duration(#[case] s: &str, #[case] d: Duration) {
+        assert_eq!(parse_duration(s), Some(d));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("soon")]
+    #[case("-1s")]
+    #[case("5h")]
+    fn invalid_duration(#[case] s: &str) {
+        assert_eq!(parse_duration(s), None);
+    }
+
+    #[rstest]
+    #[case(0, "0 B")]
+    #[case(1023, "1023 B")]
+    #[case(1024, "1.0 KiB")]
+    #[case(1536, "1.5 KiB")]
+    #[case(1024 * 1024 - 1, "1023.9 KiB")]
+    #[case(5 * 1024 * 1024 + 104_858, "5.1 MiB")]
+    #[case(3 << 40, "3.0 TiB")]
+    #[case(u64::MAX, "15.9 EiB")]
+    fn bytes(#[case] n: u64, #[case] s: &str) {
+        assert_eq!(format_bytes(n), s);
+    }
+
+    fn swiss() -> NumberStyle {
+        NumberStyle {
+            thousands_sep: String::from("'"),
+            decimal_sep: String::from(","),
+        }
+    }
+
+    #[rstest]
+    #[case(0, "0")]
+    #[case(999, "999")]
+    #[case(1000, "1'000")]
+    #[case(1_234_567, "1'234'567")]
+    #[case(123_456_789, "123'456'789")]
+    fn grouped_integer(#[case] n: u64, #[case] s: &str) {
+        assert_eq!(swiss().integer(n), s);
+    }
+
+    #[rstest]
+    #[case(1000, "1'000 B")]
+    #[case(1536, "1,5 KiB")]
+    #[case(1024 * 1024 - 1, "1'023,9 KiB")]
+    fn grouped_bytes(#[case] n: u64, #[case] s: &str) {
+        assert_eq!(swiss().bytes(n), s);
+    }
+
+    #[test]
+    fn ungrouped_decimal() {
+        assert_eq!(NumberStyle::default().decimal(12_345, "678"), "12345.678");
+    }
+
+    #[rstest]
+    #[case(0, "+0 B/s")]
+    #[case(2048, "+2.0 KiB/s")]
+    #[case(-12, "-12 B/s")]
+    #[case(i64::MIN, "-8.0 EiB/s")]
+    fn byte_rate(#[case] rate: i64, #[case] s: &str) {
+        assert_eq!(format_byte_rate(rate), s);
+    }
+
+    #[rstest]
+    #[case("0", 0)]
+    #[case("512", 512)]
+    #[case("512B", 512)]
+    #[case("2K", 2048)]
+    #[case("2 KiB", 2048)]
+    #[case("1.5G", 1_610_612_736)]
+    #[case("1.5gb", 1_610_612_736)]
+    #[case(".5M", 524_288)]
+    #[case("3.", 3)]
+    #[case("300MiB", 314_572_800)]
+    #[case("0.1", 0)]
+    #[case("15E", 15 << 60)]
+    fn parse_bytes_ok(#[case] s: &str, #[case] n: u64) {
+        assert_eq!(parse_bytes(s), Ok(n));
+    }
+
+    #[rstest]
+    #[case("", ParseBytesError::InvalidNumber)]
+    #[case("G", ParseBytesError::InvalidNumber)]
+    #[case(".", ParseBytesError::InvalidNumber)]
+    #[case("1.2.3", ParseBytesError::InvalidNumber)]
+    #[case("-1", ParseBytesError::InvalidNumber)]
+    #[case("5 apples", ParseBytesError::InvalidUnit("apples".into()))]
+    #[case("5Gi", ParseBytesError::InvalidUnit("Gi".into()))]
+    #[case("16E", ParseBytesError::Overflow)]
+    #[case("99999999999999999999999999T", ParseBytesError::Overflow)]
+    fn parse_bytes_err(#[case] s: &str, #[case] err: ParseBytesError) {
+        assert_eq!(parse_bytes(s), Err(err));
+    }
+}
@@ -0,0 +1,458 @@
+use crate::units::{ParseBytesError, format_byte_rate, format_bytes, parse_bytes};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvError, Sender, TryRecvError, channel};
+use std::thread;
+use std::time::Instant;
+use thiserror::Error;
+
+/// The number of most recent samples that growth rates are computed over
+const RATE_WINDOW: usize = 5;
+
+/// Tracks the size of a file or directory tree over time.  Walking a large
+/// tree can take a while, so the size is measured on a dedicated thread, and
+/// each sample picks up the latest measurement to have finished.
+#[derive(Debug)]
+pub(crate) struct PathWatcher {
+    path: PathBuf,
+    /// The most recent sizes of the path and when they were measured, oldest
+    /// first.  This is empty if the path did not exist when last measured.
+    samples: VecDeque<(Instant, u64)>,
+    /// True once a measurement has finished
+    measured: bool,
+    /// The thread doing the measuring, or `None` if it couldn't be started,
+    /// in which case the path is measured synchronously
+    measurer: Option<Measurer>,
+}
+
+/// A handle on the thread measuring a [`PathWatcher`]'s path
+#[derive(Debug)]
+struct Measurer {
+    /// Sends the time at which a requested measurement is made.  Dropping
+    /// this stops the thread.
+    requests: Sender<Instant>,
+    /// Receives each measurement along with the time at which it was requested
+    results: Receiver<(Instant, Option<u64>)>,
+    /// True if a measurement has been requested and not yet received
+    busy: bool,
+}
+
+impl PathWatcher {
+    /// Start watching `path`, requesting an initial measurement at time `now`
+    pub(crate) fn new(path: PathBuf, now: Instant) -> PathWatcher {
+        let (requests, requests_rx) = channel::<Instant>();
+        let (results_tx, results) = channel();
+        let thread_path = path.clone();
+        let measurer = thread::Builder::new()
+            .name(String::from("elapsed-watch-path"))
+            .spawn(move || {
+                for when in requests_rx {
+                    if results_tx.send((when, path_size(&thread_path))).is_err() {
+                        return;
+                    }
+                }
+            })
+            .ok()
+            .map(|_| Measurer {
+                requests,
+                results,
+                busy: false,
+            });
+        let mut watcher = PathWatcher {
+            path,
+            samples: VecDeque::with_capacity(RATE_WINDOW + 1),
+            measured: false,
+            measurer,
+        };
+        watcher.sample(now);
+        watcher
+    }
+
+    /// Record the latest measurement of the path's size to have finished, if
+    /// any, and request a new one at time `now` unless one is still underway
+    pub(crate) fn sample(&mut self, now: Instant) {
+        match self.measurer.as_mut().map(|m| m.poll(now)) {
+            Some(Ok(Some((when, size)))) => self.record(when, size),
+            Some(Ok(None)) => (),
+            Some(Err(RecvError)) | None => {
+                // The thread has stopped (or never started), so measure
+                // synchronously from now on.
+                self.measurer = None;
+                let size = path_size(&self.path);
+                self.record(now, size);
+            }
+        }
+    }
+
+    /// Record that the path's size as of `when` was `size` (`None` meaning
+    /// the path did not exist)
+    fn record(&mut self, when: Instant, size: Option<u64>) {
+        self.measured = true;
+        match size {
+            Some(size) => {
+                self.samples.push_back((when, size));
+                if self.samples.len() > RATE_WINDOW {
+                    self.samples.pop_front();
+                }
+            }
+            None => self.samples.clear(),
+        }
+    }
+
+    /// Return the rate at which the path has grown over the last few samples,
+    /// in bytes per second, or `None` if there aren't enough samples yet
+    fn rate(&self) -> Option<i64> {
+        let &(t0, size0) = self.samples.front()?;
+        let &(t1, size1) = self.samples.back()?;
+        let millis = t1.saturating_duration_since(t0).as_millis();
+        if millis == 0 {
+            return None;
+        }
+        let delta = i128::from(size1) - i128::from(size0);
+        let rate = delta * 1000 / i128::try_from(millis).ok()?;
+        i64::try_from(rate).ok()
+    }
+}
+
+impl Measurer {
+    /// Return the requested measurement if it's finished, and request a new
+    /// one at time `now` if none is underway.  Returns `Err` if the thread
+    /// has stopped.
+    fn poll(&mut self, now: Instant) -> Result<Option<(Instant, Option<u64>)>, RecvError> {
+        let mut finished = None;
+        if self.busy {
+            match self.results.try_recv() {
+                Ok(r) => {
+                    self.busy = false;
+                    finished = Some(r);
+                }
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+            }
+        }
+        if !self.busy {
+            self.requests.send(now).map_err(|_| RecvError)?;
+            self.busy = true;
+        }
+        Ok(finished)
+    }
+}
+
+impl fmt::Display for PathWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path.display())?;
+        match self.samples.back() {
+            Some(&(_, size)) => {
+                write!(f, "{}", format_bytes(size))?;
+                if let Some(rate) = self.rate() {
+                    write!(f, ", {}", format_byte_rate(rate))?;
+                }
+                Ok(())
+            }
+            None if self.measured => write!(f, "missing"),
+            None => write!(f, "measuring..."),
+        }
+    }
+}
+
+/// Return the total size of the file at `path` or, if it's a directory, of
+/// all files beneath it.  Symbolic links are not followed.  Returns `None` if
+/// `path` does not exist or cannot be examined; errors for entries within a
+/// directory (e.g., files deleted while the directory is being walked) are
+/// ignored.
+fn path_size(path: &Path) -> Option<u64> {
+    let md = fs::symlink_metadata(path).ok()?;
+    if !md.is_dir() {
+        return Some(md.len());
+    }
+    let mut total = 0u64;
+    let mut dirs = vec![path.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(md) = entry.metadata() else {
+                continue;
+            };
+            if md.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                total = total.saturating_add(md.len());
+            }
+        }
+    }
+    Some(total)
+}
+
+/// Tracks the free space on the filesystem containing a given path
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct DiskWatcher {
+    path: PathBuf,
+    /// The most recently measured space on the filesystem, or `None` if it
+    /// could not be determined
+    space: Option<DiskSpace>,
+    /// True if the free space was below the threshold as of the last sample
+    low: bool,
+}
+
+impl DiskWatcher {
+    /// Start watching the filesystem containing `path`, taking an initial
+    /// sample
+    pub(crate) fn new(path: PathBuf) -> DiskWatcher {
+        DiskWatcher {
+            space: disk_space(&path),
+            path,
+            low: false,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Measure the current free space on the filesystem.  If the free space
+    /// has dropped below `min_free` since the last check (or this is the
+    /// first check and it's already below), return the number of free bytes.
+    pub(crate) fn check(&mut self, min_free: MinFree) -> Option<u64> {
+        self.space = disk_space(&self.path);
+        let space = self.space?;
+        let was_low = self.low;
+        self.low = space.free < min_free.bytes_of(space.total);
+        (self.low && !was_low).then_some(space.free)
+    }
+}
+
+impl fmt::Display for DiskWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.path.display())?;
+        match self.space {
+            Some(space) => write!(f, "{} free", format_bytes(space.free)),
+            None => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The amount of space on a filesystem
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct DiskSpace {
+    /// The number of bytes available to unprivileged users
+    free: u64,
+    /// The total size of the filesystem in bytes
+    total: u64,
+}
+
+#[cfg(unix)]
+fn disk_space(path: &Path) -> Option<DiskSpace> {
+    let st = nix::sys::statvfs::statvfs(path).ok()?;
+    // The field types vary between platforms.
+    #[allow(clippy::useless_conversion)]
+    let (frsize, bavail, blocks) = (
+        u64::from(st.fragment_size()),
+        u64::from(st.blocks_available()),
+        u64::from(st.blocks()),
+    );
+    Some(DiskSpace {
+        free: bavail.saturating_mul(frsize),
+        total: blocks.saturating_mul(frsize),
+    })
+}
+
+#[cfg(not(unix))]
+fn disk_space(_path: &Path) -> Option<DiskSpace> {
+    None
+}
+
+/// A threshold for how much free space a filesystem should have, given either
+/// as an absolute number of bytes or as a percentage of the filesystem's size
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum MinFree {
+    Bytes(u64),
+    Percent(u8),
+}
+
+impl MinFree {
+    /// Return the threshold as a number of bytes for a filesystem with a
+    /// total size of `total` bytes
+    fn bytes_of(self, total: u64) -> u64 {
+        match self {
+            MinFree::Bytes(n) => n,
+            MinFree::Percent(pct) => {
+                let n = u128::from(total) * u128::from(pct) / 100;
+                u64::try_from(n).unwrap_or(u64::MAX)
+            }
+        }
+    }
+}
+
+impl Default for MinFree {
+    fn default() -> MinFree {
+        MinFree::Percent(5)
+    }
+}
+
+impl fmt::Display for MinFree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinFree::Bytes(n) => write!(f, "{}", format_bytes(*n)),
+            MinFree::Percent(pct) => write!(f, "{pct}%"),
+        }
+    }
+}
+
+impl std::str::FromStr for MinFree {
+    type Err = ParseMinFreeError;
+
+    fn from_str(s: &str) -> Result<MinFree, ParseMinFreeError> {
+        if let Some(pct) = s.strip_suffix('%') {
+            match pct.trim().parse::<u8>() {
+                Ok(pct) if pct <= 100 => Ok(MinFree::Percent(pct)),
+                _ => Err(ParseMinFreeError::Percent),
+            }
+        } else {
+            Ok(MinFree::Bytes(parse_bytes(s)?))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub(crate) enum ParseMinFreeError {
+    #[error("invalid percentage; expected an integer from 0 to 100")]
+    Percent,
+    #[error(transparent)]
+    Bytes(#[from] ParseBytesError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Wait for the measurement underway (if any) to finish and record it
+    fn settle(watcher: &mut PathWatcher) {
+        let finished = watcher.measurer.as_mut().filter(|m| m.busy).map(|m| {
+            m.busy = false;
+            m.results.recv().unwrap()
+        });
+        if let Some((when, size)) = finished {
+            watcher.record(when, size);
+        }
+    }
+
+    #[test]
+    fn watch_file() {
+        let scratch = tempfile::tempdir().unwrap();
+        let path = scratch.path().join("out.bin");
+        let start = Instant::now();
+        let mut watcher = PathWatcher::new(path.clone(), start);
+        assert_eq!(
+            watcher.to_string(),
+            format!("{}: measuring...", path.display())
+        );
+        settle(&mut watcher);
+        assert_eq!(watcher.to_string(), format!("{}: missing", path.display()));
+        fs::write(&path, vec![0u8; 1000]).unwrap();
+        watcher.sample(start + Duration::from_secs(1));
+        settle(&mut watcher);
+        assert_eq!(watcher.to_string(), format!("{}: 1000 B", path.display()));
+        fs::write(&path, vec![0u8; 5096]).unwrap();
+        watcher.sample(start + Duration::from_secs(3));
+        settle(&mut watcher);
+        assert_eq!(
+            watcher.to_string(),
+            format!("{}: 4.9 KiB, +2.0 KiB/s", path.display())
+        );
+    }
+
+    #[test]
+    fn watch_dir() {
+        let scratch = tempfile::tempdir().unwrap();
+        let path = scratch.path();
+        fs::write(path.join("foo.txt"), "Hello, world!\n").unwrap();
+        fs::create_dir_all(path.join("sub").join("dir")).unwrap();
+        fs::write(path.join("sub").join("bar.txt"), vec![0u8; 2000]).unwrap();
+        fs::write(path.join("sub").join("dir").join("baz.txt"), vec![0u8; 10]).unwrap();
+        assert_eq!(path_size(path), Some(2024));
+    }
+
+    #[test]
+    fn rate_window() {
+        let scratch = tempfile::tempdir().unwrap();
+        let path = scratch.path().join("out.bin");
+        let start = Instant::now();
+        fs::write(&path, vec![0u8; 100]).unwrap();
+        let mut watcher = PathWatcher::new(path.clone(), start);
+        settle(&mut watcher);
+        for i in 1..=RATE_WINDOW {
+            let size = if i < 3 { 100 } else { 100 + 400 * (i - 2) };
+            fs::write(&path, vec![0u8; size]).unwrap();
+            watcher.sample(start + Duration::from_secs(u64::try_from(i).unwrap()));
+            settle(&mut watcher);
+        }
+        // The sample at time 0 has been dropped, so the rate is computed from
+        // 100 bytes at t=1 to 1300 bytes at t=5.
+        assert_eq!(watcher.rate(), Some(300));
+    }
+
+    #[test]
+    fn sample_picks_up_finished_measurement() {
+        let scratch = tempfile::tempdir().unwrap();
+        let path = scratch.path().join("out.bin");
+        fs::write(&path, vec![0u8; 100]).unwrap();
+        let start = Instant::now();
+        let mut watcher = PathWatcher::new(path, start);
+        // Sampling doesn't wait for the initial measurement; once it's
+        // finished, a sample records it and requests another.
+        while watcher.samples.is_empty() {
+            watcher.sample(start + Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(watcher.samples, [(start, 100)]);
+        settle(&mut watcher);
+        assert_eq!(
+            watcher.samples,
+            [(start, 100), (start + Duration::from_secs(1), 100)]
+        );
+    }
+
+    #[test]
+    fn parse_min_free() {
+        assert_eq!("10%".parse::<MinFree>(), Ok(MinFree::Percent(10)));
+        assert_eq!("0%".parse::<MinFree>(), Ok(MinFree::Percent(0)));
+        assert_eq!("2G".parse::<MinFree>(), Ok(MinFree::Bytes(2 << 30)));
+        assert_eq!("101%".parse::<MinFree>(), Err(ParseMinFreeError::Percent));
+        assert_eq!(
+            "2 parsecs".parse::<MinFree>(),
+            Err(ParseMinFreeError::Bytes(ParseBytesError::InvalidUnit(
+                "parsecs".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn min_free_bytes_of() {
+        assert_eq!(MinFree::Bytes(1000).bytes_of(500), 1000);
+        assert_eq!(MinFree::Percent(5).bytes_of(2000), 100);
+        assert_eq!(MinFree::Percent(100).bytes_of(u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn check_disk() {
+        let scratch = tempfile::tempdir().unwrap();
+        let mut watcher = DiskWatcher::new(scratch.path().to_path_buf());
+        if cfg!(unix) {
+            // The filesystem can't have less than zero bytes free ...
+            assert_eq!(watcher.check(MinFree::Bytes(0)), None);
+            // ... and presumably has less than 15 EiB free.
+            assert!(watcher.check(MinFree::Bytes(15 << 60)).is_some());
+            // Only newly dropping below the threshold is reported.
+            assert_eq!(watcher.check(MinFree::Bytes(15 << 60)), None);
+            assert!(watcher.to_string().ends_with(" free"));
+        } else {
+            assert_eq!(watcher.check(MinFree::Bytes(15 << 60)), None);
+            assert!(watcher.to_string().ends_with(": unknown"));
+        }
+    }
+}
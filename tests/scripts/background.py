@@ -0,0 +1,7 @@
+#!/usr/bin/env python3
+import subprocess
+
+# The background process inherits our stdout & stderr and keeps them open long
+# after we exit.
+subprocess.Popen(["sleep", "5"])
+print("Leaving something running in the background", flush=True)
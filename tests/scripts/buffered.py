@@ -0,0 +1,7 @@
+#!/usr/bin/env python3
+import time
+
+print("Starting...")
+time.sleep(1)
+print("Finishing...")
+time.sleep(1)
@@ -0,0 +1,14 @@
+#!/usr/bin/env python3
+# Spend about 1.5 seconds of CPU time in a subprocess, then exit
+import subprocess
+import sys
+
+subprocess.run(
+    [
+        sys.executable,
+        "-c",
+        "import time\nwhile time.process_time() < 1.5:\n    pass\n",
+    ],
+    check=True,
+)
+print("Done", flush=True)
@@ -0,0 +1,6 @@
+#!/usr/bin/env python3
+import time
+
+files = [open("/dev/null") for _ in range(20)]
+time.sleep(1.5)
+print("Done", flush=True)
@@ -0,0 +1,9 @@
+#!/usr/bin/env python3
+import sys
+import time
+
+sys.stdout.write("Name: ")
+sys.stdout.flush()
+name = sys.stdin.readline().strip()
+print(f"Hello, {name}!", flush=True)
+time.sleep(1)
@@ -0,0 +1,9 @@
+#!/usr/bin/env python3
+import sys
+import time
+
+print("Checking things...", flush=True)
+time.sleep(0.1)
+print("ERROR: Something went wrong", file=sys.stderr, flush=True)
+time.sleep(0.1)
+print("All done", flush=True)